@@ -0,0 +1,73 @@
+// The player commands act on, remembered between calls. Working it out means
+// listing every player and asking each for its status, which is far too much
+// to repeat on every position read. The player watcher forgets it whenever a
+// player comes, goes or changes status, and the next call works it out again.
+
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Cached {
+    // Bumped on every change, so an answer worked out before the change
+    // isn't stored after it
+    generation: u64,
+    player: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ActivePlayer {
+    cached: Mutex<Cached>,
+}
+
+impl ActivePlayer {
+    // The remembered player, and the generation to hand `store` when there
+    // isn't one.
+    pub fn get(&self) -> (Option<String>, u64) {
+        match self.cached.lock() {
+            Ok(cached) => (cached.player.clone(), cached.generation),
+            Err(_) => (None, 0),
+        }
+    }
+
+    // Remembers `player`, unless something changed since `generation`.
+    pub fn store(&self, generation: u64, player: &str) {
+        if let Ok(mut cached) = self.cached.lock() {
+            if cached.generation == generation {
+                cached.player = Some(player.to_string());
+            }
+        }
+    }
+
+    pub fn invalidate(&self) {
+        if let Ok(mut cached) = self.cached.lock() {
+            cached.generation += 1;
+            cached.player = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_until_invalidated() {
+        let active = ActivePlayer::default();
+        let (player, generation) = active.get();
+        assert_eq!(player, None);
+
+        active.store(generation, "spotify");
+        assert_eq!(active.get().0.as_deref(), Some("spotify"));
+
+        active.invalidate();
+        assert_eq!(active.get().0, None);
+    }
+
+    #[test]
+    fn drops_answers_from_before_a_change() {
+        let active = ActivePlayer::default();
+        let (_, generation) = active.get();
+        active.invalidate();
+        active.store(generation, "spotify");
+        assert_eq!(active.get().0, None);
+    }
+}
@@ -0,0 +1,100 @@
+// Album art and accent colors kept on disk between runs, under the same keys
+// the webview looks them up by. Art is stored as the data URL it's shown as,
+// one file per key; accent colors are small enough to share a single JSON
+// file.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+use crate::json_file;
+use crate::lyrics::cache::hash_key;
+
+const ACCENT_COLORS_FILE: &str = "accent-colors.json";
+
+pub struct ArtCache {
+    dir: PathBuf,
+    accent_colors: Mutex<HashMap<String, String>>,
+}
+
+impl ArtCache {
+    pub fn new(dir: PathBuf) -> Self {
+        ArtCache {
+            accent_colors: Mutex::new(json_file::load(&dir.join(ACCENT_COLORS_FILE))),
+            dir,
+        }
+    }
+
+    pub async fn art(&self, key: &str) -> Option<String> {
+        tokio::fs::read_to_string(self.art_path(key)).await.ok()
+    }
+
+    // Stores `data_url` under `key`, keeping any art already there unless
+    // `replace` is set. Returns whether it was stored.
+    pub async fn put_art(&self, key: &str, data_url: &str, replace: bool) -> Result<bool, String> {
+        if !data_url.starts_with("data:image/") {
+            return Err("Album art must be an image data URL".to_string());
+        }
+        let path = self.art_path(key);
+        if !replace && tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(false);
+        }
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Written aside and renamed so a crash never leaves half a file
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data_url)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    pub async fn accent_color(&self, key: &str) -> Option<String> {
+        self.accent_colors
+            .lock()
+            .await
+            .get(&normalize(key))
+            .cloned()
+    }
+
+    // Stores each color whose key isn't there yet, or every color when
+    // `replace` is set. Returns how many were stored.
+    pub async fn put_accent_colors(
+        &self,
+        colors: impl IntoIterator<Item = (String, String)>,
+        replace: bool,
+    ) -> Result<usize, String> {
+        let mut accent_colors = self.accent_colors.lock().await;
+        let mut stored = 0;
+        for (key, color) in colors {
+            let key = normalize(&key);
+            if !replace && accent_colors.contains_key(&key) {
+                continue;
+            }
+            if accent_colors.get(&key) != Some(&color) {
+                accent_colors.insert(key, color);
+                stored += 1;
+            }
+        }
+        if stored == 0 {
+            return Ok(0);
+        }
+
+        json_file::save(&self.dir.join(ACCENT_COLORS_FILE), &*accent_colors).await?;
+        Ok(stored)
+    }
+
+    fn art_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.art", hash_key(&normalize(key))))
+    }
+}
+
+// Keys are compared case and whitespace insensitively, like lyrics lookups.
+fn normalize(key: &str) -> String {
+    key.trim().to_lowercase()
+}
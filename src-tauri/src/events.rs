@@ -0,0 +1,223 @@
+// Pushes player changes to the webview as Tauri events, so the frontend can
+// subscribe once instead of polling every command on a timer.
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{AppState, Metadata};
+
+pub const TRACK_CHANGED: &str = "track-changed";
+pub const PLAYBACK_STATUS_CHANGED: &str = "playback-status-changed";
+pub const PLAYBACK_RATE_CHANGED: &str = "playback-rate-changed";
+pub const SEEKED: &str = "seeked";
+pub const PLAYER_ADDED: &str = "player-added";
+pub const PLAYER_REMOVED: &str = "player-removed";
+
+pub enum PlayerEvent {
+    Added(String),
+    Removed(String),
+    TrackChanged(String, Metadata),
+    PlaybackStatusChanged(String, String),
+    RateChanged(String, f64),
+    // Position in seconds after the seek
+    Seeked(String, f64),
+}
+
+#[derive(Clone, Serialize)]
+struct PlayerPayload {
+    player: String,
+}
+
+#[derive(Clone, Serialize)]
+struct TrackChangedPayload {
+    player: String,
+    metadata: Metadata,
+}
+
+#[derive(Clone, Serialize)]
+struct PlaybackStatusPayload {
+    player: String,
+    status: String,
+}
+
+#[derive(Clone, Serialize)]
+struct PlaybackRatePayload {
+    player: String,
+    rate: f64,
+}
+
+#[derive(Clone, Serialize)]
+struct SeekedPayload {
+    player: String,
+    position: f64,
+}
+
+pub fn emit(app_handle: &AppHandle, event: PlayerEvent) {
+    // Keep the position clocks in step before anyone hears about the change
+    let state = app_handle.state::<AppState>();
+    update_position_clock(&state, &event);
+    // Which player counts as active may have changed
+    if let PlayerEvent::Added(_)
+    | PlayerEvent::Removed(_)
+    | PlayerEvent::PlaybackStatusChanged(..) = event
+    {
+        state.active_player.invalidate();
+    }
+    state.lyric_sync.handle_event(&event);
+
+    let result = match event {
+        PlayerEvent::Added(player) => app_handle.emit(PLAYER_ADDED, PlayerPayload { player }),
+        PlayerEvent::Removed(player) => app_handle.emit(PLAYER_REMOVED, PlayerPayload { player }),
+        PlayerEvent::TrackChanged(player, metadata) => {
+            app_handle.emit(TRACK_CHANGED, TrackChangedPayload { player, metadata })
+        }
+        PlayerEvent::PlaybackStatusChanged(player, status) => app_handle.emit(
+            PLAYBACK_STATUS_CHANGED,
+            PlaybackStatusPayload { player, status },
+        ),
+        PlayerEvent::RateChanged(player, rate) => {
+            app_handle.emit(PLAYBACK_RATE_CHANGED, PlaybackRatePayload { player, rate })
+        }
+        PlayerEvent::Seeked(player, position) => {
+            app_handle.emit(SEEKED, SeekedPayload { player, position })
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to emit player event: {}", e);
+    }
+}
+
+fn update_position_clock(state: &AppState, event: &PlayerEvent) {
+    let Ok(mut clocks) = state.positions.lock() else {
+        return;
+    };
+
+    match event {
+        // A new track (or a gone player) starts from scratch on the next read
+        PlayerEvent::Added(player)
+        | PlayerEvent::Removed(player)
+        | PlayerEvent::TrackChanged(player, _) => {
+            clocks.remove(player);
+        }
+        PlayerEvent::PlaybackStatusChanged(player, status) => {
+            if let Some(clock) = clocks.get_mut(player) {
+                clock.set_playing(status == "Playing");
+            }
+        }
+        PlayerEvent::RateChanged(player, rate) => {
+            if let Some(clock) = clocks.get_mut(player) {
+                clock.set_rate(*rate);
+            }
+        }
+        PlayerEvent::Seeked(player, position) => {
+            if let Some(clock) = clocks.get_mut(player) {
+                clock.seek(*position);
+            }
+        }
+    }
+}
+
+pub fn spawn_watcher(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        #[cfg(target_os = "linux")]
+        {
+            // MPRIS signals drive everything on Linux
+            let state = app_handle.state::<AppState>();
+            if let Err(e) = state.mpris.watch(|event| emit(&app_handle, event)).await {
+                eprintln!("Stopped watching MPRIS players: {}", e);
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            poll(app_handle).await;
+        }
+    });
+}
+
+// Windows and macOS have no bus to listen on, so one backend loop diffs the
+// player state instead and emits the same events.
+#[cfg(not(target_os = "linux"))]
+async fn poll(app_handle: AppHandle) {
+    use std::time::{Duration, Instant};
+
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+    // Working out the active player asks every player for its status (a round
+    // of `osascript` calls on macOS), so it's only redone this often; in
+    // between, the remembered one is used
+    const RESOLVE_INTERVAL: Duration = Duration::from_secs(5);
+
+    let mut players: Vec<String> = Vec::new();
+    let mut track: Option<Metadata> = None;
+    let mut playing: Option<bool> = None;
+    let mut resolved_at: Option<Instant> = None;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let state = app_handle.state::<AppState>();
+
+        if let Ok(current) = crate::get_available_players(app_handle.clone(), state.clone()).await {
+            for player in current.iter().filter(|p| !players.contains(p)) {
+                emit(&app_handle, PlayerEvent::Added(player.clone()));
+            }
+            for player in players.iter().filter(|p| !current.contains(p)) {
+                emit(&app_handle, PlayerEvent::Removed(player.clone()));
+            }
+            players = current;
+        }
+
+        // Worked out afresh now and then, since nothing else would notice
+        // another player starting to play. Players coming and going, and the
+        // active one changing status, already make it be worked out again.
+        let player = match resolved_at {
+            Some(at) if at.elapsed() < RESOLVE_INTERVAL => {
+                crate::get_active_player(app_handle.clone(), state.clone()).await
+            }
+            _ => {
+                resolved_at = Some(Instant::now());
+                crate::resolve_active_player(app_handle.clone(), state.clone())
+                    .await
+                    .inspect(|player| {
+                        if state.active_player.get().0.as_ref() != Some(player) {
+                            state.active_player.invalidate();
+                        }
+                    })
+            }
+        };
+        let Ok(player) = player else {
+            continue;
+        };
+
+        if let Ok(metadata) =
+            crate::get_current_playing_song(app_handle.clone(), state.clone()).await
+        {
+            if track.as_ref() != Some(&metadata) {
+                track = Some(metadata.clone());
+                emit(
+                    &app_handle,
+                    PlayerEvent::TrackChanged(player.clone(), metadata),
+                );
+            }
+        }
+
+        if let Ok(is_playing) = crate::is_playing(app_handle.clone(), state.clone()).await {
+            if playing != Some(is_playing) {
+                playing = Some(is_playing);
+                let status = if is_playing { "Playing" } else { "Paused" };
+                emit(
+                    &app_handle,
+                    PlayerEvent::PlaybackStatusChanged(player.clone(), status.to_string()),
+                );
+            }
+        }
+
+        // The clock notices when the reported position jumps away from where
+        // it expected it to be, which is how seeks show up here
+        if let Ok(sample) = crate::sample_position(&state, &player).await {
+            if let Ok((position, true)) = crate::sync_position(&state, &player, sample) {
+                emit(&app_handle, PlayerEvent::Seeked(player.clone(), position));
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+// The HTTP client everything shares, so connections to the same host are
+// reused. Requests the webview asks for (album art searches, cover images)
+// go through `fetch`, which only reaches the lyric and art hosts below, with a
+// time limit and a cap on the body. No request, redirects included, reaches
+// this machine or the local network unless the settings allow the host, so a
+// compromised page or a bad redirect can't use the app as a proxy. The
+// settings can also route everything through a proxy, or stop all requests
+// in offline mode.
+
+use base64::Engine;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect;
+use url::{Host, Url};
+
+use crate::settings::Settings;
+
+pub const USER_AGENT: &str = concat!(
+    "ohun/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/n3-rd/ohun)"
+);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 5;
+// Bytes; generous for cover art, and far more than any search response
+const MAX_BODY: usize = 10 * 1024 * 1024;
+
+pub const OFFLINE: &str = "Offline mode is on, so only saved lyrics, art and colors are available";
+
+// Hosts `fetch` may reach, subdomains included: the lyric providers, Deezer
+// for art searches, and the CDNs players and Deezer serve cover art from.
+const ALLOWED_HOSTS: &[&str] = &[
+    "lrclib.net",
+    "music.163.com",
+    "y.qq.com",
+    "musixmatch.com",
+    "deezer.com",
+    "dzcdn.net",
+    "scdn.co",
+    "spotifycdn.com",
+    "mzstatic.com",
+    "ytimg.com",
+    "ggpht.com",
+    "googleusercontent.com",
+    "sndcdn.com",
+    "bcbits.com",
+    "tidal.com",
+    "coverartarchive.org",
+    "archive.org",
+    "lastfm.freetls.fastly.net",
+];
+
+// Hosts the settings allow on this machine or the local network. Shared
+// with the resolver and the redirect policy, which outlive any one request.
+type PrivateHosts = Arc<RwLock<HashSet<String>>>;
+
+pub struct Http {
+    // Rebuilt when the proxy changes. Never a plain client, which would skip
+    // the host checks.
+    client: RwLock<Option<reqwest::Client>>,
+    private_hosts: PrivateHosts,
+    offline: Arc<AtomicBool>,
+}
+
+impl Http {
+    // Starts without the proxy if the settings' one is invalid.
+    pub fn new(settings: &Settings) -> Self {
+        let http = Http {
+            client: RwLock::default(),
+            private_hosts: PrivateHosts::default(),
+            offline: Arc::default(),
+        };
+        if let Err(e) = http.configure(settings) {
+            eprintln!("Ignoring the proxy setting: {}", e);
+            let mut settings = settings.clone();
+            settings.proxy = None;
+            if let Err(e) = http.configure(&settings) {
+                eprintln!("Failed to set up the HTTP client: {}", e);
+            }
+        }
+        http
+    }
+
+    // For the lyric providers, whose hosts are fixed or come from the
+    // settings. Providers built before the proxy changes keep the old
+    // client until they're rebuilt.
+    pub fn client(&self) -> Result<reqwest::Client, String> {
+        self.client
+            .read()
+            .map_err(|_| "Failed to lock the HTTP client")?
+            .clone()
+            .ok_or_else(|| "The HTTP client couldn't be set up".to_string())
+    }
+
+    // Picks up the proxy, offline mode and the hosts the settings allow. A
+    // custom lrclib server and the proxy are allowed wherever they are,
+    // since the user pointed the app at them. Fails, changing nothing, when
+    // the proxy isn't a valid URL.
+    pub fn configure(&self, settings: &Settings) -> Result<(), String> {
+        let proxy = settings
+            .proxy
+            .as_deref()
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                let url = Url::parse(proxy).map_err(|e| format!("Invalid proxy: {}", e))?;
+                if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                    return Err(format!("Unsupported proxy type: {}", url.scheme()));
+                }
+                let host = url.host_str().map(normalize_host);
+                let proxy = reqwest::Proxy::all(url).map_err(|e| e.to_string())?;
+                Ok((proxy, host))
+            })
+            .transpose()?;
+        let (proxy, proxy_host) = proxy.unzip();
+
+        let host_of = |url: &str| Url::parse(url.trim()).ok()?.host_str().map(normalize_host);
+        let hosts = settings
+            .allowed_private_hosts
+            .iter()
+            .map(|host| normalize_host(host))
+            .filter(|host| !host.is_empty())
+            .chain(settings.lrclib_url.as_deref().and_then(host_of))
+            .chain(proxy_host.flatten())
+            .collect();
+
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .dns_resolver(Arc::new(PublicResolver {
+                private_hosts: self.private_hosts.clone(),
+                offline: self.offline.clone(),
+            }))
+            .redirect(redirect_policy(self.private_hosts.clone()));
+        // Otherwise the system's proxy variables apply, as they always have
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+        let client = builder.build().map_err(|e| e.to_string())?;
+
+        if let Ok(mut private_hosts) = self.private_hosts.write() {
+            *private_hosts = hosts;
+        }
+        self.offline.store(settings.offline, Ordering::Relaxed);
+        *self
+            .client
+            .write()
+            .map_err(|_| "Failed to lock the HTTP client")? = Some(client);
+        Ok(())
+    }
+
+    pub fn ensure_online(&self) -> Result<(), String> {
+        if self.offline.load(Ordering::Relaxed) {
+            return Err(OFFLINE.to_string());
+        }
+        Ok(())
+    }
+
+    pub async fn fetch_text(&self, url: &str) -> Result<String, String> {
+        let (_, body) = self.fetch(url).await?;
+        String::from_utf8(body).map_err(|_| "The response wasn't text".to_string())
+    }
+
+    // The image at `url` as a data URL.
+    pub async fn fetch_image(&self, url: &str) -> Result<String, String> {
+        let (content_type, body) = self.fetch(url).await?;
+        let content_type = content_type.unwrap_or_else(|| "image/jpeg".to_string());
+        if !content_type.starts_with("image/") {
+            return Err(format!("Expected an image, got {}", content_type));
+        }
+        let b64 = base64::engine::general_purpose::STANDARD.encode(&body);
+        Ok(format!("data:{};base64,{}", content_type, b64))
+    }
+
+    // GETs `url` if it's on an allowed host, returning the content type and
+    // the body.
+    async fn fetch(&self, url: &str) -> Result<(Option<String>, Vec<u8>), String> {
+        self.ensure_online()?;
+        let url = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+        check_url(&url, &self.private_hosts)?;
+
+        let mut response = self
+            .client()?
+            .get(url)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        let too_large = || format!("The response is over {} MB", MAX_BODY / 1024 / 1024);
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_BODY as u64)
+        {
+            return Err(too_large());
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        // Read a chunk at a time, since the length header may be missing or
+        // wrong
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            if body.len() + chunk.len() > MAX_BODY {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok((content_type, body))
+    }
+}
+
+// Whether the webview, or a redirect, may send a request to `url`.
+fn check_url(url: &Url, private_hosts: &PrivateHosts) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("{} URLs can't be fetched", url.scheme()));
+    }
+    let host = url.host().ok_or("The URL has no host")?;
+    let name = normalize_host(&host.to_string());
+    if is_private_host(private_hosts, &name) {
+        return Ok(());
+    }
+    match host {
+        Host::Domain(domain) => {
+            let domain = normalize_host(domain);
+            let allowed = ALLOWED_HOSTS
+                .iter()
+                .any(|allowed| domain == *allowed || domain.ends_with(&format!(".{}", allowed)));
+            if !allowed {
+                return Err(format!("{} isn't a lyrics or album art host", domain));
+            }
+        }
+        // Addresses skip both the resolver and the host list, so only the
+        // ones the settings allow get through
+        Host::Ipv4(_) | Host::Ipv6(_) => {
+            return Err(format!("{} isn't a lyrics or album art host", name));
+        }
+    }
+    Ok(())
+}
+
+fn redirect_policy(private_hosts: PrivateHosts) -> redirect::Policy {
+    redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("Too many redirects");
+        }
+        match check_url(attempt.url(), &private_hosts) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(format!("Refused to follow a redirect: {}", e)),
+        }
+    })
+}
+
+// Resolves names like the system does, but leaves out private and loopback
+// addresses for hosts the settings don't allow them for. Checking here
+// rather than before each request means DNS can't change its answer in
+// between. Behind an HTTP or `socks5h` proxy, the proxy resolves the names
+// it's asked for, so only the host checks apply. In offline mode nothing
+// resolves, which stops requests `fetch` never sees.
+struct PublicResolver {
+    private_hosts: PrivateHosts,
+    offline: Arc<AtomicBool>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> Resolving {
+        let host = normalize_host(name.as_str());
+        let allow_private = is_private_host(&self.private_hosts, &host);
+        let offline = self.offline.load(Ordering::Relaxed);
+        Box::pin(async move {
+            if offline {
+                return Err(OFFLINE.into());
+            }
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || !is_private(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_private_host(private_hosts: &PrivateHosts, host: &str) -> bool {
+    private_hosts
+        .read()
+        .map(|hosts| hosts.contains(host))
+        .unwrap_or(false)
+}
+
+// Lowercase, without the trailing dot or IPv6 brackets, so it compares the
+// same however it was written.
+fn normalize_host(host: &str) -> String {
+    host.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+// Loopback, the local network and other addresses that don't lead to the
+// internet.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(url: &str, allowed: &[&str]) -> Result<(), String> {
+        let private_hosts: PrivateHosts = Arc::new(RwLock::new(
+            allowed.iter().map(|host| host.to_string()).collect(),
+        ));
+        check_url(&Url::parse(url).unwrap(), &private_hosts)
+    }
+
+    #[test]
+    fn only_listed_hosts_and_their_subdomains_are_fetched() {
+        assert!(check("https://lrclib.net/api/search", &[]).is_ok());
+        assert!(check("https://e-cdns-images.dzcdn.net/a.jpg", &[]).is_ok());
+        assert!(check("https://I.SCDN.CO./image", &[]).is_ok());
+        assert!(check("https://example.com/", &[]).is_err());
+        assert!(check("https://notlrclib.net/", &[]).is_err());
+        assert!(check("https://lrclib.net.example.com/", &[]).is_err());
+        assert!(check("file:///etc/passwd", &[]).is_err());
+        assert!(check("ftp://lrclib.net/", &[]).is_err());
+    }
+
+    #[test]
+    fn addresses_need_the_settings_to_allow_them() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://192.168.1.2/",
+            "http://[::1]/",
+            "http://1.1.1.1/",
+            "http://[2606:4700::1111]/",
+            "http://2130706433/",
+        ] {
+            assert!(check(url, &[]).is_err(), "{}", url);
+        }
+        assert!(check("http://192.168.1.2:8080/art", &["192.168.1.2"]).is_ok());
+        assert!(check("http://[::1]/", &["::1"]).is_ok());
+        assert!(check("http://localhost:3000/", &["localhost"]).is_ok());
+    }
+
+    #[test]
+    fn private_addresses_are_told_from_public_ones() {
+        let private = |ip: &str| is_private(ip.parse().unwrap());
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.0.1",
+            "169.254.1.1",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(private(ip), "{}", ip);
+        }
+        for ip in [
+            "1.1.1.1",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!private(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn a_poisoned_client_lock_is_an_error() {
+        let http = Http::new(&Settings::default());
+        assert!(http.client().is_ok());
+
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = http.client.write().unwrap();
+            panic!("poisoning the lock");
+        }));
+        assert!(http.client().is_err());
+    }
+
+    #[test]
+    fn hosts_compare_however_they_were_written() {
+        assert_eq!(normalize_host(" LrcLib.NET. "), "lrclib.net");
+        assert_eq!(normalize_host("[::1]"), "::1");
+    }
+}
@@ -0,0 +1,61 @@
+// JSON files written back whole after every change: the small stores (the
+// blacklist, lyric offsets, accent colors), each read once at startup, and
+// the lyrics cache's entries.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+
+// Reads `path`; a missing or broken file starts empty.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    std::fs::read(path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+// Writes `value` aside and renames it over `path`, so a crash never leaves
+// half a file. Callers save while still holding the lock around `value`, so
+// concurrent writes land in order.
+pub async fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let contents = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let partial = path.with_extension("json.partial");
+    tokio::fs::write(&partial, contents)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::fs::rename(&partial, path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::fixture;
+    use std::collections::HashMap;
+
+    #[test]
+    fn missing_and_broken_files_load_empty() {
+        let missing: HashMap<String, i64> = load(Path::new("/nonexistent/store.json"));
+        assert!(missing.is_empty());
+
+        let broken = fixture("broken-store.json", b"{\"a\": ");
+        let loaded: HashMap<String, i64> = load(&broken.0);
+        assert!(loaded.is_empty());
+    }
+
+    #[tokio::test]
+    async fn saved_values_load_back() {
+        let file = fixture("store.json", b"");
+        let value = HashMap::from([("a".to_string(), 1), ("b".to_string(), -2)]);
+        save(&file.0, &value).await.unwrap();
+
+        assert_eq!(load::<HashMap<String, i64>>(&file.0), value);
+        assert!(!file.0.with_extension("json.partial").exists());
+    }
+}
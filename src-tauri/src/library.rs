@@ -0,0 +1,473 @@
+// Gets a whole music folder lyric-ready ahead of time. The job walks the
+// folder, reads each audio file's tags, looks the track up like playback
+// would, and then caches the lyrics, writes them next to the file, or embeds
+// them in its tags. Progress goes to the webview as events. Only one job runs
+// at a time, it can be cancelled between files, and network lookups are
+// spaced out so the providers aren't flooded.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::Instant;
+
+use crate::lyrics::{self, LyricsResult, LyricsService, TrackQuery};
+use crate::{tags, AppState};
+
+pub const LIBRARY_SCAN_PROGRESS: &str = "library-scan-progress";
+pub const LIBRARY_SCAN_FINISHED: &str = "library-scan-finished";
+
+// Least time between two lookups that go out to the network
+const LOOKUP_INTERVAL: Duration = Duration::from_secs(1);
+
+const AUDIO_EXTENSIONS: [&str; 10] = [
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "wav", "aiff", "aif",
+];
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryAction {
+    // Keep the lyrics in the lyrics cache
+    Cache,
+    // Write `<basename>.lrc` next to the audio file
+    Sidecar,
+    // Write them into the audio file's tags
+    Embed,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+    Saved,
+    // The file already had lyrics the way it was asked for
+    AlreadyDone,
+    NotFound,
+    // No artist or title to look the track up by
+    NoTags,
+    Failed,
+}
+
+#[derive(Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub saved: usize,
+    pub already_done: usize,
+    pub not_found: usize,
+    pub no_tags: usize,
+    pub failed: usize,
+}
+
+impl Summary {
+    fn count(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Saved => self.saved += 1,
+            Outcome::AlreadyDone => self.already_done += 1,
+            Outcome::NotFound => self.not_found += 1,
+            Outcome::NoTags => self.no_tags += 1,
+            Outcome::Failed => self.failed += 1,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProgressPayload {
+    total: usize,
+    done: usize,
+    // The file just handled; `None` for the first event, once the folder
+    // has been walked
+    path: Option<String>,
+    outcome: Option<Outcome>,
+    error: Option<String>,
+    summary: Summary,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FinishedPayload {
+    total: usize,
+    summary: Summary,
+    cancelled: bool,
+}
+
+#[derive(Default)]
+pub struct LibraryJob {
+    running: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl LibraryJob {
+    // Asks the running job to stop after the file it's on.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub fn start(app_handle: AppHandle, dir: PathBuf, action: LibraryAction) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    if !dir.is_dir() {
+        return Err(format!("{} is not a folder", dir.display()));
+    }
+    if state.library.running.swap(true, Ordering::AcqRel) {
+        return Err("A library scan is already running".to_string());
+    }
+    state.library.cancelled.store(false, Ordering::Relaxed);
+
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        run(&app_handle, &state, &dir, action).await;
+        state.library.running.store(false, Ordering::Release);
+    });
+    Ok(())
+}
+
+async fn run(app_handle: &AppHandle, state: &AppState, dir: &Path, action: LibraryAction) {
+    let files = audio_files(dir).await;
+    let total = files.len();
+    let mut summary = Summary::default();
+    let mut last_lookup: Option<Instant> = None;
+
+    let progress = |done: usize, path: Option<&Path>, outcome, error, summary: &Summary| {
+        let payload = ProgressPayload {
+            total,
+            done,
+            path: path.map(|path| path.to_string_lossy().into_owned()),
+            outcome,
+            error,
+            summary: summary.clone(),
+        };
+        if let Err(e) = app_handle.emit(LIBRARY_SCAN_PROGRESS, payload) {
+            eprintln!("Failed to emit library scan progress: {}", e);
+        }
+    };
+    progress(0, None, None, None, &summary);
+
+    let backups = state.data_dir.join(tags::BACKUP_DIR);
+    for (i, path) in files.iter().enumerate() {
+        if state.library.is_cancelled() {
+            break;
+        }
+        let result = process(&state.lyrics, &backups, path, action, &mut last_lookup).await;
+        let (outcome, error) = match result {
+            Ok(outcome) => (outcome, None),
+            Err(e) => (Outcome::Failed, Some(e)),
+        };
+        summary.count(outcome);
+        progress(i + 1, Some(path), Some(outcome), error, &summary);
+    }
+
+    let payload = FinishedPayload {
+        total,
+        summary,
+        cancelled: state.library.is_cancelled(),
+    };
+    if let Err(e) = app_handle.emit(LIBRARY_SCAN_FINISHED, payload) {
+        eprintln!("Failed to emit library scan result: {}", e);
+    }
+}
+
+// Does `action` for one file, looking its lyrics up with `service`. Tags are
+// backed up into `backups` before they're changed.
+async fn process(
+    service: &LyricsService,
+    backups: &Path,
+    path: &Path,
+    action: LibraryAction,
+    last_lookup: &mut Option<Instant>,
+) -> Result<Outcome, String> {
+    let file = path.to_path_buf();
+    let tags = tokio::task::spawn_blocking(move || tags::read(&file))
+        .await
+        .map_err(|e| e.to_string())??;
+    let present = |value: &Option<String>| value.as_ref().is_some_and(|v| !v.trim().is_empty());
+    if !present(&tags.artist) || !present(&tags.title) {
+        return Ok(Outcome::NoTags);
+    }
+
+    let sidecar = path.with_extension("lrc");
+    let done = match action {
+        // Either kind, `.lrc` or `.txt`, as lookups would find it
+        LibraryAction::Sidecar => lyrics::sidecar(path).await.is_some(),
+        LibraryAction::Embed => tags.synced.is_some(),
+        // Checked below, since anything found offline counts
+        LibraryAction::Cache => false,
+    };
+    if done {
+        return Ok(Outcome::AlreadyDone);
+    }
+
+    let query = TrackQuery {
+        artist: tags.artist.unwrap_or_default(),
+        title: tags.title.unwrap_or_default(),
+        album: tags.album,
+        duration: tags.duration,
+        path: Some(path.to_path_buf()),
+    };
+    let result = match service.lookup_offline(&query).await? {
+        Some(_) if matches!(action, LibraryAction::Cache) => return Ok(Outcome::AlreadyDone),
+        Some(result) => Some(result),
+        // Only what's saved counts in offline mode
+        None if service.is_offline() => None,
+        None => {
+            if let Some(last) = *last_lookup {
+                tokio::time::sleep_until(last + LOOKUP_INTERVAL).await;
+            }
+            *last_lookup = Some(Instant::now());
+            // Cached by the service as it's fetched
+            service.fetch_remote(&query).await?
+        }
+    };
+    let Some(result) = result else {
+        return Ok(Outcome::NotFound);
+    };
+
+    match action {
+        LibraryAction::Cache => Ok(Outcome::Saved),
+        LibraryAction::Sidecar => write_sidecar(&sidecar, &result).await,
+        LibraryAction::Embed => embed(backups, path, result).await,
+    }
+}
+
+// Synced lyrics go in `<basename>.lrc`; plain ones in `<basename>.txt`,
+// which is found just the same.
+async fn write_sidecar(sidecar: &Path, result: &LyricsResult) -> Result<Outcome, String> {
+    let (path, text) = match (&result.synced, &result.plain) {
+        (Some(synced), _) => (sidecar.to_path_buf(), synced),
+        (None, Some(plain)) => (sidecar.with_extension("txt"), plain),
+        (None, None) => return Ok(Outcome::NotFound),
+    };
+    if path.exists() {
+        return Ok(Outcome::AlreadyDone);
+    }
+    tokio::fs::write(&path, text)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Outcome::Saved)
+}
+
+// Only synced lyrics are embedded, parsed here if they came without it.
+async fn embed(backups: &Path, path: &Path, result: LyricsResult) -> Result<Outcome, String> {
+    let parsed = result.parsed.or_else(|| {
+        let synced = result.synced.as_deref()?;
+        Some(lyrics::lrc::parse(synced).lyrics)
+    });
+    let Some(lyrics) = parsed.filter(|lyrics| !lyrics.lines.is_empty()) else {
+        return Ok(Outcome::NotFound);
+    };
+    let path = path.to_path_buf();
+    let backups = backups.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        tags::backup(&path, &backups)?;
+        tags::write_lyrics(&path, &lyrics)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(Outcome::Saved)
+}
+
+// Every audio file under `dir`, sorted. Symlinked folders aren't followed,
+// so a link back up the tree can't loop.
+async fn audio_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut read_dir) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if is_audio(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::blacklist::Blacklist;
+    use crate::lyrics::cache::LyricsCache;
+    use crate::lyrics::{FetchStrategy, LocalFolder, ProviderChain};
+    use crate::test_files::{fixture_dir, flac, Fixture};
+    use std::sync::Arc;
+
+    const SYNCED: &str = "[00:01.00]Hello";
+
+    // A folder holding `song.flac`, tagged with `comments`, and a service
+    // that finds lyrics on disk and in its cache only.
+    fn library(name: &str, comments: &[&str]) -> (Fixture, LyricsService) {
+        let dir = fixture_dir(name, &[]);
+        std::fs::write(dir.join("song.flac"), flac(comments)).unwrap();
+        let chain = ProviderChain {
+            local: Some(Arc::new(LocalFolder::new(Vec::new()))),
+            providers: Vec::new(),
+            strategy: FetchStrategy::Sequential,
+            duration_tolerance: 3.0,
+            offline: true,
+        };
+        let service = LyricsService::new(
+            chain,
+            LyricsCache::new(dir.join("cache"), u64::MAX),
+            Blacklist::new(dir.join("blacklist.json")),
+        );
+        (dir, service)
+    }
+
+    async fn cache(service: &LyricsService) {
+        let query = TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        };
+        let result = lyrics::lyrics_from_text(SYNCED.to_string(), "lrclib").unwrap();
+        service.cache().put(&query, &result).await.unwrap();
+    }
+
+    async fn run(dir: &Fixture, service: &LyricsService, action: LibraryAction) -> Outcome {
+        let path = dir.join("song.flac");
+        let backups = dir.join("backups");
+        process(service, &backups, &path, action, &mut None)
+            .await
+            .unwrap()
+    }
+
+    const TAGS: &[&str] = &["ARTIST=Artist", "TITLE=Song"];
+
+    #[tokio::test]
+    async fn files_without_an_artist_and_title_are_skipped() {
+        let (dir, service) = library("library-no-tags", &["TITLE=Song", "ALBUM=Album"]);
+        for action in [
+            LibraryAction::Cache,
+            LibraryAction::Sidecar,
+            LibraryAction::Embed,
+        ] {
+            assert!(matches!(run(&dir, &service, action).await, Outcome::NoTags));
+        }
+    }
+
+    #[tokio::test]
+    async fn what_the_action_would_make_counts_as_done() {
+        let (dir, service) = library("library-done", TAGS);
+        std::fs::write(dir.join("song.txt"), "Hello").unwrap();
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Sidecar).await,
+            Outcome::AlreadyDone
+        ));
+        // Lyrics found without the network need no caching
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Cache).await,
+            Outcome::AlreadyDone
+        ));
+
+        let lyrics = format!("LYRICS={}", SYNCED);
+        let (dir, service) = library("library-embedded", &[TAGS[0], TAGS[1], &lyrics]);
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Embed).await,
+            Outcome::AlreadyDone
+        ));
+    }
+
+    #[tokio::test]
+    async fn nothing_found_offline_is_not_found() {
+        let (dir, service) = library("library-not-found", TAGS);
+        for action in [
+            LibraryAction::Cache,
+            LibraryAction::Sidecar,
+            LibraryAction::Embed,
+        ] {
+            assert!(matches!(
+                run(&dir, &service, action).await,
+                Outcome::NotFound
+            ));
+        }
+        assert!(!dir.join("song.lrc").exists());
+    }
+
+    #[tokio::test]
+    async fn found_lyrics_are_saved_the_way_asked() {
+        let (dir, service) = library("library-saved", TAGS);
+        cache(&service).await;
+
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Cache).await,
+            Outcome::AlreadyDone
+        ));
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Sidecar).await,
+            Outcome::Saved
+        ));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("song.lrc")).unwrap(),
+            SYNCED
+        );
+
+        std::fs::remove_file(dir.join("song.lrc")).unwrap();
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Embed).await,
+            Outcome::Saved
+        ));
+        let tags = tags::read(&dir.join("song.flac")).unwrap();
+        assert_eq!(tags.synced.unwrap().lines[0].text, "Hello");
+        assert_eq!(std::fs::read_dir(dir.join("backups")).unwrap().count(), 1);
+        assert!(matches!(
+            run(&dir, &service, LibraryAction::Embed).await,
+            Outcome::AlreadyDone
+        ));
+    }
+
+    #[tokio::test]
+    async fn sidecars_are_lrc_for_synced_lyrics_and_txt_for_plain() {
+        let dir = fixture_dir("library-sidecars", &[]);
+        let sidecar = dir.join("song.lrc");
+        let plain = LyricsResult {
+            plain: Some("Hello".to_string()),
+            ..Default::default()
+        };
+        let synced = LyricsResult {
+            synced: Some(SYNCED.to_string()),
+            ..plain.clone()
+        };
+
+        assert!(matches!(
+            write_sidecar(&sidecar, &plain).await,
+            Ok(Outcome::Saved)
+        ));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("song.txt")).unwrap(),
+            "Hello"
+        );
+        assert!(matches!(
+            write_sidecar(&sidecar, &synced).await,
+            Ok(Outcome::Saved)
+        ));
+        assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), SYNCED);
+
+        assert!(matches!(
+            write_sidecar(&sidecar, &synced).await,
+            Ok(Outcome::AlreadyDone)
+        ));
+        assert!(matches!(
+            write_sidecar(&sidecar, &LyricsResult::default()).await,
+            Ok(Outcome::NotFound)
+        ));
+    }
+}
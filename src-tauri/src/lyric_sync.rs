@@ -0,0 +1,374 @@
+// Follows playback through the current track's lyrics and emits
+// `lyric-line-changed` as each line starts and `lyric-word-changed` as each
+// word does. Rather than polling, the loop works out from the position clock
+// when the next line or word begins and sleeps until exactly then, asking
+// the player itself whenever the clock is due a resync. Anything that moves
+// the clock (seeks, pauses, rate changes, new lyrics) wakes it early to
+// work the schedule out again.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::events::PlayerEvent;
+use crate::lyrics::lrc::{Lyrics, Word};
+use crate::AppState;
+
+pub const LYRIC_LINE_CHANGED: &str = "lyric-line-changed";
+pub const LYRIC_WORD_CHANGED: &str = "lyric-word-changed";
+
+// Upper bound on a sleep, so a clock change nobody announced is still
+// picked up reasonably quickly
+const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct LyricLinePayload {
+    player: String,
+    // `None` before the first line and when there are no lyrics
+    index: Option<usize>,
+    // The line's timestamp as written, in seconds
+    time: f64,
+    text: String,
+    next: Option<String>,
+    // Player positions, in seconds, the line shows from and until (the end
+    // when known), with the offsets applied. Progress through
+    // the line is the webview's position between the two.
+    start: f64,
+    end: Option<f64>,
+    // The line's length in seconds, when known
+    duration: Option<f64>,
+    // Spread evenly over the line when the lyrics only have line timings
+    words: Vec<Word>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LyricWordPayload {
+    player: String,
+    line: usize,
+    index: usize,
+    // Seconds, as written
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+// Where playback is in the lyrics
+struct Located {
+    line: LyricLinePayload,
+    word: Option<usize>,
+    // Track seconds until the next line or word starts
+    until_next: Option<f64>,
+}
+
+#[derive(Default)]
+struct Active {
+    player: Option<String>,
+    lyrics: Option<Lyrics>,
+    // The user's offsets for the track, in seconds, on top of the lyrics'
+    // own
+    offset: f64,
+    // What was last emitted, so each line and word is announced once
+    index: Option<Option<usize>>,
+    word: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct LyricSync {
+    active: Mutex<Active>,
+    wake: Notify,
+}
+
+impl LyricSync {
+    // Starts following `lyrics` on `player`'s clock, shifted by `offset`
+    // seconds. `None` clears them.
+    pub fn set_lyrics(&self, player: String, lyrics: Option<Lyrics>, offset: f64) {
+        if let Ok(mut active) = self.active.lock() {
+            *active = Active {
+                player: Some(player),
+                lyrics,
+                offset,
+                index: None,
+                word: None,
+            };
+        }
+        self.wake.notify_one();
+    }
+
+    // Shifts the lyrics being followed, after the user nudged them.
+    pub fn set_offset(&self, offset: f64) {
+        if let Ok(mut active) = self.active.lock() {
+            active.offset = offset;
+        }
+        self.wake.notify_one();
+    }
+
+    // The position may have moved; work out the schedule again.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn handle_event(&self, event: &PlayerEvent) {
+        if let PlayerEvent::TrackChanged(player, _) | PlayerEvent::Removed(player) = event {
+            // The old lyrics don't belong to the new track
+            if let Ok(mut active) = self.active.lock() {
+                if active.player.as_ref() == Some(player) {
+                    active.lyrics = None;
+                }
+            }
+        }
+        self.wake();
+    }
+}
+
+pub fn spawn(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        loop {
+            resync(&state).await;
+            let next = step(&app_handle, &state);
+            let deadline = Instant::now() + next.unwrap_or(MAX_SLEEP);
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = state.lyric_sync.wake.notified() => {}
+            }
+        }
+    });
+}
+
+// Asks the player where it is when its clock is missing or due a resync.
+// The webview's reads keep the clock fresh too, but they stall while the
+// window is hidden or throttled, and the lyrics shouldn't.
+async fn resync(state: &AppState) {
+    let player = match state.lyric_sync.active.lock() {
+        Ok(active) if active.lyrics.is_some() => active.player.clone(),
+        _ => None,
+    };
+    let Some(player) = player else {
+        return;
+    };
+    let stale = state.positions.lock().is_ok_and(|clocks| {
+        clocks.get(&player).map_or(true, |clock| {
+            clock.needs_sync(crate::POSITION_RESYNC_INTERVAL)
+        })
+    });
+    if !stale {
+        return;
+    }
+    // A player that can't be asked keeps the clock it has; the webview's
+    // reads report the trouble
+    if let Ok(sample) = crate::sample_position(state, &player).await {
+        let _ = crate::sync_position(state, &player, sample);
+    }
+}
+
+// Emits the current line if it changed, and returns how long until the next
+// one starts, if it's coming at all.
+fn step(app_handle: &AppHandle, state: &AppState) -> Option<Duration> {
+    let Ok(mut active) = state.lyric_sync.active.lock() else {
+        return None;
+    };
+    let player = active.player.clone()?;
+    let (position, rate, playing) = {
+        let clocks = state.positions.lock().ok()?;
+        let clock = clocks.get(&player)?;
+        (clock.position(), clock.rate(), clock.is_playing())
+    };
+
+    let located = match &active.lyrics {
+        Some(lyrics) => locate(&player, lyrics, active.offset, position),
+        None => Located {
+            line: LyricLinePayload {
+                player: player.clone(),
+                ..Default::default()
+            },
+            word: None,
+            until_next: None,
+        },
+    };
+    let Located {
+        line,
+        word,
+        until_next,
+    } = located;
+
+    if active.index != Some(line.index) {
+        active.index = Some(line.index);
+        active.word = None;
+        if let Err(e) = app_handle.emit(LYRIC_LINE_CHANGED, line.clone()) {
+            eprintln!("Failed to emit lyric line: {}", e);
+        }
+    }
+    if let (Some(index), Some(word)) = (line.index, word) {
+        if active.word != Some(word) {
+            active.word = Some(word);
+            let timing = &line.words[word];
+            let payload = LyricWordPayload {
+                player: player.clone(),
+                line: index,
+                index: word,
+                start: timing.start,
+                end: timing.end,
+                text: timing.text.clone(),
+            };
+            if let Err(e) = app_handle.emit(LYRIC_WORD_CHANGED, payload) {
+                eprintln!("Failed to emit lyric word: {}", e);
+            }
+        }
+    }
+
+    // Position seconds become wall-clock seconds at the playback rate
+    let until_next = until_next?;
+    if !playing || rate <= 0.0 {
+        return None;
+    }
+    sleep_for(until_next / rate)
+}
+
+// `seconds` as a sleep no longer than `MAX_SLEEP`. Timestamps can be absurd
+// (a huge minutes field, an infinite TTML time), and `Duration` panics on
+// anything it can't hold.
+fn sleep_for(seconds: f64) -> Option<Duration> {
+    (seconds.is_finite() && seconds >= 0.0)
+        .then(|| Duration::from_secs_f64(seconds.min(MAX_SLEEP.as_secs_f64())))
+}
+
+// The line and word showing at `position`, with the lyrics moved earlier by
+// their `[offset:]` and the user's `offset`.
+fn locate(player: &str, lyrics: &Lyrics, offset: f64, position: f64) -> Located {
+    // The position a timestamp shows at
+    let start = |time: f64| time - lyrics.offset - offset;
+    let index = lyrics
+        .lines
+        .iter()
+        .take_while(|line| start(line.time) <= position)
+        .count()
+        .checked_sub(1);
+
+    let next = index.map_or(0, |i| i + 1);
+    let next_start = lyrics.lines.get(next).map(|line| start(line.time));
+
+    let Some(i) = index else {
+        return Located {
+            line: LyricLinePayload {
+                player: player.to_string(),
+                next: lyrics.lines.first().map(|line| line.text.clone()),
+                ..Default::default()
+            },
+            word: None,
+            until_next: next_start.map(|next_start| next_start - position),
+        };
+    };
+
+    let line = &lyrics.lines[i];
+    let end = lyrics.line_end(i);
+    let words = line.timed_words(end);
+    let word = words
+        .iter()
+        .take_while(|word| start(word.start) <= position)
+        .count()
+        .checked_sub(1);
+    let next_word = words
+        .get(word.map_or(0, |w| w + 1))
+        .map(|word| start(word.start));
+
+    let duration = end.map(|end| end - line.time).filter(|d| *d > 0.0);
+    let until_next = [next_start, next_word]
+        .into_iter()
+        .flatten()
+        .map(|next| next - position)
+        // An absurd timestamp can't be waited for
+        .filter(|until| until.is_finite())
+        .map(|until| until.max(0.0))
+        .reduce(f64::min);
+
+    Located {
+        line: LyricLinePayload {
+            player: player.to_string(),
+            index: Some(i),
+            time: line.time,
+            text: line.text.clone(),
+            next: lyrics.lines.get(i + 1).map(|line| line.text.clone()),
+            start: start(line.time),
+            end: end.map(start),
+            duration,
+            words,
+        },
+        word,
+        until_next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+
+    fn lyrics() -> Lyrics {
+        lrc::parse("[offset:1000]\n[00:10.00]One\n[00:20.00]<00:20.00>Two <00:22.00>words\n").lyrics
+    }
+
+    #[test]
+    fn lines_show_early_by_the_offsets() {
+        let lyrics = lyrics();
+        // 10s, less the 1s `[offset:]` and the user's 2s
+        let before = locate("p", &lyrics, 2.0, 6.9);
+        assert_eq!(before.line.index, None);
+        assert_eq!(before.line.next.as_deref(), Some("One"));
+        assert!((before.until_next.unwrap() - 0.1).abs() < 1e-9);
+
+        let shown = locate("p", &lyrics, 2.0, 7.0).line;
+        assert_eq!(shown.index, Some(0));
+        assert_eq!(shown.time, 10.0);
+        assert_eq!(shown.start, 7.0);
+        assert_eq!(shown.end, Some(17.0));
+        assert_eq!(shown.duration, Some(10.0));
+    }
+
+    #[test]
+    fn the_range_lets_progress_be_worked_out_later() {
+        let lyrics = lyrics();
+        let line = locate("p", &lyrics, 0.0, 10.0).line;
+        let progress = |position: f64| (position - line.start) / (line.end.unwrap() - line.start);
+        assert_eq!(progress(9.0), 0.0);
+        assert_eq!(progress(14.0), 0.5);
+    }
+
+    #[test]
+    fn words_are_located_and_scheduled() {
+        let lyrics = lyrics();
+        // Word two shows at 22 - 1, right on its timestamp
+        let located = locate("p", &lyrics, 0.0, 20.5);
+        assert_eq!(located.line.index, Some(1));
+        assert_eq!(located.line.end, None);
+        assert_eq!(located.word, Some(0));
+        assert!((located.until_next.unwrap() - 0.5).abs() < 1e-9);
+
+        let located = locate("p", &lyrics, 0.0, 21.0);
+        assert_eq!(located.word, Some(1));
+        assert_eq!(located.until_next, None);
+    }
+
+    #[test]
+    fn sleeps_are_capped_and_absurd_ones_skipped() {
+        assert_eq!(sleep_for(0.25), Some(Duration::from_millis(250)));
+        assert_eq!(sleep_for(1e30), Some(MAX_SLEEP));
+        assert_eq!(sleep_for(f64::INFINITY), None);
+        assert_eq!(sleep_for(f64::NAN), None);
+        assert_eq!(sleep_for(-1.0), None);
+    }
+
+    #[test]
+    fn absurd_timestamps_dont_panic() {
+        let mut lyrics = lyrics();
+        lyrics.lines[1].time = f64::INFINITY;
+        lyrics.lines[1].words.clear();
+        let located = locate("p", &lyrics, 0.0, 10.0);
+        assert_eq!(located.line.index, Some(0));
+        assert_eq!(located.until_next, None);
+    }
+}
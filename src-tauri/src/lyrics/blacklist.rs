@@ -0,0 +1,70 @@
+// Candidates the user has said are wrong for a track, by the track's cache
+// id. Lookups for the track pass over them. Small enough to share a single
+// JSON file, read once at startup.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+use crate::json_file;
+
+pub struct Blacklist {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, BTreeSet<String>>>,
+}
+
+impl Blacklist {
+    pub fn new(path: PathBuf) -> Self {
+        Blacklist {
+            entries: Mutex::new(json_file::load(&path)),
+            path,
+        }
+    }
+
+    // The candidate ids blacklisted for a track.
+    pub async fn get(&self, track_key: &str) -> BTreeSet<String> {
+        self.entries
+            .lock()
+            .await
+            .get(track_key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn add(&self, track_key: &str, candidate_id: &str) -> Result<(), String> {
+        let mut entries = self.entries.lock().await;
+        let added = entries
+            .entry(track_key.to_string())
+            .or_default()
+            .insert(candidate_id.to_string());
+        if !added {
+            return Ok(());
+        }
+
+        json_file::save(&self.path, &*entries).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::fixture_dir;
+
+    #[tokio::test]
+    async fn candidates_stay_blacklisted_for_their_track_only() {
+        let dir = fixture_dir("blacklist", &[]);
+        let blacklist = Blacklist::new(dir.join("blacklist.json"));
+        assert!(blacklist.get("track").await.is_empty());
+
+        blacklist.add("track", "lrclib:1").await.unwrap();
+        blacklist.add("track", "netease:2").await.unwrap();
+        blacklist.add("track", "lrclib:1").await.unwrap();
+        blacklist.add("other", "lrclib:3").await.unwrap();
+
+        let reloaded = Blacklist::new(dir.join("blacklist.json"));
+        let ids: Vec<String> = reloaded.get("track").await.into_iter().collect();
+        assert_eq!(ids, ["lrclib:1", "netease:2"]);
+        assert!(!reloaded.get("other").await.contains("lrclib:1"));
+        assert!(reloaded.get("missing").await.is_empty());
+    }
+}
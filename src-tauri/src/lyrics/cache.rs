@@ -0,0 +1,475 @@
+// Lyrics kept on disk between runs, one JSON file per track under the app
+// data dir. Files are named after a hash of the normalized track key, and
+// once the folder grows past its size limit the least recently read files
+// are evicted first. Lyrics imported from older versions' localStorage cache
+// are only keyed by artist and title run together, so they're kept under a
+// key of their own and moved to the full track key the first time they're
+// read. Lyrics the user picked or imported themselves are pinned: they win
+// over anything found for the track later and are never evicted.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{LyricsResult, TrackQuery};
+use crate::json_file;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub id: String,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub duration: Option<f64>,
+    // Seconds since the Unix epoch
+    pub fetched_at: u64,
+    #[serde(default)]
+    pub pinned: bool,
+    pub lyrics: LyricsResult,
+}
+
+// What `list` returns, without the lyrics themselves.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheSummary {
+    pub id: String,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub provider: String,
+    pub fetched_at: u64,
+    pub synced: bool,
+    pub pinned: bool,
+    // Bytes on disk
+    pub size: u64,
+}
+
+pub struct LyricsCache {
+    dir: PathBuf,
+    max_size: AtomicU64,
+    // Bytes on disk, kept up to date by writes so eviction only has to look
+    // at the folder once it's over the limit. `None` until the folder has
+    // been counted, and again after files were removed.
+    size: Mutex<Option<u64>>,
+}
+
+impl LyricsCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        LyricsCache {
+            dir,
+            max_size: AtomicU64::new(max_size),
+            size: Mutex::new(None),
+        }
+    }
+
+    pub fn set_max_size(&self, max_size: u64) {
+        self.max_size.store(max_size, Ordering::Relaxed);
+    }
+
+    pub async fn get(&self, query: &TrackQuery) -> Option<LyricsResult> {
+        let path = self.path(&entry_id(query));
+        if let Some(entry) = read_entry(&path).await {
+            touch(&path).await;
+            return Some(entry.lyrics);
+        }
+
+        let legacy = self.path(&legacy_id(&legacy_key(query)));
+        let entry = read_entry(&legacy).await?;
+        if self.put(query, &entry.lyrics).await.is_ok()
+            && tokio::fs::remove_file(&legacy).await.is_ok()
+        {
+            self.set_size(None);
+        }
+        Some(entry.lyrics)
+    }
+
+    // The track's lyrics, only if they were pinned.
+    pub async fn pinned(&self, query: &TrackQuery) -> Option<LyricsResult> {
+        let path = self.path(&entry_id(query));
+        let entry = read_entry(&path).await.filter(|entry| entry.pinned)?;
+        touch(&path).await;
+        Some(entry.lyrics)
+    }
+
+    pub async fn put(&self, query: &TrackQuery, lyrics: &LyricsResult) -> Result<(), String> {
+        self.write(new_entry(query, lyrics, false)).await
+    }
+
+    pub async fn pin(&self, query: &TrackQuery, lyrics: &LyricsResult) -> Result<(), String> {
+        self.write(new_entry(query, lyrics, true)).await
+    }
+
+    // Stores lyrics from the old localStorage cache under their `artist` +
+    // `title` key. Returns false, leaving the cache alone, when lyrics for
+    // the track are already there.
+    pub async fn import_legacy(&self, key: &str, lyrics: LyricsResult) -> Result<bool, String> {
+        let id = legacy_id(key);
+        if tokio::fs::try_exists(self.path(&id)).await.unwrap_or(false) {
+            return Ok(false);
+        }
+        // The artist can't be told apart from the title, so the whole key
+        // stands in as the title until the track is played again
+        self.write(CacheEntry {
+            id,
+            artist: String::new(),
+            title: key.trim().to_string(),
+            album: None,
+            duration: None,
+            fetched_at: now(),
+            pinned: false,
+            lyrics,
+        })
+        .await?;
+        Ok(true)
+    }
+
+    async fn write(&self, entry: CacheEntry) -> Result<(), String> {
+        let path = self.path(&entry.id);
+        let replaced = file_size(&path).await;
+        json_file::save(&path, &entry).await?;
+        let written = file_size(&path).await;
+
+        let size = self.size.lock().ok().and_then(|mut size| {
+            size.as_mut().map(|size| {
+                *size = (*size + written).saturating_sub(replaced);
+                *size
+            })
+        });
+        let size = match size {
+            Some(size) => size,
+            None => {
+                let size = self.files().await.iter().map(|(_, size, _)| size).sum();
+                self.set_size(Some(size));
+                size
+            }
+        };
+        if size > self.max_size.load(Ordering::Relaxed) {
+            self.evict().await;
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<CacheSummary>, String> {
+        let mut summaries = Vec::new();
+        for (path, size, _) in self.files().await {
+            let Some(entry) = read_entry(&path).await else {
+                continue;
+            };
+            summaries.push(CacheSummary {
+                id: entry.id,
+                artist: entry.artist,
+                title: entry.title,
+                album: entry.album,
+                provider: entry.lyrics.provider,
+                fetched_at: entry.fetched_at,
+                synced: entry.lyrics.synced.is_some(),
+                pinned: entry.pinned,
+                size,
+            });
+        }
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.fetched_at));
+        Ok(summaries)
+    }
+
+    pub async fn inspect(&self, id: &str) -> Result<CacheEntry, String> {
+        check_id(id)?;
+        read_entry(&self.path(id))
+            .await
+            .ok_or_else(|| "No cached lyrics with that id".to_string())
+    }
+
+    // Removes one entry, or everything when `id` is `None`. Returns how many
+    // entries went.
+    pub async fn purge(&self, id: Option<&str>) -> Result<usize, String> {
+        let paths = match id {
+            Some(id) => {
+                check_id(id)?;
+                vec![self.path(id)]
+            }
+            None => self
+                .files()
+                .await
+                .into_iter()
+                .map(|(path, ..)| path)
+                .collect(),
+        };
+
+        let mut removed = 0;
+        self.set_size(None);
+        for path in paths {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(removed)
+    }
+
+    fn set_size(&self, size: Option<u64>) {
+        if let Ok(mut current) = self.size.lock() {
+            *current = size;
+        }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    // Every entry file with its size and last read time.
+    async fn files(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let mut files = Vec::new();
+        let Ok(mut read_dir) = tokio::fs::read_dir(&self.dir).await else {
+            return files;
+        };
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata().await {
+                let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+                files.push((path, metadata.len(), modified));
+            }
+        }
+        files
+    }
+
+    // Removes the least recently read entries, leaving pinned ones, until the
+    // folder is back under the limit.
+    async fn evict(&self) {
+        let max_size = self.max_size.load(Ordering::Relaxed);
+        let mut files = self.files().await;
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+
+        files.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in files {
+            if total <= max_size {
+                break;
+            }
+            if read_entry(&path).await.is_some_and(|entry| entry.pinned) {
+                continue;
+            }
+            if tokio::fs::remove_file(&path).await.is_ok() {
+                total = total.saturating_sub(size);
+            }
+        }
+        self.set_size(Some(total));
+    }
+}
+
+fn new_entry(query: &TrackQuery, lyrics: &LyricsResult, pinned: bool) -> CacheEntry {
+    CacheEntry {
+        id: entry_id(query),
+        artist: query.artist.trim().to_string(),
+        title: query.title.trim().to_string(),
+        album: query.album.clone(),
+        duration: query.duration,
+        fetched_at: now(),
+        pinned,
+        lyrics: lyrics.clone(),
+    }
+}
+
+pub fn entry_id(query: &TrackQuery) -> String {
+    hash_key(&query.cache_key())
+}
+
+// How the old webview cache keyed lyrics: trimmed artist and title run
+// together.
+fn legacy_key(query: &TrackQuery) -> String {
+    format!("{}{}", query.artist.trim(), query.title.trim())
+}
+
+fn legacy_id(key: &str) -> String {
+    hash_key(&format!("legacy\n{}", key.trim().to_lowercase()))
+}
+
+// Stable across runs and Rust versions, unlike the std hasher: 64-bit FNV-1a,
+// as 16 hex digits.
+pub fn hash_key(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// Ids come from the webview, so they must not be able to name other files.
+fn check_id(id: &str) -> Result<(), String> {
+    if id.len() == 16 && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err("Invalid cache id".to_string())
+    }
+}
+
+async fn file_size(path: &Path) -> u64 {
+    tokio::fs::metadata(path)
+        .await
+        .map_or(0, |metadata| metadata.len())
+}
+
+async fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let contents = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+// Marks the entry as recently read, for eviction.
+async fn touch(path: &Path) {
+    let Ok(file) = tokio::fs::OpenOptions::new().append(true).open(path).await else {
+        return;
+    };
+    let _ = file.into_std().await.set_modified(SystemTime::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::fixture_dir;
+    use std::time::Duration;
+
+    fn query(artist: &str, title: &str) -> TrackQuery {
+        TrackQuery {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            album: Some("Album".to_string()),
+            duration: Some(200.2),
+            path: None,
+        }
+    }
+
+    fn lyrics(text: &str) -> LyricsResult {
+        LyricsResult {
+            plain: Some(text.to_string()),
+            provider: "lrclib".to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Marks the entry as last read `seconds` ago.
+    fn age(cache: &LyricsCache, query: &TrackQuery, seconds: u64) {
+        std::fs::File::options()
+            .append(true)
+            .open(cache.path(&entry_id(query)))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn ids_ignore_case_spacing_and_fractions_of_a_second() {
+        let id = entry_id(&query("Artist", "Song"));
+        let same = TrackQuery {
+            album: Some(" ALBUM ".to_string()),
+            duration: Some(199.8),
+            ..query("  artist", "SONG ")
+        };
+        assert_eq!(entry_id(&same), id);
+
+        let other_album = TrackQuery {
+            album: None,
+            ..query("Artist", "Song")
+        };
+        assert_ne!(entry_id(&other_album), id);
+        assert!(check_id(&id).is_ok());
+    }
+
+    #[test]
+    fn ids_that_could_name_other_files_are_refused() {
+        for id in [
+            "",
+            "../../etc/passwd",
+            "0123456789abcdeg",
+            "0123456789abcdef0",
+            "..\\0123456789ab",
+        ] {
+            assert!(check_id(id).is_err(), "{}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn legacy_entries_move_to_the_full_key_when_read() {
+        let dir = fixture_dir("cache-legacy", &[]);
+        let cache = LyricsCache::new(dir.0.clone(), u64::MAX);
+        assert!(cache
+            .import_legacy("ArtistSong", lyrics("Old"))
+            .await
+            .unwrap());
+        assert!(!cache
+            .import_legacy(" artistsong ", lyrics("Older"))
+            .await
+            .unwrap());
+
+        let query = query(" Artist", "Song ");
+        assert_eq!(
+            cache.get(&query).await.unwrap().plain.as_deref(),
+            Some("Old")
+        );
+        let entries = cache.list().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, entry_id(&query));
+        assert_eq!(entries[0].artist, "Artist");
+
+        // Lyrics under the full key win over the old ones
+        assert!(cache
+            .import_legacy("ArtistSong", lyrics("Old"))
+            .await
+            .unwrap());
+        cache.put(&query, &lyrics("New")).await.unwrap();
+        assert_eq!(
+            cache.get(&query).await.unwrap().plain.as_deref(),
+            Some("New")
+        );
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_read_unpinned_entries_go_first() {
+        let dir = fixture_dir("cache-evict", &[]);
+        let cache = LyricsCache::new(dir.0.clone(), u64::MAX);
+        let (pinned, read, unread) = (query("A", "1"), query("A", "2"), query("A", "3"));
+        cache.pin(&pinned, &lyrics("Pinned")).await.unwrap();
+        cache.put(&read, &lyrics("Read")).await.unwrap();
+        cache.put(&unread, &lyrics("Unread")).await.unwrap();
+        age(&cache, &pinned, 300);
+        age(&cache, &read, 200);
+        age(&cache, &unread, 100);
+        assert!(cache.get(&read).await.is_some());
+
+        // One byte over, which takes one entry going
+        let total: u64 = cache.list().await.unwrap().iter().map(|e| e.size).sum();
+        cache.set_max_size(total - 1);
+        cache.pin(&pinned, &lyrics("Pinned")).await.unwrap();
+
+        assert!(cache.pinned(&pinned).await.is_some());
+        assert!(cache.get(&read).await.is_some());
+        assert!(cache.get(&unread).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn purge_removes_one_entry_or_all_of_them() {
+        let dir = fixture_dir("cache-purge", &[]);
+        let cache = LyricsCache::new(dir.0.clone(), u64::MAX);
+        for title in ["1", "2", "3"] {
+            cache.put(&query("A", title), &lyrics(title)).await.unwrap();
+        }
+        let id = entry_id(&query("A", "1"));
+
+        assert_eq!(cache.inspect(&id).await.unwrap().title, "1");
+        assert_eq!(cache.purge(Some(&id)).await, Ok(1));
+        assert_eq!(cache.purge(Some(&id)).await, Ok(0));
+        assert!(cache.inspect(&id).await.is_err());
+        assert!(cache.purge(Some("../cache")).await.is_err());
+        assert!(cache.inspect("../cache").await.is_err());
+        assert_eq!(cache.purge(None).await, Ok(2));
+        assert!(cache.list().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+// The sync editor, for making synced lyrics out of plain ones while the track
+// plays. Each tap stamps the next line with the playback position. Seeking
+// back unstamps the lines stamped after the new position, so they get stamped
+// again as playback reaches them. Every change can be undone.
+
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard};
+
+use super::lrc::{Line, Lyrics};
+use super::TrackQuery;
+
+pub const PROVIDER: &str = "editor";
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorLine {
+    pub text: String,
+    // Seconds, once the line has been stamped
+    pub time: Option<f64>,
+}
+
+// The session as the webview shows it.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorState {
+    pub lines: Vec<EditorLine>,
+    // The line the next tap stamps, `None` once every line has a time
+    pub next: Option<usize>,
+    pub can_undo: bool,
+}
+
+struct Session {
+    // The track the lyrics are being made for, which may stop playing
+    // before they're saved
+    track: TrackQuery,
+    lines: Vec<String>,
+    // Times for the first `stamps.len()` lines, in order
+    stamps: Vec<f64>,
+    // What `stamps` was before each change, newest last
+    history: Vec<Vec<f64>>,
+}
+
+impl Session {
+    // Drops the stamps after `position`. Returns what they were, when any
+    // were dropped.
+    fn rewind(&mut self, position: f64) -> Option<Vec<f64>> {
+        let keep = self
+            .stamps
+            .iter()
+            .take_while(|time| **time <= position)
+            .count();
+        if keep == self.stamps.len() {
+            return None;
+        }
+        let before = self.stamps.clone();
+        self.stamps.truncate(keep);
+        Some(before)
+    }
+
+    fn state(&self) -> EditorState {
+        let lines = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| EditorLine {
+                text: text.clone(),
+                time: self.stamps.get(i).copied(),
+            })
+            .collect();
+        EditorState {
+            lines,
+            next: (self.stamps.len() < self.lines.len()).then_some(self.stamps.len()),
+            can_undo: !self.history.is_empty(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SyncEditor {
+    session: Mutex<Option<Session>>,
+}
+
+impl SyncEditor {
+    // Starts a session over `plain`'s lines, replacing any other. Blank lines
+    // only separate verses, so they're left out.
+    pub fn start(&self, track: TrackQuery, plain: &str) -> Result<EditorState, String> {
+        let lines: Vec<String> = plain
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        if lines.is_empty() {
+            return Err("There are no lyrics to sync".to_string());
+        }
+        let session = Session {
+            track,
+            lines,
+            stamps: Vec::new(),
+            history: Vec::new(),
+        };
+        let state = session.state();
+        *self.lock()? = Some(session);
+        Ok(state)
+    }
+
+    // Stamps the next line with `position`. Lines stamped after `position`
+    // are stamped again first, since playback was moved back over them.
+    pub fn tap(&self, position: f64) -> Result<EditorState, String> {
+        self.update(|session| {
+            let before = session
+                .rewind(position)
+                .unwrap_or_else(|| session.stamps.clone());
+            if session.stamps.len() >= session.lines.len() {
+                return Err("Every line has a time already".to_string());
+            }
+            session.stamps.push(position.max(0.0));
+            session.history.push(before);
+            Ok(())
+        })
+    }
+
+    // Playback jumped to `position`.
+    pub fn seeked(&self, position: f64) -> Result<(), String> {
+        let mut session = self.lock()?;
+        if let Some(session) = session.as_mut() {
+            if let Some(before) = session.rewind(position) {
+                session.history.push(before);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn undo(&self) -> Result<EditorState, String> {
+        self.update(|session| {
+            session.stamps = session.history.pop().ok_or("Nothing to undo")?;
+            Ok(())
+        })
+    }
+
+    // The finished lyrics and the track they're for. The session stays open
+    // so they can be saved more than one way.
+    pub fn lyrics(&self) -> Result<(TrackQuery, Lyrics), String> {
+        let session = self.lock()?;
+        let session = session.as_ref().ok_or("The sync editor isn't open")?;
+        let missing = session.lines.len() - session.stamps.len();
+        if missing > 0 {
+            return Err(format!(
+                "{} line{} still need{} a time",
+                missing,
+                if missing == 1 { "" } else { "s" },
+                if missing == 1 { "s" } else { "" }
+            ));
+        }
+
+        let track = &session.track;
+        let present = |value: &str| Some(value.to_string()).filter(|v| !v.trim().is_empty());
+        let lyrics = Lyrics {
+            title: present(&track.title),
+            artist: present(&track.artist),
+            album: track.album.as_deref().and_then(present),
+            length: track.duration,
+            lines: session
+                .lines
+                .iter()
+                .zip(&session.stamps)
+                .map(|(text, time)| Line {
+                    time: *time,
+                    text: text.clone(),
+                    words: Vec::new(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Ok((track.clone(), lyrics))
+    }
+
+    pub fn close(&self) -> Result<(), String> {
+        *self.lock()? = None;
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut Session) -> Result<(), String>,
+    ) -> Result<EditorState, String> {
+        let mut session = self.lock()?;
+        let session = session.as_mut().ok_or("The sync editor isn't open")?;
+        change(session)?;
+        Ok(session.state())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Option<Session>>, String> {
+        self.session
+            .lock()
+            .map_err(|_| "Failed to lock the sync editor".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor() -> SyncEditor {
+        let editor = SyncEditor::default();
+        let track = TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            duration: Some(180.0),
+            ..Default::default()
+        };
+        editor.start(track, "One\n\n  Two \nThree\n").unwrap();
+        editor
+    }
+
+    fn times(state: &EditorState) -> Vec<Option<f64>> {
+        state.lines.iter().map(|line| line.time).collect()
+    }
+
+    #[test]
+    fn taps_stamp_lines_in_order() {
+        let editor = editor();
+        let state = editor.tap(1.0).unwrap();
+        assert_eq!(state.lines[1].text, "Two");
+        assert_eq!(times(&state), [Some(1.0), None, None]);
+        assert_eq!(state.next, Some(1));
+        assert!(state.can_undo);
+
+        editor.tap(2.0).unwrap();
+        let state = editor.tap(3.0).unwrap();
+        assert_eq!(times(&state), [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(state.next, None);
+        assert_eq!(
+            editor.tap(4.0).unwrap_err(),
+            "Every line has a time already"
+        );
+    }
+
+    #[test]
+    fn seeking_back_unstamps_later_lines() {
+        let editor = editor();
+        for position in [1.0, 2.0, 3.0] {
+            editor.tap(position).unwrap();
+        }
+        editor.seeked(1.5).unwrap();
+        let state = editor.undo().unwrap();
+        assert_eq!(times(&state), [Some(1.0), Some(2.0), Some(3.0)]);
+
+        editor.seeked(1.5).unwrap();
+        // Seeking forward leaves the stamps alone
+        editor.seeked(10.0).unwrap();
+        let state = editor.tap(2.5).unwrap();
+        assert_eq!(times(&state), [Some(1.0), Some(2.5), None]);
+
+        // A tap behind the stamps restamps from there
+        let state = editor.tap(0.5).unwrap();
+        assert_eq!(times(&state), [Some(0.5), None, None]);
+    }
+
+    #[test]
+    fn undo_steps_back_through_taps_and_seeks() {
+        let editor = editor();
+        editor.tap(1.0).unwrap();
+        editor.tap(2.0).unwrap();
+        editor.seeked(0.0).unwrap();
+        editor.tap(0.5).unwrap();
+
+        let undone = [
+            vec![None, None, None],
+            vec![Some(1.0), Some(2.0), None],
+            vec![Some(1.0), None, None],
+            vec![None, None, None],
+        ];
+        for expected in undone {
+            assert_eq!(times(&editor.undo().unwrap()), expected);
+        }
+        assert_eq!(editor.undo().unwrap_err(), "Nothing to undo");
+    }
+
+    #[test]
+    fn lyrics_wait_for_every_line() {
+        let editor = editor();
+        editor.tap(1.0).unwrap();
+        assert_eq!(editor.lyrics().unwrap_err(), "2 lines still need a time");
+        editor.tap(2.0).unwrap();
+        assert_eq!(editor.lyrics().unwrap_err(), "1 line still needs a time");
+
+        editor.tap(3.0).unwrap();
+        let (track, lyrics) = editor.lyrics().unwrap();
+        assert_eq!(track.title, "Song");
+        assert_eq!(lyrics.title.as_deref(), Some("Song"));
+        assert_eq!(lyrics.album, None);
+        assert_eq!(lyrics.length, Some(180.0));
+        let lines: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(1.0, "One"), (2.0, "Two"), (3.0, "Three")]);
+
+        editor.close().unwrap();
+        assert!(editor.lyrics().is_err());
+        assert!(editor.tap(4.0).is_err());
+    }
+
+    #[test]
+    fn blank_lyrics_cant_be_synced() {
+        let editor = SyncEditor::default();
+        assert!(editor.start(TrackQuery::default(), " \n\n").is_err());
+    }
+}
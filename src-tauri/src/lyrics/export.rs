@@ -0,0 +1,396 @@
+// Writes the lyrics model out in formats other tools read: LRC for players,
+// SRT, WebVTT and ASS for video editors, and the TTML Apple Music uses. The
+// subtitle formats need an end time for every line, so each line runs until
+// the next one starts, for at most `MAX_CUE` unless its words run longer.
+// They have no offset field either, so the offset is applied to the times.
+
+use serde::Deserialize;
+
+use super::lrc::{self, Lyrics, Word};
+
+// Seconds a subtitle stays up without a next line or word timings to go by
+const MAX_CUE: f64 = 8.0;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Lrc,
+    Srt,
+    Vtt,
+    Ass,
+    Ttml,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Lrc => "lrc",
+            ExportFormat::Srt => "srt",
+            ExportFormat::Vtt => "vtt",
+            ExportFormat::Ass => "ass",
+            ExportFormat::Ttml => "ttml",
+        }
+    }
+}
+
+// The track the lyrics are for, as far as the webview knows it.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportTrack {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    // Seconds
+    pub duration: Option<f64>,
+}
+
+// A line as a subtitle: when it shows and when it goes away.
+struct Cue<'a> {
+    start: f64,
+    end: f64,
+    text: &'a str,
+    // Only the line's own word timings, never spread out ones
+    words: &'a [Word],
+}
+
+pub fn export(lyrics: &Lyrics, format: ExportFormat) -> String {
+    let timed = lyrics.without_offset();
+    let cues = cues(&timed);
+    match format {
+        ExportFormat::Lrc => lrc::to_lrc(lyrics),
+        ExportFormat::Srt => to_srt(&cues),
+        ExportFormat::Vtt => to_vtt(&cues),
+        ExportFormat::Ass => to_ass(&timed, &cues),
+        ExportFormat::Ttml => to_ttml(&timed, &cues),
+    }
+}
+
+// Empty lines only mark where the line before them ends, so they don't get
+// a cue of their own.
+fn cues(lyrics: &Lyrics) -> Vec<Cue<'_>> {
+    let lines = &lyrics.lines;
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.text.trim().is_empty())
+        .map(|(i, line)| {
+            let next = lines[i + 1..]
+                .iter()
+                .map(|next| next.time)
+                .find(|time| *time > line.time)
+                .or(lyrics.length.filter(|length| *length > line.time));
+            let words_end = line.words.last().map_or(line.time, |word| word.end);
+            let longest = (line.time + MAX_CUE).max(words_end);
+            Cue {
+                start: line.time,
+                end: next.map_or(longest, |next| next.min(longest)),
+                text: &line.text,
+                words: &line.words,
+            }
+        })
+        .collect()
+}
+
+// `hh:mm:ss` then `separator` and the fraction to `digits` places.
+fn clock(seconds: f64, separator: char, digits: u32) -> String {
+    let scale = 10u64.pow(digits);
+    let units = (seconds.max(0.0) * scale as f64).round() as u64;
+    let whole = units / scale;
+    format!(
+        "{:02}:{:02}:{:02}{}{:0width$}",
+        whole / 3600,
+        whole / 60 % 60,
+        whole % 60,
+        separator,
+        units % scale,
+        width = digits as usize
+    )
+}
+
+fn to_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            clock(cue.start, ',', 3),
+            clock(cue.end, ',', 3),
+            cue.text
+        ));
+    }
+    out
+}
+
+// Word timings become cue timestamps, which players use to highlight the
+// words as they're sung.
+fn to_vtt(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in cues {
+        out.push_str(&format!(
+            "{} --> {}\n",
+            clock(cue.start, '.', 3),
+            clock(cue.end, '.', 3)
+        ));
+        let mut text = String::new();
+        if cue.words.is_empty() {
+            text.push_str(&escape_markup(cue.text));
+        }
+        for (i, word) in cue.words.iter().enumerate() {
+            // A timestamp at the cue's start is redundant, and not allowed
+            if i > 0 || word.start > cue.start {
+                text.push_str(&format!("<{}>", clock(word.start, '.', 3)));
+            }
+            text.push_str(&escape_markup(&word.text));
+        }
+        out.push_str(text.trim_end());
+        out.push_str("\n\n");
+    }
+    out
+}
+
+fn to_ass(lyrics: &Lyrics, cues: &[Cue]) -> String {
+    // ASS times are `h:mm:ss.cc`
+    let time = |seconds: f64| clock(seconds, '.', 2)[1..].to_string();
+    let centiseconds = |seconds: f64| (seconds.max(0.0) * 100.0).round() as i64;
+
+    let mut out = String::from("[Script Info]\n");
+    if let Some(title) = &lyrics.title {
+        out.push_str(&format!("Title: {}\n", title));
+    }
+    out.push_str(concat!(
+        "ScriptType: v4.00+\n",
+        "PlayResX: 1920\n",
+        "PlayResY: 1080\n",
+        "WrapStyle: 0\n",
+        "\n",
+        "[V4+ Styles]\n",
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, ",
+        "BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, ",
+        "BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n",
+        "Style: Default,Arial,64,&H00FFFFFF,&H00808080,&H00000000,&H80000000,",
+        "0,0,0,0,100,100,0,0,1,3,0,2,60,60,60,1\n",
+        "\n",
+        "[Events]\n",
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    ));
+
+    for cue in cues {
+        let mut text = String::new();
+        if cue.words.is_empty() {
+            text.push_str(&escape_ass(cue.text));
+        }
+        // Each `\k` holds its syllable until the next one starts, counted in
+        // centiseconds from rounded times so rounding doesn't add up
+        let mut at = centiseconds(cue.start);
+        for (i, word) in cue.words.iter().enumerate() {
+            let start = centiseconds(word.start);
+            if i == 0 && start > at {
+                text.push_str(&format!("{{\\k{}}}", start - at));
+                at = start;
+            }
+            let until = cue
+                .words
+                .get(i + 1)
+                .map_or(word.end, |next| next.start)
+                .max(word.start);
+            let until = centiseconds(until).max(at);
+            text.push_str(&format!("{{\\k{}}}{}", until - at, escape_ass(&word.text)));
+            at = until;
+        }
+        out.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,,{}\n",
+            time(cue.start),
+            time(cue.end),
+            text.trim_end()
+        ));
+    }
+    out
+}
+
+// Timed per word when any line has word timings, as Apple's lyrics are.
+fn to_ttml(lyrics: &Lyrics, cues: &[Cue]) -> String {
+    let time = |seconds: f64| clock(seconds, '.', 3);
+    let by_word = cues.iter().any(|cue| !cue.words.is_empty());
+    let end = cues
+        .iter()
+        .map(|cue| cue.end)
+        .fold(lyrics.length.unwrap_or(0.0), f64::max);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        concat!(
+            "<tt xmlns=\"http://www.w3.org/ns/ttml\" ",
+            "xmlns:ttm=\"http://www.w3.org/ns/ttml#metadata\" ",
+            "xmlns:itunes=\"http://music.apple.com/lyric-ttml-internal\" ",
+            "itunes:timing=\"{}\">\n"
+        ),
+        if by_word { "Word" } else { "Line" }
+    ));
+    out.push_str("  <head>\n    <metadata>\n");
+    if let Some(title) = &lyrics.title {
+        out.push_str(&format!(
+            "      <ttm:title>{}</ttm:title>\n",
+            escape_markup(title)
+        ));
+    }
+    out.push_str("      <ttm:agent type=\"person\" xml:id=\"v1\"/>\n");
+    out.push_str("    </metadata>\n  </head>\n");
+    out.push_str(&format!("  <body dur=\"{}\">\n", time(end)));
+    if let Some(first) = cues.first() {
+        out.push_str(&format!(
+            "    <div begin=\"{}\" end=\"{}\">\n",
+            time(first.start),
+            time(end)
+        ));
+        for cue in cues {
+            out.push_str(&format!(
+                "      <p begin=\"{}\" end=\"{}\" ttm:agent=\"v1\">",
+                time(cue.start),
+                time(cue.end)
+            ));
+            if cue.words.is_empty() {
+                out.push_str(&escape_markup(cue.text));
+            }
+            for (i, word) in cue.words.iter().enumerate() {
+                out.push_str(&format!(
+                    "<span begin=\"{}\" end=\"{}\">{}</span>",
+                    time(word.start),
+                    time(word.end.max(word.start)),
+                    escape_markup(word.text.trim())
+                ));
+                // Spacing between spans is significant, inside them it isn't
+                if i + 1 < cue.words.len() && word.text.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+            }
+            out.push_str("</p>\n");
+        }
+        out.push_str("    </div>\n");
+    }
+    out.push_str("  </body>\n</tt>\n");
+    out
+}
+
+// For WebVTT cue text and XML alike.
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Braces would start an override block.
+fn escape_ass(text: &str) -> String {
+    text.replace('{', "\\{").replace('}', "\\}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Covers the offset, word timings, an empty line ending the one before,
+    // a gap longer than a cue lasts, and text that needs escaping
+    const LRC: &str = "[ti:Song & Dance]\n\
+                       [offset:500]\n\
+                       [00:01.50]Hello & <world>\n\
+                       [00:04.00]<00:04.20>Word <00:04.50>by {word}<00:05.25>\n\
+                       [00:06.00]\n\
+                       [00:20.00]Long gap\n";
+
+    fn export_as(format: ExportFormat) -> String {
+        export(&lrc::parse(LRC).lyrics, format)
+    }
+
+    #[test]
+    fn lrc_keeps_the_offset_as_a_tag() {
+        assert_eq!(export_as(ExportFormat::Lrc), LRC);
+    }
+
+    #[test]
+    fn srt() {
+        assert_eq!(
+            export_as(ExportFormat::Srt),
+            "1\n00:00:01,000 --> 00:00:03,500\nHello & <world>\n\n\
+             2\n00:00:03,500 --> 00:00:05,500\nWord by {word}\n\n\
+             3\n00:00:19,500 --> 00:00:27,500\nLong gap\n\n"
+        );
+    }
+
+    #[test]
+    fn vtt() {
+        assert_eq!(
+            export_as(ExportFormat::Vtt),
+            "WEBVTT\n\n\
+             00:00:01.000 --> 00:00:03.500\nHello &amp; &lt;world&gt;\n\n\
+             00:00:03.500 --> 00:00:05.500\n<00:00:03.700>Word <00:00:04.000>by {word}\n\n\
+             00:00:19.500 --> 00:00:27.500\nLong gap\n\n"
+        );
+    }
+
+    #[test]
+    fn ass_karaoke() {
+        let ass = export_as(ExportFormat::Ass);
+        assert!(ass.starts_with("[Script Info]\nTitle: Song & Dance\nScriptType: v4.00+\n"));
+        let events = &ass[ass.find("[Events]").unwrap()..];
+        assert_eq!(
+            events,
+            "[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,Hello & <world>\n\
+             Dialogue: 0,0:00:03.50,0:00:05.50,Default,,0,0,0,,{\\k20}{\\k30}Word {\\k75}by \\{word\\}\n\
+             Dialogue: 0,0:00:19.50,0:00:27.50,Default,,0,0,0,,Long gap\n"
+        );
+    }
+
+    #[test]
+    fn ass_syllables_add_up_despite_rounding() {
+        let lyrics = lrc::parse("[00:01.00]<00:01.004>a<00:01.333>b<00:01.666>c<00:02.000>").lyrics;
+        let ass = export(&lyrics, ExportFormat::Ass);
+        let line = ass.lines().last().unwrap();
+        assert!(line.ends_with(",,{\\k33}a{\\k34}b{\\k33}c"), "{}", line);
+    }
+
+    #[test]
+    fn ttml_by_word() {
+        assert_eq!(
+            export_as(ExportFormat::Ttml),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<tt xmlns=\"http://www.w3.org/ns/ttml\" ",
+                "xmlns:ttm=\"http://www.w3.org/ns/ttml#metadata\" ",
+                "xmlns:itunes=\"http://music.apple.com/lyric-ttml-internal\" ",
+                "itunes:timing=\"Word\">\n",
+                "  <head>\n",
+                "    <metadata>\n",
+                "      <ttm:title>Song &amp; Dance</ttm:title>\n",
+                "      <ttm:agent type=\"person\" xml:id=\"v1\"/>\n",
+                "    </metadata>\n",
+                "  </head>\n",
+                "  <body dur=\"00:00:27.500\">\n",
+                "    <div begin=\"00:00:01.000\" end=\"00:00:27.500\">\n",
+                "      <p begin=\"00:00:01.000\" end=\"00:00:03.500\" ttm:agent=\"v1\">",
+                "Hello &amp; &lt;world&gt;</p>\n",
+                "      <p begin=\"00:00:03.500\" end=\"00:00:05.500\" ttm:agent=\"v1\">",
+                "<span begin=\"00:00:03.700\" end=\"00:00:04.000\">Word</span> ",
+                "<span begin=\"00:00:04.000\" end=\"00:00:04.750\">by {word}</span></p>\n",
+                "      <p begin=\"00:00:19.500\" end=\"00:00:27.500\" ttm:agent=\"v1\">Long gap</p>\n",
+                "    </div>\n",
+                "  </body>\n",
+                "</tt>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn ttml_by_line_runs_to_the_track_length() {
+        let lyrics = lrc::parse("[length:00:30]\n[00:01.00]One\n[00:25.00]Two").lyrics;
+        let ttml = export(&lyrics, ExportFormat::Ttml);
+        assert!(ttml.contains("itunes:timing=\"Line\""));
+        assert!(ttml.contains("<body dur=\"00:00:30.000\">"));
+        // The first line's cue is cut short, the last one's by the length
+        assert!(ttml
+            .contains("<p begin=\"00:00:01.000\" end=\"00:00:09.000\" ttm:agent=\"v1\">One</p>"));
+        assert!(ttml
+            .contains("<p begin=\"00:00:25.000\" end=\"00:00:30.000\" ttm:agent=\"v1\">Two</p>"));
+    }
+}
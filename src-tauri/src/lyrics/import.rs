@@ -0,0 +1,571 @@
+// Lyrics the user already has, from a file or the clipboard. LRC, SRT,
+// WebVTT and TTML (as Apple Music uses) are told apart by their contents;
+// untimed text is turned away, as it couldn't be followed. Subtitle cues
+// have an end time the lyrics model has no place for, so a cue that ends
+// before the next one starts is followed by an empty line, the way LRC marks
+// a gap.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::path::Path;
+
+use super::lrc::{self, Line, Lyrics, Word};
+use super::{lyrics_from_model, LyricsResult};
+
+pub const PROVIDER: &str = "import";
+
+// Lyrics files are a few kilobytes; anything this big is something else
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+// How much later than a cue's end the next one has to start for the gap to
+// be kept
+const MIN_GAP: f64 = 0.1;
+
+pub async fn read_file(path: &Path) -> Result<String, String> {
+    let metadata = tokio::fs::metadata(path).await.map_err(|e| e.to_string())?;
+    if metadata.len() > MAX_FILE_SIZE {
+        return Err("File is too large to be lyrics".to_string());
+    }
+    let bytes = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn import(text: &str) -> Result<LyricsResult, String> {
+    let text = text.trim_start_matches('\u{feff}').trim();
+    if text.is_empty() {
+        return Err("Nothing to import".to_string());
+    }
+
+    let lyrics = if text.starts_with("WEBVTT") {
+        from_cues(parse_vtt(text))
+    } else if text.starts_with('<') {
+        parse_ttml(text)?
+    } else if text.lines().take(2).any(|line| line.contains("-->")) {
+        from_cues(parse_srt(text))
+    } else {
+        lrc::parse(text).lyrics
+    };
+    if lyrics.lines.iter().all(|line| line.text.is_empty()) {
+        return Err("No timed lyrics found. Use LRC, SRT, WebVTT or TTML.".to_string());
+    }
+    Ok(lyrics_from_model(lyrics, PROVIDER))
+}
+
+// Lines with the time each one stops showing, when known.
+fn from_cues(mut cues: Vec<(Line, Option<f64>)>) -> Lyrics {
+    cues.sort_by(|a, b| a.0.time.total_cmp(&b.0.time));
+    let starts: Vec<Option<f64>> = (0..cues.len())
+        .map(|i| cues.get(i + 1).map(|(line, _)| line.time))
+        .collect();
+
+    let mut lines = Vec::new();
+    for ((line, end), next) in cues.into_iter().zip(starts) {
+        let end = end.filter(|end| *end > line.time);
+        lines.push(line);
+        let Some(end) = end else {
+            continue;
+        };
+        if next.map_or(true, |next| next > end + MIN_GAP) {
+            lines.push(Line {
+                time: end,
+                ..Default::default()
+            });
+        }
+    }
+    Lyrics {
+        lines,
+        ..Default::default()
+    }
+}
+
+// Numbered cues of `start --> end` and text, split by blank lines. Styling
+// tags are dropped.
+fn parse_srt(text: &str) -> Vec<(Line, Option<f64>)> {
+    let mut cues = Vec::new();
+    for block in blocks(text) {
+        let Some(at) = block.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+        let Some((start, end)) = cue_times(block[at]) else {
+            continue;
+        };
+        let text = strip_tags(&block[at + 1..].join(" "));
+        if !text.is_empty() {
+            cues.push((
+                Line {
+                    time: start,
+                    text,
+                    words: Vec::new(),
+                },
+                Some(end),
+            ));
+        }
+    }
+    cues
+}
+
+// Like SRT, minus the numbers, plus a header and blocks that aren't cues.
+// Timestamps inside a cue's text time the words after them.
+fn parse_vtt(text: &str) -> Vec<(Line, Option<f64>)> {
+    let mut cues = Vec::new();
+    for block in blocks(text) {
+        let Some(at) = block.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+        let Some((start, end)) = cue_times(block[at]) else {
+            continue;
+        };
+        let (text, words) = vtt_words(&block[at + 1..].join(" "), start, end);
+        if !text.is_empty() {
+            cues.push((
+                Line {
+                    time: start,
+                    text,
+                    words,
+                },
+                Some(end),
+            ));
+        }
+    }
+    cues
+}
+
+// Plain text and timed words from cue text. Text before the first timestamp
+// starts with the cue.
+fn vtt_words(text: &str, start: f64, end: f64) -> (String, Vec<Word>) {
+    let mut plain = String::new();
+    let mut words: Vec<Word> = Vec::new();
+    let mut rest = text;
+    let push = |plain: &mut String, words: &mut Vec<Word>, text: &str| {
+        let text = unescape(text);
+        plain.push_str(&text);
+        if let Some(word) = words.last_mut() {
+            word.text.push_str(&text);
+        }
+    };
+
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        push(&mut plain, &mut words, &rest[..open]);
+        if let Some(time) = parse_clock(&rest[open + 1..open + close]) {
+            if words.is_empty() && !plain.trim().is_empty() {
+                words.push(Word {
+                    start,
+                    end: time,
+                    text: plain.clone(),
+                });
+            }
+            if let Some(previous) = words.last_mut() {
+                previous.end = time;
+            }
+            words.push(Word {
+                start: time,
+                end: time,
+                text: String::new(),
+            });
+        }
+        rest = &rest[open + close + 1..];
+    }
+    push(&mut plain, &mut words, rest);
+
+    if let Some(last) = words.last_mut() {
+        last.end = end.max(last.start);
+    }
+    words.retain(|word| !word.text.trim().is_empty());
+    (plain.trim().to_string(), words)
+}
+
+// `<p>` elements are lines and timed `<span>`s in them are words. Background
+// vocals, translations and romanizations are left out.
+fn parse_ttml(text: &str) -> Result<Lyrics, String> {
+    let invalid = |e: quick_xml::Error| format!("Invalid TTML: {}", e);
+    let mut reader = Reader::from_str(text);
+    let mut cues = Vec::new();
+    let mut line: Option<(Line, Option<f64>)> = None;
+    let mut word: Option<Word> = None;
+    // How deep into a left-out span the reader is
+    let mut skipping = 0;
+
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(element) => {
+                if skipping > 0 {
+                    skipping += 1;
+                    continue;
+                }
+                match element.local_name().as_ref() {
+                    b"p" => {
+                        let begin = time_attribute(&element, b"begin");
+                        let end = time_attribute(&element, b"end");
+                        line = begin.map(|time| {
+                            let line = Line {
+                                time,
+                                ..Default::default()
+                            };
+                            (line, end)
+                        });
+                    }
+                    b"span" if line.is_some() => {
+                        let role = attribute(&element, b"role");
+                        if role.is_some_and(|role| role.starts_with("x-")) {
+                            skipping = 1;
+                            continue;
+                        }
+                        if let Some(start) = time_attribute(&element, b"begin") {
+                            let end = time_attribute(&element, b"end").unwrap_or(start);
+                            word = Some(Word {
+                                start,
+                                end: end.max(start),
+                                text: String::new(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Empty(element) if element.local_name().as_ref() == b"br" => {
+                if let Some((line, _)) = &mut line {
+                    let target = line.words.last_mut().map(|previous| &mut previous.text);
+                    push_collapsed(&mut line.text, target, " ");
+                }
+            }
+            Event::Text(text) if skipping == 0 => {
+                let Some((line, _)) = &mut line else {
+                    continue;
+                };
+                let text = text.unescape().map_err(invalid)?;
+                let target = match &mut word {
+                    Some(word) => Some(&mut word.text),
+                    None => line.words.last_mut().map(|previous| &mut previous.text),
+                };
+                push_collapsed(&mut line.text, target, &text);
+            }
+            Event::End(element) => {
+                if skipping > 0 {
+                    skipping -= 1;
+                    continue;
+                }
+                match element.local_name().as_ref() {
+                    b"span" => {
+                        if let (Some(word), Some((line, _))) = (word.take(), &mut line) {
+                            line.words.push(word);
+                        }
+                    }
+                    b"p" => {
+                        if let Some((mut line, end)) = line.take() {
+                            line.text = line.text.trim().to_string();
+                            line.words.retain(|word| !word.text.trim().is_empty());
+                            if let Some(first) = line.words.first_mut() {
+                                first.text = first.text.trim_start().to_string();
+                            }
+                            if !line.text.is_empty() {
+                                cues.push((line, end));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(from_cues(cues))
+}
+
+// Appends `text` to the line, and to the word it belongs to, with whitespace
+// runs collapsed to one space as XML renders them.
+fn push_collapsed(line: &mut String, mut word: Option<&mut String>, text: &str) {
+    for c in text.chars() {
+        let c = if c.is_whitespace() { ' ' } else { c };
+        if c == ' ' && (line.is_empty() || line.ends_with(' ')) {
+            continue;
+        }
+        line.push(c);
+        if let Some(word) = &mut word {
+            word.push(c);
+        }
+    }
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name)
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+fn time_attribute(element: &BytesStart, name: &[u8]) -> Option<f64> {
+    parse_time(&attribute(element, name)?)
+}
+
+// TTML times: a clock time (`1:23.456`, `00:01:23.456`), seconds on their
+// own, or a number with a unit (`83.456s`, `500ms`).
+fn parse_time(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let units = [("ms", 0.001), ("h", 3600.0), ("m", 60.0), ("s", 1.0)];
+    let unit = units
+        .iter()
+        .find_map(|(unit, scale)| Some((text.strip_suffix(unit)?, scale)));
+    let seconds = match unit {
+        Some((value, scale)) => value.parse::<f64>().ok()? * scale,
+        None if text.contains(':') => parse_clock(text)?,
+        None => text.parse().ok()?,
+    };
+    // `inf`, `NaN` and negative numbers parse, but aren't times
+    (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+}
+
+// `hh:mm:ss.mmm` or `mm:ss.mmm`, with a comma allowed before the fraction
+// as SRT has it.
+fn parse_clock(text: &str) -> Option<f64> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    let (seconds, whole) = parts.split_last()?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let seconds = seconds.replace(',', ".");
+    let (integer, fraction) = seconds.split_once('.').unwrap_or((&seconds, "0"));
+    if !whole.iter().all(|part| digits(part)) || !digits(integer) || !digits(fraction) {
+        return None;
+    }
+    let seconds: f64 = seconds.parse().ok()?;
+    Some(
+        whole.iter().try_fold(0.0, |total, part| {
+            Some(total * 60.0 + part.parse::<f64>().ok()?)
+        })? * 60.0
+            + seconds,
+    )
+}
+
+// `start --> end`, ignoring WebVTT cue settings after the end.
+fn cue_times(line: &str) -> Option<(f64, f64)> {
+    let (start, end) = line.split_once("-->")?;
+    let start = parse_clock(start)?;
+    let end = parse_clock(end.split_whitespace().next()?)?;
+    Some((start, end.max(start)))
+}
+
+// Runs of non-blank lines.
+fn blocks(text: &str) -> Vec<Vec<&str>> {
+    let mut blocks = vec![Vec::new()];
+    for line in text.lines() {
+        match blocks.last_mut() {
+            Some(block) if !line.trim().is_empty() => block.push(line.trim()),
+            Some(block) if !block.is_empty() => blocks.push(Vec::new()),
+            _ => {}
+        }
+    }
+    blocks.retain(|block| !block.is_empty());
+    blocks
+}
+
+// Drops `<i>`-style markup and `{\an8}`-style overrides.
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find(['<', '{']) {
+        let tag = &rest[open..];
+        let close = if tag.starts_with('<') || tag.starts_with("{\\") {
+            tag.find(['>', '}'])
+        } else {
+            None
+        };
+        let Some(close) = close else {
+            plain.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+        plain.push_str(&rest[..open]);
+        rest = &rest[open + close + 1..];
+    }
+    plain.push_str(rest);
+    unescape(plain.trim())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::export::{export, ExportFormat};
+
+    fn lines(text: &str) -> Vec<(f64, String)> {
+        import(text)
+            .unwrap()
+            .parsed
+            .unwrap()
+            .lines
+            .into_iter()
+            .map(|line| (line.time, line.text))
+            .collect()
+    }
+
+    fn line(time: f64, text: &str) -> (f64, String) {
+        (time, text.to_string())
+    }
+
+    #[test]
+    fn formats_are_told_apart_by_their_contents() {
+        let expected = [line(1.0, "One"), line(2.0, "Two")];
+        assert_eq!(lines("\u{feff}[00:01.00]One\n[00:02.00]Two"), expected);
+        assert_eq!(
+            lines("1\n00:00:01,000 --> 00:00:02,000\nOne\n\n2\n00:00:02,000 --> 00:00:02,000\nTwo"),
+            expected
+        );
+        assert_eq!(
+            lines("WEBVTT\n\nNOTE a comment\n\n00:01.000 --> 00:02.000 align:start\nOne\n\n00:02.000 --> 00:02.000\nTwo"),
+            expected
+        );
+        assert_eq!(
+            lines("<tt><body><div><p begin=\"1s\" end=\"2s\">One</p><p begin=\"2s\">Two</p></div></body></tt>"),
+            expected
+        );
+        assert_eq!(import(&"\n".repeat(3)).unwrap_err(), "Nothing to import");
+        assert!(import("Just some words\nwith no times").is_err());
+        assert!(import("<tt><p begin=\"1s\">Unclosed</tt>").is_err());
+    }
+
+    #[test]
+    fn cue_gaps_become_empty_lines() {
+        let srt = "1\n00:00:01,000 --> 00:00:03,000\n<i>One</i>\n\n\
+                   2\n00:00:03,050 --> 00:00:04,000\n{\\an8}Two &amp; more\n\n\
+                   3\n00:00:10,000 --> 00:00:12,000\nThree\nand a half\n";
+        // A gap shorter than `MIN_GAP` isn't one; the last cue's end is kept
+        assert_eq!(
+            lines(srt),
+            [
+                line(1.0, "One"),
+                line(3.05, "Two & more"),
+                line(4.0, ""),
+                line(10.0, "Three and a half"),
+                line(12.0, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn cues_are_sorted() {
+        let srt = "2\n00:00:05,000 --> 00:00:06,000\nLater\n\n\
+                   1\n00:00:01,000 --> 00:00:05,000\nEarlier\n";
+        assert_eq!(
+            lines(srt),
+            [line(1.0, "Earlier"), line(5.0, "Later"), line(6.0, "")]
+        );
+    }
+
+    #[test]
+    fn vtt_timestamps_time_the_words() {
+        let vtt = "WEBVTT\n\n00:00:01.000 --> 00:00:03.000\nHey <00:00:01.500>there <00:00:02.000>&lt;you&gt;\n";
+        let lyrics = import(vtt).unwrap().parsed.unwrap();
+        let words: Vec<(f64, f64, &str)> = lyrics.lines[0]
+            .words
+            .iter()
+            .map(|word| (word.start, word.end, word.text.as_str()))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                (1.0, 1.5, "Hey "),
+                (1.5, 2.0, "there "),
+                (2.0, 3.0, "<you>")
+            ]
+        );
+        assert_eq!(lyrics.lines[0].text, "Hey there <you>");
+    }
+
+    #[test]
+    fn ttml_leaves_out_background_vocals() {
+        let ttml = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata">
+  <body><div>
+    <p begin="00:01.000" end="00:03.000">
+      <span begin="00:01.000" end="00:01.500">Lead</span>
+      <span begin="00:01.500" end="00:02.000">line</span>
+      <span ttm:role="x-bg"><span begin="00:02.000" end="00:02.500">(ooh</span> <span begin="00:02.500" end="00:03.000">ooh)</span></span>
+      <span ttm:role="x-translation">Translated</span>
+    </p>
+    <p begin="1:00.5" end="61.5s"><span begin="60500ms" end="61s">Next</span><br/><span begin="61s" end="61.5s">row</span></p>
+  </div></body>
+</tt>"#;
+        let lyrics = import(ttml).unwrap().parsed.unwrap();
+        let line = &lyrics.lines[0];
+        assert_eq!(line.text, "Lead line");
+        let words: Vec<(f64, f64, &str)> = line
+            .words
+            .iter()
+            .map(|word| (word.start, word.end, word.text.as_str()))
+            .collect();
+        assert_eq!(words, [(1.0, 1.5, "Lead "), (1.5, 2.0, "line ")]);
+
+        let times: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect();
+        assert_eq!(
+            times,
+            [
+                (1.0, "Lead line"),
+                (3.0, ""),
+                (60.5, "Next row"),
+                (61.5, "")
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_import_back() {
+        let lrc_text = "[00:01.00]<00:01.00>One <00:01.50>two<00:02.00>\n[00:04.00]Three\n";
+        let original = lrc::parse(lrc_text).lyrics;
+        for format in [ExportFormat::Srt, ExportFormat::Vtt, ExportFormat::Ttml] {
+            let lyrics = import(&export(&original, format)).unwrap().parsed.unwrap();
+            let texts: Vec<&str> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+            assert_eq!(texts, ["One two", "Three", ""], "{:?}", format);
+            assert_eq!(lyrics.lines[1].time, 4.0);
+            assert_eq!(lyrics.lines[2].time, 12.0);
+        }
+    }
+
+    #[test]
+    fn clock_times() {
+        assert_eq!(parse_clock("01:02:03,500"), Some(3723.5));
+        assert_eq!(parse_clock("02:03.25"), Some(123.25));
+        assert_eq!(parse_clock("1:2:3:4"), None);
+        assert_eq!(parse_clock("aa:00"), None);
+        assert_eq!(parse_time("1.5h"), Some(5400.0));
+        assert_eq!(parse_time("83.456s"), Some(83.456));
+        assert_eq!(parse_time("12"), Some(12.0));
+        assert_eq!(parse_time("NaN"), None);
+    }
+
+    #[test]
+    fn times_that_arent_times_are_refused() {
+        for text in [
+            "infs", "NaNs", "-5s", "-1", "inf", "1e400ms", "1e306h", "s", "",
+        ] {
+            assert_eq!(parse_time(text), None, "{}", text);
+        }
+        assert_eq!(parse_time("0s"), Some(0.0));
+
+        let ttml =
+            r#"<tt><body><div><p begin="infs">Never</p><p begin="1s">Once</p></div></body></tt>"#;
+        let lyrics = parse_ttml(ttml).unwrap();
+        let lines: Vec<(f64, &str)> = lyrics
+            .lines
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(1.0, "Once")]);
+    }
+}
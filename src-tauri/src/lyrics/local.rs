@@ -0,0 +1,283 @@
+// Lyrics already on disk. For a local audio file, its `<basename>.lrc`
+// sidecar is looked for first, then lyrics embedded in its tags. After that
+// come the folders listed in the settings, where a track is found as
+// `Artist - Title.lrc`, `Title.lrc` or `Artist/Title.lrc`. `.txt` is accepted
+// for plain lyrics, and names are compared case-insensitively.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{
+    lyrics_from_model, lyrics_from_text, Candidate, LyricsProvider, LyricsResult, ProviderError,
+    TrackQuery,
+};
+use crate::tags;
+
+const EXTENSIONS: [&str; 2] = ["lrc", "txt"];
+
+// Reported as the provider of lyrics read from the file's tags
+const EMBEDDED: &str = "embedded";
+
+pub struct LocalFolder {
+    folders: Vec<PathBuf>,
+}
+
+impl LocalFolder {
+    pub fn new(folders: Vec<PathBuf>) -> Self {
+        LocalFolder { folders }
+    }
+
+    pub async fn find(&self, query: &TrackQuery) -> Option<PathBuf> {
+        let artist = file_name(&query.artist);
+        let title = file_name(&query.title);
+        let names = [format!("{} - {}", artist, title), title.clone()];
+
+        for folder in &self.folders {
+            let listing = entries(folder).await;
+            if let Some(path) = find_file(&listing, &names) {
+                return Some(path);
+            }
+            if let Some(artist_dir) = listing.get(&artist).filter(|path| path.is_dir()) {
+                if let Some(path) =
+                    find_file(&entries(artist_dir).await, std::slice::from_ref(&title))
+                {
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+}
+
+// `<basename>.lrc` (or `.txt`) next to the audio file.
+pub async fn sidecar(audio: &Path) -> Option<PathBuf> {
+    let stem = audio.file_stem()?.to_string_lossy().to_lowercase();
+    let listing = entries(audio.parent()?).await;
+    find_file(&listing, &[stem])
+}
+
+// Lyrics in the audio file's own tags, preferring time-synced ones. The
+// candidate carries them, along with what the tags say the track is, falling
+// back to what the player said.
+async fn embedded(audio: &Path, query: &TrackQuery) -> Option<Candidate> {
+    let path = audio.to_path_buf();
+    let tags = match tokio::task::spawn_blocking(move || tags::read(&path)).await {
+        Ok(Ok(tags)) => tags,
+        Ok(Err(e)) => {
+            eprintln!("Failed to read tags from {}: {}", audio.display(), e);
+            return None;
+        }
+        Err(_) => return None,
+    };
+
+    let lyrics = match (tags.synced, tags.plain) {
+        (Some(synced), _) => lyrics_from_model(synced, EMBEDDED),
+        (None, Some(plain)) => lyrics_from_text(plain, EMBEDDED)?,
+        (None, None) => return None,
+    };
+    Some(Candidate {
+        provider: "local".to_string(),
+        id: audio.to_string_lossy().into_owned(),
+        artist: tags.artist.unwrap_or_else(|| query.artist.clone()),
+        title: tags.title.unwrap_or_else(|| query.title.clone()),
+        album: tags.album.or_else(|| query.album.clone()),
+        duration: tags.duration,
+        synced: Some(lyrics.synced.is_some()),
+        score: 0.0,
+        lyrics: Some(lyrics),
+    })
+}
+
+// Lowercased, with characters that can't appear in file names replaced the
+// way most taggers do.
+fn file_name(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_")
+}
+
+// Directory listing keyed by lowercased file name.
+async fn entries(dir: &Path) -> HashMap<String, PathBuf> {
+    let mut entries = HashMap::new();
+    let Ok(mut read_dir) = tokio::fs::read_dir(dir).await else {
+        return entries;
+    };
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        entries.insert(
+            entry.file_name().to_string_lossy().to_lowercase(),
+            entry.path(),
+        );
+    }
+    entries
+}
+
+fn find_file(entries: &HashMap<String, PathBuf>, names: &[String]) -> Option<PathBuf> {
+    names.iter().find_map(|name| {
+        EXTENSIONS
+            .iter()
+            .find_map(|extension| entries.get(&format!("{}.{}", name, extension)))
+            .filter(|path| path.is_file())
+            .cloned()
+    })
+}
+
+#[async_trait]
+impl LyricsProvider for LocalFolder {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    // A file is matched by name, so it's taken to be the track itself.
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+        let candidate = |path: PathBuf| Candidate {
+            provider: self.name().to_string(),
+            id: path.to_string_lossy().into_owned(),
+            artist: query.artist.clone(),
+            title: query.title.clone(),
+            album: query.album.clone(),
+            ..Default::default()
+        };
+
+        if let Some(audio) = &query.path {
+            if let Some(path) = sidecar(audio).await {
+                return Ok(vec![candidate(path)]);
+            }
+            if let Some(embedded) = embedded(audio, query).await {
+                return Ok(vec![embedded]);
+            }
+        }
+        Ok(self.find(query).await.map(candidate).into_iter().collect())
+    }
+
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError> {
+        let text = tokio::fs::read_to_string(&candidate.id)
+            .await
+            .map_err(|e| ProviderError::Fatal(format!("{}: {}", candidate.id, e)))?;
+        Ok(lyrics_from_text(text, self.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::{fixture_dir, flac};
+
+    fn query(artist: &str, title: &str) -> TrackQuery {
+        TrackQuery {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sidecars_of_either_kind_are_found() {
+        let dir = fixture_dir(
+            "sidecars",
+            &[("Plain.TXT", "Hello"), ("synced.lrc", "[00:01.00]Hello")],
+        );
+
+        let found = sidecar(&dir.join("plain.mp3")).await;
+        assert_eq!(found, Some(dir.join("Plain.TXT")));
+        let found = sidecar(&dir.join("Synced.flac")).await;
+        assert_eq!(found, Some(dir.join("synced.lrc")));
+        assert_eq!(sidecar(&dir.join("other.mp3")).await, None);
+    }
+
+    #[tokio::test]
+    async fn tracks_are_found_by_each_folder_pattern() {
+        let dir = fixture_dir(
+            "lyrics-folder",
+            &[
+                ("Band - First.lrc", "[00:01.00]One"),
+                ("Second.txt", "Two"),
+                ("band/Third.lrc", "[00:01.00]Three"),
+                ("AC_DC - T.N.T..lrc", "[00:01.00]Oi"),
+            ],
+        );
+        let local = LocalFolder::new(vec![dir.0.clone()]);
+
+        let found = local.find(&query("band", "FIRST ")).await;
+        assert_eq!(found, Some(dir.join("Band - First.lrc")));
+        let found = local.find(&query("Band", "Second")).await;
+        assert_eq!(found, Some(dir.join("Second.txt")));
+        let found = local.find(&query("Band", "Third")).await;
+        assert_eq!(found, Some(dir.join("band/Third.lrc")));
+        let found = local.find(&query("AC/DC", "T.N.T.")).await;
+        assert_eq!(found, Some(dir.join("AC_DC - T.N.T..lrc")));
+        assert_eq!(local.find(&query("Band", "Fourth")).await, None);
+        assert_eq!(local.find(&query("Other", "Third")).await, None);
+    }
+
+    #[tokio::test]
+    async fn lrc_wins_over_txt() {
+        let dir = fixture_dir(
+            "lrc-over-txt",
+            &[
+                ("Song.txt", "Plain"),
+                ("Song.lrc", "[00:01.00]Synced"),
+                ("Band - Other.txt", "Plain"),
+                ("Band - Other.lrc", "[00:01.00]Synced"),
+            ],
+        );
+
+        let found = sidecar(&dir.join("song.mp3")).await;
+        assert_eq!(found, Some(dir.join("Song.lrc")));
+        let found = LocalFolder::new(vec![dir.0.clone()])
+            .find(&query("Band", "Other"))
+            .await;
+        assert_eq!(found, Some(dir.join("Band - Other.lrc")));
+    }
+
+    #[tokio::test]
+    async fn a_sidecar_comes_before_the_tags_and_the_tags_before_the_folders() {
+        let dir = fixture_dir(
+            "local-precedence",
+            &[
+                ("music/song.lrc", "[00:01.00]Sidecar"),
+                ("lyrics/Band - Song.lrc", "[00:01.00]Folder"),
+            ],
+        );
+        std::fs::write(
+            dir.join("music/song.flac"),
+            flac(&["TITLE=Tagged", "LYRICS=[00:01.00]Embedded"]),
+        )
+        .unwrap();
+        std::fs::write(dir.join("music/bare.flac"), flac(&[])).unwrap();
+        let local = LocalFolder::new(vec![dir.join("lyrics")]);
+        let track = |file: &str| TrackQuery {
+            path: Some(dir.join("music").join(file)),
+            ..query("Band", "Song")
+        };
+        let found = |candidates: Vec<Candidate>| {
+            assert_eq!(candidates.len(), 1);
+            candidates.into_iter().next().unwrap()
+        };
+
+        let candidate = found(local.search(&track("song.flac")).await.ok().unwrap());
+        assert_eq!(candidate.id, dir.join("music/song.lrc").to_string_lossy());
+        let lyrics = local.lyrics(&candidate).await.ok().unwrap().unwrap();
+        assert_eq!(lyrics.synced.as_deref(), Some("[00:01.00]Sidecar"));
+
+        std::fs::remove_file(dir.join("music/song.lrc")).unwrap();
+        let candidate = found(local.search(&track("song.flac")).await.ok().unwrap());
+        assert_eq!(candidate.id, dir.join("music/song.flac").to_string_lossy());
+        assert_eq!(candidate.title, "Tagged");
+        let lyrics = candidate.lyrics.unwrap();
+        assert_eq!(lyrics.provider, EMBEDDED);
+        assert_eq!(lyrics.parsed.unwrap().lines[0].text, "Embedded");
+
+        let candidate = found(local.search(&track("bare.flac")).await.ok().unwrap());
+        assert_eq!(
+            candidate.id,
+            dir.join("lyrics/Band - Song.lrc").to_string_lossy()
+        );
+        assert!(local
+            .search(&query("Band", "Other"))
+            .await
+            .ok()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,542 @@
+// LRC parsing and writing. Handles the common dialects: several timestamps on
+// one line, the `[ar:]`/`[ti:]`/`[al:]`/`[length:]`/`[offset:]` header tags,
+// and Enhanced LRC's inline `<mm:ss.xx>` word timings. Problems are collected as
+// diagnostics with the line they were found on instead of failing the whole
+// file, since most lyrics in the wild have a few broken lines.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    // Seconds, from `[length:]`
+    pub length: Option<f64>,
+    // Seconds, from `[offset:]`. Positive values show lines earlier.
+    pub offset: f64,
+    // Header tags without a field of their own, such as `[by:]` or `[re:]`
+    pub tags: BTreeMap<String, String>,
+    // Sorted by time. Times are as written, before the offset is applied.
+    pub lines: Vec<Line>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Line {
+    // Seconds
+    pub time: f64,
+    pub text: String,
+    // Empty unless the line has Enhanced LRC word timings
+    pub words: Vec<Word>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Word {
+    // Seconds
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+impl Lyrics {
+    // When line `i` gives way to the next one, or to the end of the track.
+    pub fn line_end(&self, i: usize) -> Option<f64> {
+        self.lines.get(i + 1).map(|line| line.time).or(self.length)
+    }
+
+    // A copy with the offset applied to every timestamp, for formats and
+    // players that don't honor `[offset:]`.
+    pub fn without_offset(&self) -> Lyrics {
+        let mut lyrics = self.clone();
+        let offset = std::mem::take(&mut lyrics.offset);
+        for line in &mut lyrics.lines {
+            line.time = (line.time - offset).max(0.0);
+            for word in &mut line.words {
+                word.start = (word.start - offset).max(0.0);
+                word.end = (word.end - offset).max(0.0);
+            }
+        }
+        lyrics
+    }
+}
+
+impl Line {
+    // The line's words with their timings. Lines without word timings of
+    // their own get them spread evenly up to `end`.
+    pub fn timed_words(&self, end: Option<f64>) -> Vec<Word> {
+        if !self.words.is_empty() {
+            return self.words.clone();
+        }
+
+        let pieces: Vec<&str> = self
+            .text
+            .split_inclusive(' ')
+            .filter(|piece| !piece.trim().is_empty())
+            .collect();
+        let span = end.map_or(0.0, |end| (end - self.time).max(0.0)) / pieces.len().max(1) as f64;
+        pieces
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| Word {
+                start: self.time + span * i as f64,
+                end: self.time + span * (i + 1) as f64,
+                text: piece.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    // 1-based
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Parsed {
+    pub lyrics: Lyrics,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn parse(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    // Lines of text without a timestamp, reported together at the end
+    let mut untimed = Vec::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let number = i + 1;
+        let mut warn = |message: String| {
+            parsed.diagnostics.push(Diagnostic {
+                line: number,
+                message,
+            })
+        };
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // Leading `[...]` groups are either timestamps or one header tag
+        let mut rest = line;
+        let mut times = Vec::new();
+        let mut tag = None;
+        while let Some(inner) = rest.strip_prefix('[') {
+            let Some(end) = inner.find(']') else {
+                warn("Unclosed '['".to_string());
+                times.clear();
+                tag = None;
+                rest = "";
+                break;
+            };
+            let content = &inner[..end];
+            rest = &inner[end + 1..];
+            match parse_timestamp(content) {
+                Some(time) => times.push(time),
+                None if times.is_empty() && tag.is_none() => tag = Some(content),
+                None => warn(format!("Invalid timestamp '[{}]'", content)),
+            }
+        }
+
+        if times.is_empty() {
+            match tag {
+                // Header tags stand alone, so anything after one means it
+                // was meant as a timestamp
+                Some(tag) if !rest.trim().is_empty() => {
+                    warn(format!("Invalid timestamp '[{}]'", tag))
+                }
+                Some(tag) => apply_tag(&mut parsed.lyrics, tag, &mut warn),
+                None if line.starts_with('[') => {}
+                None => untimed.push(number),
+            }
+            continue;
+        }
+        if let Some(tag) = tag {
+            warn(format!("Invalid timestamp '[{}]'", tag));
+        }
+
+        let (text, words) = parse_words(rest, &mut warn);
+        // A line stamped more than once repeats with its word timings moved
+        // along with it
+        let first = times[0];
+        for time in times {
+            parsed.lyrics.lines.push(Line {
+                time,
+                text: text.clone(),
+                words: words
+                    .iter()
+                    .map(|word| Word {
+                        start: word.start + (time - first),
+                        end: word.end + (time - first),
+                        text: word.text.clone(),
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    // One diagnostic, so plain lyrics don't get one on every line
+    if let Some(&first) = untimed.first() {
+        let message = match untimed.len() {
+            1 => "Line has no timestamp".to_string(),
+            n => format!("Line has no timestamp, and nor do {} more after it", n - 1),
+        };
+        parsed.diagnostics.push(Diagnostic {
+            line: first,
+            message,
+        });
+        parsed.diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    }
+
+    let lyrics = &mut parsed.lyrics;
+    lyrics.lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    // A last word without a closing marker runs until the next line
+    for i in 0..lyrics.lines.len() {
+        let end = lyrics.line_end(i);
+        if let Some(word) = lyrics.lines[i].words.last_mut() {
+            if word.end <= word.start {
+                word.end = end.unwrap_or(word.start).max(word.start);
+            }
+        }
+    }
+    parsed
+}
+
+fn apply_tag(lyrics: &mut Lyrics, tag: &str, warn: &mut impl FnMut(String)) {
+    let Some((key, value)) = tag.split_once(':') else {
+        warn(format!("Unrecognized tag '[{}]'", tag));
+        return;
+    };
+    let key = key.trim().to_lowercase();
+    let value = value.trim().to_string();
+
+    match key.as_str() {
+        "ti" => lyrics.title = Some(value),
+        "ar" => lyrics.artist = Some(value),
+        "al" => lyrics.album = Some(value),
+        "length" => match parse_timestamp(&value).or_else(|| value.parse().ok()) {
+            Some(length) => lyrics.length = Some(length),
+            None => warn(format!("Invalid length '{}'", value)),
+        },
+        // Milliseconds, optionally signed
+        "offset" => match value.trim_start_matches('+').parse::<i64>() {
+            Ok(offset) => lyrics.offset = offset as f64 / 1000.0,
+            Err(_) => warn(format!("Invalid offset '{}'", value)),
+        },
+        _ => {
+            lyrics.tags.insert(key, value);
+        }
+    }
+}
+
+// Splits Enhanced LRC `<mm:ss.xx>` markers out of a line's text. Returns the
+// plain text and the timed words, which are empty for ordinary lines.
+fn parse_words(text: &str, warn: &mut impl FnMut(String)) -> (String, Vec<Word>) {
+    let mut words: Vec<Word> = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let marker = rest[start + 1..]
+            .find('>')
+            .and_then(|end| Some((end, parse_timestamp(&rest[start + 1..start + 1 + end])?)));
+        let Some((end, time)) = marker else {
+            // Not a word timing; keep the '<' as text
+            push_text(&mut plain, &mut words, &rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+
+        push_text(&mut plain, &mut words, &rest[..start]);
+        if let Some(previous) = words.last_mut() {
+            if time < previous.start {
+                warn(format!(
+                    "Word timing <{}> goes backwards",
+                    &rest[start + 1..start + 1 + end]
+                ));
+            }
+            previous.end = time;
+        }
+        words.push(Word {
+            start: time,
+            end: time,
+            text: String::new(),
+        });
+        rest = &rest[start + end + 2..];
+    }
+    push_text(&mut plain, &mut words, rest);
+
+    // A trailing marker only closes off the last word
+    words.retain(|word| !word.text.trim().is_empty());
+    (plain.trim().to_string(), words)
+}
+
+fn push_text(plain: &mut String, words: &mut [Word], text: &str) {
+    plain.push_str(text);
+    if let Some(word) = words.last_mut() {
+        word.text.push_str(text);
+    }
+}
+
+// Writes lyrics back out as LRC, with Enhanced LRC word markers for lines
+// that have word timings.
+pub fn to_lrc(lyrics: &Lyrics) -> String {
+    let mut out = String::new();
+    let mut header = |key: &str, value: &str| out.push_str(&format!("[{}:{}]\n", key, value));
+    if let Some(title) = &lyrics.title {
+        header("ti", title);
+    }
+    if let Some(artist) = &lyrics.artist {
+        header("ar", artist);
+    }
+    if let Some(album) = &lyrics.album {
+        header("al", album);
+    }
+    if let Some(length) = lyrics.length {
+        header("length", &format_timestamp(length));
+    }
+    if lyrics.offset != 0.0 {
+        header(
+            "offset",
+            &((lyrics.offset * 1000.0).round() as i64).to_string(),
+        );
+    }
+    for (key, value) in &lyrics.tags {
+        header(key, value);
+    }
+
+    for line in &lyrics.lines {
+        out.push_str(&format!("[{}]", format_timestamp(line.time)));
+        if line.words.is_empty() {
+            out.push_str(&line.text);
+        }
+        for word in &line.words {
+            out.push_str(&format!("<{}>{}", format_timestamp(word.start), word.text));
+        }
+        if let Some(last) = line.words.last() {
+            out.push_str(&format!("<{}>", format_timestamp(last.end)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// `mm:ss.xx`
+pub fn format_timestamp(seconds: f64) -> String {
+    let hundredths = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{:02}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+const MAX_MINUTE_DIGITS: usize = 5;
+
+// `mm:ss`, `mm:ss.xx` (any number of fraction digits) or `mm:ss:xx`, in
+// seconds. Minutes may run past 59, up to `MAX_MINUTE_DIGITS` digits; more
+// than that is no song, and would make for a time too large to wait for.
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (minutes, rest) = text.split_once(':')?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (rest, None),
+    };
+
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(minutes)
+        || minutes.len() > MAX_MINUTE_DIGITS
+        || !digits(seconds)
+        || !fraction.map_or(true, digits)
+    {
+        return None;
+    }
+    // Parsed as one decimal, the way the webview reads timestamps, so both
+    // sides land on the same float for the same line
+    let seconds: f64 = match fraction {
+        Some(fraction) => format!("{}.{}", seconds, fraction).parse().ok()?,
+        None => seconds.parse().ok()?,
+    };
+    if seconds >= 60.0 {
+        return None;
+    }
+    Some(minutes.parse::<f64>().ok()? * 60.0 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lyrics: &Lyrics) -> Vec<f64> {
+        lyrics.lines.iter().map(|line| line.time).collect()
+    }
+
+    fn messages(parsed: &Parsed) -> Vec<(usize, &str)> {
+        parsed
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("01:02"), Some(62.0));
+        assert_eq!(parse_timestamp("01:02.5"), Some(62.5));
+        assert_eq!(parse_timestamp("01:02.50"), Some(62.5));
+        assert_eq!(parse_timestamp("01:02.500"), Some(62.5));
+        assert_eq!(parse_timestamp("01:02:50"), Some(62.5));
+        assert_eq!(parse_timestamp(" 125:00.00 "), Some(7500.0));
+        assert_eq!(parse_timestamp("01:60.00"), None);
+        assert_eq!(parse_timestamp("1:-2"), None);
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("01:02."), None);
+        assert_eq!(parse_timestamp("99999:59.99"), Some(5_999_999.99));
+        assert_eq!(parse_timestamp("100000:00.00"), None);
+        assert_eq!(parse_timestamp(&format!("{}:00.00", "9".repeat(400))), None);
+
+        assert_eq!(format_timestamp(62.5), "01:02.50");
+        assert_eq!(format_timestamp(7500.0), "125:00.00");
+        assert_eq!(format_timestamp(-1.0), "00:00.00");
+        // Rounded, not truncated
+        assert_eq!(format_timestamp(59.999), "01:00.00");
+    }
+
+    #[test]
+    fn lines_are_sorted_and_may_have_several_stamps() {
+        let parsed = parse("[00:10.00]Chorus\n[00:05.00][00:20.00]Verse\n[00:01.00]");
+        assert!(parsed.diagnostics.is_empty());
+        let lyrics = parsed.lyrics;
+        assert_eq!(times(&lyrics), [1.0, 5.0, 10.0, 20.0]);
+        let texts: Vec<&str> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["", "Verse", "Chorus", "Verse"]);
+    }
+
+    #[test]
+    fn header_tags() {
+        let parsed = parse(
+            "\u{feff}[ti: Title ]\n[ar:Artist]\n[al:Album]\n[length: 03:25]\n[by:Someone]\n[00:01.00]Hi",
+        );
+        assert!(parsed.diagnostics.is_empty());
+        let lyrics = parsed.lyrics;
+        assert_eq!(lyrics.title.as_deref(), Some("Title"));
+        assert_eq!(lyrics.artist.as_deref(), Some("Artist"));
+        assert_eq!(lyrics.album.as_deref(), Some("Album"));
+        assert_eq!(lyrics.length, Some(205.0));
+        assert_eq!(lyrics.tags.get("by").map(String::as_str), Some("Someone"));
+        assert_eq!(times(&lyrics), [1.0]);
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse("[offset:+500]").lyrics.offset, 0.5);
+        assert_eq!(parse("[offset:-250]").lyrics.offset, -0.25);
+
+        let parsed = parse("[offset:soon]");
+        assert_eq!(parsed.lyrics.offset, 0.0);
+        assert_eq!(messages(&parsed), [(1, "Invalid offset 'soon'")]);
+
+        // Positive offsets show lines earlier
+        let lyrics = parse("[offset:500]\n[00:01.00]<00:01.00>Hi <00:02.00>there<00:03.00>")
+            .lyrics
+            .without_offset();
+        assert_eq!(lyrics.offset, 0.0);
+        assert_eq!(lyrics.lines[0].time, 0.5);
+        assert_eq!(lyrics.lines[0].words[1].start, 1.5);
+        assert_eq!(lyrics.lines[0].words[1].end, 2.5);
+    }
+
+    #[test]
+    fn word_timings() {
+        let parsed =
+            parse("[00:01.00]<00:01.00>Hello <00:01.50>a<b <00:02.00>world\n[00:04.00]Next");
+        assert!(parsed.diagnostics.is_empty());
+        let line = &parsed.lyrics.lines[0];
+        assert_eq!(line.text, "Hello a<b world");
+        let words: Vec<(f64, f64, &str)> = line
+            .words
+            .iter()
+            .map(|word| (word.start, word.end, word.text.as_str()))
+            .collect();
+        // The last word has no closing marker, so it runs to the next line
+        assert_eq!(
+            words,
+            [
+                (1.0, 1.5, "Hello "),
+                (1.5, 2.0, "a<b "),
+                (2.0, 4.0, "world")
+            ]
+        );
+        assert!(parsed.lyrics.lines[1].words.is_empty());
+    }
+
+    #[test]
+    fn word_timings_follow_repeated_stamps() {
+        let lyrics = parse("[00:01.00][00:11.00]<00:01.00>Hey<00:02.00>").lyrics;
+        assert_eq!(lyrics.lines[1].words[0].start, 11.0);
+        assert_eq!(lyrics.lines[1].words[0].end, 12.0);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let parsed = parse(
+            "[00:01.00]<00:03.00>Back <00:02.00>wards\n\
+             [00:02.00\n\
+             [00:03.00][0x:00]Bad\n\
+             [ar:Artist]Text after a tag\n\
+             [notatag]\n\
+             [00:04.00]Fine",
+        );
+        assert_eq!(
+            messages(&parsed),
+            [
+                (1, "Word timing <00:02.00> goes backwards"),
+                (2, "Unclosed '['"),
+                (3, "Invalid timestamp '[0x:00]'"),
+                (4, "Invalid timestamp '[ar:Artist]'"),
+                (5, "Unrecognized tag '[notatag]'"),
+            ]
+        );
+        // The broken lines are skipped, the rest kept
+        assert_eq!(times(&parsed.lyrics), [1.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn untimed_text_is_reported_once() {
+        let parsed = parse("[00:01.00]One\nplain\n\nmore plain\n[00:02.00]Two\nlast");
+        assert_eq!(
+            messages(&parsed),
+            [(2, "Line has no timestamp, and nor do 2 more after it")]
+        );
+        assert_eq!(times(&parsed.lyrics), [1.0, 2.0]);
+
+        let parsed = parse("[00:01.00]One\n[00:02.0x]Two\nplain");
+        assert_eq!(
+            messages(&parsed),
+            [
+                (2, "Invalid timestamp '[00:02.0x]'"),
+                (3, "Line has no timestamp")
+            ]
+        );
+    }
+
+    #[test]
+    fn to_lrc_round_trips() {
+        let text = "[ti:Title]\n[ar:Artist]\n[length:03:25.00]\n[offset:-120]\n[by:Someone]\n\
+                    [00:01.00]Plain line\n[00:02.50]<00:02.50>Word <00:03.00>timed<00:04.00>\n";
+        let parsed = parse(text);
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(to_lrc(&parsed.lyrics), text);
+        assert_eq!(parse(&to_lrc(&parsed.lyrics)).lyrics, parsed.lyrics);
+    }
+}
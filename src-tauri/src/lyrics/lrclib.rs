@@ -0,0 +1,407 @@
+// Client for https://lrclib.net. The exact `/api/get` lookup needs the album
+// and duration, so its match goes first when we have them, followed by
+// whatever `/api/search` finds. Publishing is anonymous, but each upload
+// needs a token earned by solving a proof-of-work challenge.
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::settings::Settings;
+
+use super::{Candidate, LyricsProvider, LyricsResult, ProviderError, TrackQuery};
+
+pub const DEFAULT_BASE_URL: &str = "https://lrclib.net";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LrclibTrack {
+    pub id: i64,
+    pub track_name: String,
+    pub artist_name: String,
+    pub album_name: Option<String>,
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub instrumental: bool,
+    pub plain_lyrics: Option<String>,
+    pub synced_lyrics: Option<String>,
+}
+
+impl LrclibTrack {
+    fn into_result(self) -> LyricsResult {
+        LyricsResult {
+            synced: self.synced_lyrics.filter(|s| !s.trim().is_empty()),
+            plain: self.plain_lyrics.filter(|s| !s.trim().is_empty()),
+            instrumental: self.instrumental,
+            provider: "lrclib".to_string(),
+            score: None,
+            candidate: None,
+            parsed: None,
+        }
+    }
+
+    // Search results carry the lyrics too, so the candidate keeps them.
+    fn into_candidate(self) -> Candidate {
+        Candidate {
+            provider: "lrclib".to_string(),
+            id: self.id.to_string(),
+            artist: self.artist_name.clone(),
+            title: self.track_name.clone(),
+            album: self.album_name.clone(),
+            duration: self.duration,
+            synced: Some(self.synced_lyrics.is_some()),
+            score: 0.0,
+            lyrics: Some(self.into_result()),
+        }
+    }
+}
+
+// lrclib's challenges usually take a few million tries; well past that, the
+// target is as good as unsolvable
+const MAX_TRIES: u64 = 1 << 28;
+
+// A proof-of-work puzzle from `/api/request-challenge`: find a nonce that
+// makes the SHA-256 of `prefix` followed by the nonce no greater than
+// `target`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Challenge {
+    pub prefix: String,
+    // Hex
+    pub target: String,
+}
+
+impl Challenge {
+    // The `X-Publish-Token` for the challenge. Takes a few seconds of CPU,
+    // so it belongs on a blocking thread.
+    pub fn solve(&self) -> Result<String, String> {
+        self.solve_within(MAX_TRIES)
+    }
+
+    fn solve_within(&self, tries: u64) -> Result<String, String> {
+        let target = decode_hex(&self.target)
+            .filter(|target| target.len() == 32 && target.iter().any(|byte| *byte != 0))
+            .ok_or("lrclib sent a malformed challenge")?;
+        for nonce in 0..tries {
+            let hash = Sha256::new()
+                .chain_update(self.prefix.as_bytes())
+                .chain_update(nonce.to_string().as_bytes())
+                .finalize();
+            if hash.as_slice() <= target.as_slice() {
+                return Ok(format!("{}:{}", self.prefix, nonce));
+            }
+        }
+        Err("lrclib's challenge is too hard to solve; try again later".to_string())
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishRequest {
+    pub track_name: String,
+    pub artist_name: String,
+    pub album_name: String,
+    // Seconds
+    pub duration: f64,
+    pub plain_lyrics: String,
+    pub synced_lyrics: String,
+}
+
+// lrclib's error body
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+pub struct Lrclib {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl Lrclib {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self::with_base_url(client, DEFAULT_BASE_URL)
+    }
+
+    // lrclib.net, or the server the settings name instead.
+    pub fn from_settings(settings: &Settings, client: &reqwest::Client) -> Self {
+        match settings.lrclib_url.as_deref().map(str::trim) {
+            Some(url) if !url.is_empty() => Self::with_base_url(client.clone(), url),
+            _ => Self::new(client.clone()),
+        }
+    }
+
+    // `base_url` is the server root, without `/api`, so a self-hosted
+    // mirror or a test server can stand in for lrclib.net.
+    pub fn with_base_url(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Lrclib {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub async fn get(&self, query: &TrackQuery) -> Result<Option<LrclibTrack>, ProviderError> {
+        let album = query.album.as_deref().filter(|album| !album.is_empty());
+        let (Some(album), Some(duration)) = (album, query.duration) else {
+            return Ok(None);
+        };
+        // lrclib matches durations to the second, give or take two
+        let duration = (duration.round() as i64).to_string();
+
+        let response = self
+            .client
+            .get(format!("{}/api/get", self.base_url))
+            .query(&[
+                ("artist_name", query.artist.as_str()),
+                ("track_name", query.title.as_str()),
+                ("album_name", album),
+                ("duration", duration.as_str()),
+            ])
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> Result<Option<LrclibTrack>, ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/api/get/{}", self.base_url, id))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    pub async fn search_tracks(
+        &self,
+        query: &TrackQuery,
+    ) -> Result<Vec<LrclibTrack>, ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/api/search", self.base_url))
+            .query(&[
+                ("artist_name", query.artist.as_str()),
+                ("track_name", query.title.as_str()),
+            ])
+            .send()
+            .await?;
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    pub async fn request_challenge(&self) -> Result<Challenge, ProviderError> {
+        let response = self
+            .client
+            .post(format!("{}/api/request-challenge", self.base_url))
+            .send()
+            .await?;
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    // `token` is a solved challenge.
+    pub async fn publish(
+        &self,
+        request: &PublishRequest,
+        token: &str,
+    ) -> Result<(), ProviderError> {
+        let response = self
+            .client
+            .post(format!("{}/api/publish", self.base_url))
+            .header("X-Publish-Token", token)
+            .json(request)
+            .send()
+            .await?;
+
+        // Rejections explain themselves, e.g. an expired or wrong token
+        let status = response.status();
+        if status.is_client_error() {
+            let message = response
+                .json::<ApiError>()
+                .await
+                .map_or_else(|_| status.to_string(), |error| error.message);
+            return Err(ProviderError::Fatal(message));
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for Lrclib {
+    fn name(&self) -> &'static str {
+        "lrclib"
+    }
+
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+        let mut tracks: Vec<LrclibTrack> = self.get(query).await?.into_iter().collect();
+        for track in self.search_tracks(query).await? {
+            if !tracks.iter().any(|t| t.id == track.id) {
+                tracks.push(track);
+            }
+        }
+        Ok(tracks
+            .into_iter()
+            .map(LrclibTrack::into_candidate)
+            .collect())
+    }
+
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError> {
+        Ok(self
+            .get_by_id(&candidate.id)
+            .await?
+            .map(LrclibTrack::into_result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response, TestServer};
+    use serde_json::json;
+
+    fn track(id: i64, synced: Option<&str>) -> serde_json::Value {
+        json!({
+            "id": id,
+            "trackName": "Song",
+            "artistName": "Artist",
+            "albumName": "Album",
+            "duration": 200.0,
+            "instrumental": false,
+            "plainLyrics": "Hello",
+            "syncedLyrics": synced,
+        })
+    }
+
+    fn query() -> TrackQuery {
+        TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            album: Some("Album".to_string()),
+            duration: Some(199.6),
+            path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn search_puts_the_exact_match_first_without_repeating_it() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/get" => Response::json(track(2, Some("[00:01.00]Hello"))),
+            "/api/search" => Response::json(json!([track(1, None), track(2, None)])),
+            _ => Response::status(404, json!({})),
+        })
+        .await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), &server.url);
+
+        let candidates = lrclib.search(&query()).await.ok().unwrap();
+        let ids: Vec<&str> = candidates.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["2", "1"]);
+        let lyrics = candidates[0].lyrics.as_ref().unwrap();
+        assert_eq!(lyrics.synced.as_deref(), Some("[00:01.00]Hello"));
+        assert_eq!(candidates[1].synced, Some(false));
+
+        let get = &server.requests()[0];
+        assert_eq!(get.query["album_name"], "Album");
+        assert_eq!(get.query["duration"], "200");
+    }
+
+    #[tokio::test]
+    async fn a_missing_track_is_no_lyrics() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/search" => Response::json(json!([])),
+            _ => Response::status(404, json!({ "message": "Not found" })),
+        })
+        .await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), &server.url);
+
+        assert!(lrclib.search(&query()).await.ok().unwrap().is_empty());
+        let candidate = Candidate {
+            id: "7".to_string(),
+            ..Default::default()
+        };
+        assert!(lrclib.lyrics(&candidate).await.ok().unwrap().is_none());
+        assert_eq!(server.requests().last().unwrap().path, "/api/get/7");
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_worth_retrying_and_bad_requests_are_not() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/get/1" => Response::status(503, json!({})),
+            _ => Response::status(400, json!({})),
+        })
+        .await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), format!("{}/", server.url));
+
+        let candidate = |id: &str| Candidate {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            lrclib.lyrics(&candidate("1")).await,
+            Err(ProviderError::Transient(_))
+        ));
+        assert!(matches!(
+            lrclib.lyrics(&candidate("2")).await,
+            Err(ProviderError::Fatal(_))
+        ));
+    }
+
+    fn challenge(target: &str) -> Challenge {
+        Challenge {
+            prefix: "prefix".to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn solved_challenges_hash_under_the_target() {
+        let target = format!("0f{}", "ff".repeat(31));
+        let token = challenge(&target).solve().unwrap();
+        let (prefix, nonce) = token.split_once(':').unwrap();
+        assert_eq!(prefix, "prefix");
+
+        let hash = Sha256::digest(format!("{}{}", prefix, nonce));
+        assert!(hash.as_slice() <= decode_hex(&target).unwrap().as_slice());
+    }
+
+    #[test]
+    fn malformed_and_unsolvable_challenges_give_up() {
+        for target in [
+            "",
+            "ff",
+            &"00".repeat(32),
+            &"zz".repeat(32),
+            &"ff".repeat(33),
+        ] {
+            assert_eq!(
+                challenge(target).solve(),
+                Err("lrclib sent a malformed challenge".to_string()),
+                "{:?}",
+                target
+            );
+        }
+        let target = format!("{}01", "00".repeat(31));
+        assert!(challenge(&target).solve_within(1000).is_err());
+    }
+}
@@ -0,0 +1,950 @@
+// Lyrics lookup. Each source sits behind the `LyricsProvider` trait and the
+// `LyricsService` in front of them owns the provider chain, retries and
+// caching, so commands (and anything else in the backend) just ask for a
+// track.
+
+pub mod blacklist;
+pub mod cache;
+pub mod editor;
+pub mod export;
+pub mod import;
+mod local;
+pub mod lrc;
+mod lrclib;
+mod musixmatch;
+mod netease;
+pub mod offsets;
+pub mod publish;
+mod qqmusic;
+mod scoring;
+
+use async_trait::async_trait;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::settings::Settings;
+use blacklist::Blacklist;
+use cache::LyricsCache;
+
+pub use local::{sidecar, LocalFolder};
+pub use lrclib::Lrclib;
+pub use musixmatch::Musixmatch;
+pub use netease::Netease;
+pub use qqmusic::QqMusic;
+
+const MAX_RETRIES: u32 = 2;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+// How many of a provider's best candidates are tried before giving up on it
+const MAX_CANDIDATES: usize = 3;
+
+#[derive(Clone, Debug, Default)]
+pub struct TrackQuery {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    // Track length in seconds
+    pub duration: Option<f64>,
+    // The audio file, when the player is playing a local one
+    pub path: Option<PathBuf>,
+}
+
+impl TrackQuery {
+    // Lookups are case and whitespace insensitive, and durations within the
+    // same second count as the same track.
+    fn cache_key(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}",
+            self.artist.trim().to_lowercase(),
+            self.title.trim().to_lowercase(),
+            self.album
+                .as_deref()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase(),
+            self.duration.map(|d| d.round() as i64).unwrap_or_default()
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsResult {
+    pub synced: Option<String>,
+    pub plain: Option<String>,
+    pub instrumental: bool,
+    // Which provider answered
+    pub provider: String,
+    // How well the match fits the track, from 0 to 1
+    pub score: Option<f64>,
+    // The candidate the lyrics came from, as `Candidate::key` has it
+    #[serde(default)]
+    pub candidate: Option<String>,
+    // `synced`, parsed
+    pub parsed: Option<lrc::Lyrics>,
+}
+
+// A track a provider might have lyrics for.
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    pub provider: String,
+    // The provider's own id for the track
+    pub id: String,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    // Seconds
+    pub duration: Option<f64>,
+    // `None` until the lyrics have been fetched, for providers whose search
+    // doesn't say
+    pub synced: Option<bool>,
+    pub score: f64,
+    // Lyrics that came back with the search, saving a second request
+    #[serde(skip)]
+    pub lyrics: Option<LyricsResult>,
+}
+
+impl Candidate {
+    // Identifies the candidate across providers.
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.provider, self.id)
+    }
+}
+
+// What a manual search found, for the user to pick from.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    // The cache id of the track a pick is pinned to
+    pub track_key: String,
+    // Best first. Candidates scoring will reject are kept, with a score of 0.
+    pub candidates: Vec<SearchCandidate>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchCandidate {
+    pub candidate_id: String,
+    #[serde(flatten)]
+    pub candidate: Candidate,
+    pub blacklisted: bool,
+}
+
+// The last search, kept so a pick can be fetched without searching again.
+struct Search {
+    track_key: String,
+    track: TrackQuery,
+    candidates: Vec<Candidate>,
+}
+
+pub enum ProviderError {
+    // Worth trying again: timeouts, dropped connections, 5xx responses
+    Transient(String),
+    Fatal(String),
+}
+
+impl ProviderError {
+    pub fn message(&self) -> &str {
+        match self {
+            ProviderError::Transient(message) | ProviderError::Fatal(message) => message,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        let transient = e.is_timeout()
+            || e.is_connect()
+            || e.status().is_some_and(|status| status.is_server_error());
+        if transient {
+            ProviderError::Transient(e.to_string())
+        } else {
+            ProviderError::Fatal(e.to_string())
+        }
+    }
+}
+
+// Providers other than lrclib hand back a single blob of text, which may or
+// may not carry LRC timestamps.
+pub fn lyrics_from_text(text: String, provider: &str) -> Option<LyricsResult> {
+    if text.trim().is_empty() {
+        return None;
+    }
+    let synced = text.lines().any(|line| {
+        line.trim_start()
+            .strip_prefix('[')
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    });
+    let (synced, plain) = if synced {
+        (Some(text), None)
+    } else {
+        (None, Some(text))
+    };
+    Some(LyricsResult {
+        synced,
+        plain,
+        instrumental: false,
+        provider: provider.to_string(),
+        score: None,
+        candidate: None,
+        parsed: None,
+    })
+}
+
+// For providers with their own timed format (word timings, usually), which
+// gets converted to the shared model and written back out as LRC.
+pub fn lyrics_from_model(lyrics: lrc::Lyrics, provider: &str) -> LyricsResult {
+    let plain = lyrics
+        .lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    LyricsResult {
+        synced: Some(lrc::to_lrc(&lyrics)),
+        plain: Some(plain),
+        instrumental: false,
+        provider: provider.to_string(),
+        score: None,
+        candidate: None,
+        parsed: Some(lyrics),
+    }
+}
+
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Tracks that might match the query, in the provider's own order. The
+    // service does the scoring.
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError>;
+
+    // Only called for candidates that didn't come with lyrics. `Ok(None)`
+    // means the track has none after all.
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Lrclib,
+    Netease,
+    QqMusic,
+    Musixmatch,
+    Local,
+}
+
+impl ProviderKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProviderKind::Lrclib => "lrclib",
+            ProviderKind::Netease => "netease",
+            ProviderKind::QqMusic => "qqmusic",
+            ProviderKind::Musixmatch => "musixmatch",
+            ProviderKind::Local => "local",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchStrategy {
+    // Ask each provider in order
+    #[default]
+    Sequential,
+    // Ask every provider at once
+    Race,
+}
+
+// Builds the providers named in the settings, in the order they're listed.
+pub fn build_chain(settings: &Settings, client: &reqwest::Client) -> ProviderChain {
+    let mut providers: Vec<Arc<dyn LyricsProvider>> = Vec::new();
+    let mut local = None;
+    for kind in &settings.lyrics_providers {
+        if providers.iter().any(|p| p.name() == kind.name()) {
+            continue;
+        }
+        providers.push(match kind {
+            ProviderKind::Lrclib => Arc::new(Lrclib::from_settings(settings, client)),
+            ProviderKind::Netease => Arc::new(Netease::new(client.clone())),
+            ProviderKind::QqMusic => Arc::new(QqMusic::new(client.clone())),
+            ProviderKind::Musixmatch => Arc::new(Musixmatch::new(
+                client.clone(),
+                settings.musixmatch_token.clone(),
+            )),
+            // Kept out of the chain; local files are always looked at first
+            ProviderKind::Local => {
+                local = Some(Arc::new(LocalFolder::new(settings.lyrics_folders.clone())));
+                continue;
+            }
+        });
+    }
+    ProviderChain {
+        local,
+        providers,
+        strategy: settings.lyrics_strategy,
+        duration_tolerance: settings.duration_tolerance,
+        offline: settings.offline,
+    }
+}
+
+#[derive(Clone)]
+pub struct ProviderChain {
+    // Sidecar files and the lyrics folders, when enabled. Checked before the
+    // cache and the network, so curated lyrics always win.
+    pub local: Option<Arc<LocalFolder>>,
+    // Network providers, in the order they're tried
+    pub providers: Vec<Arc<dyn LyricsProvider>>,
+    pub strategy: FetchStrategy,
+    // Seconds a candidate's duration may be off by
+    pub duration_tolerance: f64,
+    // Only pinned lyrics, local files and the cache are looked at
+    pub offline: bool,
+}
+
+impl ProviderChain {
+    // Local files first, then the network providers in order.
+    fn all(&self) -> impl Iterator<Item = &dyn LyricsProvider> {
+        let local = self
+            .local
+            .iter()
+            .map(|local| local.as_ref() as &dyn LyricsProvider);
+        local.chain(self.providers.iter().map(|provider| provider.as_ref()))
+    }
+}
+
+pub struct LyricsService {
+    chain: RwLock<ProviderChain>,
+    cache: LyricsCache,
+    blacklist: Blacklist,
+    last_search: Mutex<Option<Search>>,
+}
+
+impl LyricsService {
+    pub fn new(chain: ProviderChain, cache: LyricsCache, blacklist: Blacklist) -> Self {
+        LyricsService {
+            chain: RwLock::new(chain),
+            cache,
+            blacklist,
+            last_search: Mutex::new(None),
+        }
+    }
+
+    pub fn cache(&self) -> &LyricsCache {
+        &self.cache
+    }
+
+    // Swaps in a new chain, e.g. after the settings change. Lookups already
+    // running finish against the old one.
+    pub fn configure(&self, chain: ProviderChain) {
+        if let Ok(mut current) = self.chain.write() {
+            *current = chain;
+        }
+    }
+
+    pub async fn fetch(&self, query: &TrackQuery) -> Result<Option<LyricsResult>, String> {
+        if let Some(result) = self.lookup_offline(query).await? {
+            return Ok(Some(result));
+        }
+        self.fetch_remote(query).await
+    }
+
+    // Lyrics already on this machine: pinned ones, local files, then the
+    // rest of the cache.
+    pub async fn lookup_offline(&self, query: &TrackQuery) -> Result<Option<LyricsResult>, String> {
+        if let Some(result) = self.cache.pinned(query).await {
+            return Ok(Some(result));
+        }
+        let chain = self.chain()?;
+        // Local files aren't cached; they're cheap to read and may be edited
+        if let Some(local) = &chain.local {
+            let blacklisted = self.blacklist.get(&cache::entry_id(query)).await;
+            match fetch_from(local.as_ref(), query, &chain, &blacklisted).await {
+                Ok(Some(result)) => return Ok(Some(result)),
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to read local lyrics: {}", e),
+            }
+        }
+        Ok(self.cache.get(query).await)
+    }
+
+    // Asks the network providers, skipping anything local, and caches what
+    // they find.
+    pub async fn fetch_remote(&self, query: &TrackQuery) -> Result<Option<LyricsResult>, String> {
+        let chain = self.chain()?;
+        if chain.offline {
+            return Err(
+                "Offline mode is on, and there are no saved lyrics for this track".to_string(),
+            );
+        }
+        let blacklisted = self.blacklist.get(&cache::entry_id(query)).await;
+        let result = match chain.strategy {
+            FetchStrategy::Sequential => fetch_sequential(&chain, query, &blacklisted).await?,
+            FetchStrategy::Race => fetch_race(&chain, query, &blacklisted).await?,
+        };
+        if let Some(result) = &result {
+            // Losing the cache only costs a refetch next time
+            if let Err(e) = self.cache.put(query, result).await {
+                eprintln!("Failed to cache lyrics: {}", e);
+            }
+        }
+        Ok(result)
+    }
+
+    // Every candidate each provider has for `query`, scored against it, for
+    // the user to pick lyrics for `track` from. The two differ when the
+    // user reworded the search.
+    pub async fn search(
+        &self,
+        track: TrackQuery,
+        query: &TrackQuery,
+    ) -> Result<SearchResults, String> {
+        let chain = self.chain()?;
+        if chain.offline {
+            return Err("Searching for lyrics needs the network; offline mode is on".to_string());
+        }
+        let mut pending: FuturesUnordered<_> = chain
+            .all()
+            .enumerate()
+            .map(|(i, provider)| async move {
+                (i, with_retries(provider, || provider.search(query)).await)
+            })
+            .collect();
+
+        let mut found = Vec::new();
+        let mut errors = Vec::new();
+        while let Some((i, outcome)) = pending.next().await {
+            match outcome {
+                Ok(candidates) => found.push((i, candidates)),
+                Err(e) => errors.push(e),
+            }
+        }
+        if found.is_empty() && !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        // Chain order, so equal scores rank the way automatic lookups would
+        found.sort_by_key(|(i, _)| *i);
+
+        let mut candidates: Vec<Candidate> = found
+            .into_iter()
+            .flat_map(|(_, candidates)| candidates)
+            .map(|mut candidate| {
+                candidate.score =
+                    scoring::score(query, &candidate, chain.duration_tolerance).unwrap_or(0.0);
+                if let Some(lyrics) = &candidate.lyrics {
+                    candidate.synced.get_or_insert(lyrics.synced.is_some());
+                }
+                candidate
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let track_key = cache::entry_id(&track);
+        let blacklisted = self.blacklist.get(&track_key).await;
+        let results = SearchResults {
+            track_key: track_key.clone(),
+            candidates: candidates
+                .iter()
+                .map(|candidate| SearchCandidate {
+                    candidate_id: candidate.key(),
+                    candidate: candidate.clone(),
+                    blacklisted: blacklisted.contains(&candidate.key()),
+                })
+                .collect(),
+        };
+        *self
+            .last_search
+            .lock()
+            .map_err(|_| "Failed to lock search results")? = Some(Search {
+            track_key,
+            track,
+            candidates,
+        });
+        Ok(results)
+    }
+
+    // Fetches lyrics for a candidate from the last search and pins them to
+    // the track it was for, so it's used from now on.
+    pub async fn pin(&self, track_key: &str, candidate_id: &str) -> Result<LyricsResult, String> {
+        let (track, candidate) = {
+            let search = self
+                .last_search
+                .lock()
+                .map_err(|_| "Failed to lock search results")?;
+            let candidate = search
+                .as_ref()
+                .filter(|search| search.track_key == track_key)
+                .and_then(|search| {
+                    let candidate = search
+                        .candidates
+                        .iter()
+                        .find(|candidate| candidate.key() == candidate_id)?;
+                    Some((search.track.clone(), candidate.clone()))
+                });
+            candidate.ok_or("That candidate is no longer available; search again")?
+        };
+
+        let chain = self.chain()?;
+        let provider = chain
+            .all()
+            .find(|provider| provider.name() == candidate.provider)
+            .ok_or_else(|| format!("{} is no longer enabled", candidate.provider))?;
+        let lyrics = match candidate.lyrics.clone() {
+            Some(lyrics) => Some(lyrics),
+            None => with_retries(provider, || provider.lyrics(&candidate)).await?,
+        };
+        let lyrics = lyrics
+            .map(|lyrics| from_candidate(lyrics, &candidate))
+            .ok_or("No lyrics for that candidate")?;
+        self.cache.pin(&track, &lyrics).await?;
+        Ok(lyrics)
+    }
+
+    // Keeps a candidate from being picked for the track again. Lyrics cached
+    // from it are dropped, so the next lookup finds something else.
+    pub async fn blacklist(&self, track_key: &str, candidate_id: &str) -> Result<(), String> {
+        self.blacklist.add(track_key, candidate_id).await?;
+        let cached = self.cache.inspect(track_key).await.ok();
+        if cached.is_some_and(|entry| entry.lyrics.candidate.as_deref() == Some(candidate_id)) {
+            self.cache.purge(Some(track_key)).await?;
+        }
+        Ok(())
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.chain().is_ok_and(|chain| chain.offline)
+    }
+
+    fn chain(&self) -> Result<ProviderChain, String> {
+        Ok(self
+            .chain
+            .read()
+            .map_err(|_| "Failed to lock provider chain")?
+            .clone())
+    }
+}
+
+// Synced lyrics win outright. Failing that, the first plain-only answer is
+// kept while the rest of the chain gets a chance to do better.
+async fn fetch_sequential(
+    chain: &ProviderChain,
+    query: &TrackQuery,
+    blacklisted: &BTreeSet<String>,
+) -> Result<Option<LyricsResult>, String> {
+    let mut fallback = None;
+    let mut errors = Vec::new();
+
+    for provider in &chain.providers {
+        match fetch_from(provider.as_ref(), query, chain, blacklisted).await {
+            Ok(Some(result)) if result.synced.is_some() => return Ok(Some(result)),
+            Ok(Some(result)) => {
+                fallback.get_or_insert(result);
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    finish(fallback, errors, chain.providers.len())
+}
+
+// Same preference as `fetch_sequential`, but every provider is asked at once
+// and the first synced answer to arrive is used. Plain-only answers fall
+// back to chain order.
+async fn fetch_race(
+    chain: &ProviderChain,
+    query: &TrackQuery,
+    blacklisted: &BTreeSet<String>,
+) -> Result<Option<LyricsResult>, String> {
+    let mut pending: FuturesUnordered<_> = chain
+        .providers
+        .iter()
+        .enumerate()
+        .map(|(i, provider)| async move {
+            (
+                i,
+                fetch_from(provider.as_ref(), query, chain, blacklisted).await,
+            )
+        })
+        .collect();
+    let mut fallback: Option<(usize, LyricsResult)> = None;
+    let mut errors = Vec::new();
+
+    while let Some((i, outcome)) = pending.next().await {
+        match outcome {
+            Ok(Some(result)) if result.synced.is_some() => return Ok(Some(result)),
+            Ok(Some(result)) => {
+                if fallback.as_ref().map_or(true, |(j, _)| i < *j) {
+                    fallback = Some((i, result));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    finish(
+        fallback.map(|(_, result)| result),
+        errors,
+        chain.providers.len(),
+    )
+}
+
+// Errors only surface when every provider failed; a clean miss from any of
+// them means the track really has no lyrics we can find.
+fn finish(
+    result: Option<LyricsResult>,
+    errors: Vec<String>,
+    providers: usize,
+) -> Result<Option<LyricsResult>, String> {
+    if result.is_none() && !errors.is_empty() && errors.len() == providers {
+        return Err(errors.join("; "));
+    }
+    Ok(result)
+}
+
+// Scores a provider's candidates, drops the ones that can't be the track or
+// that the user blacklisted for it, and fetches lyrics for the best few
+// until one of them is synced.
+async fn fetch_from(
+    provider: &dyn LyricsProvider,
+    query: &TrackQuery,
+    chain: &ProviderChain,
+    blacklisted: &BTreeSet<String>,
+) -> Result<Option<LyricsResult>, String> {
+    let mut candidates = with_retries(provider, || provider.search(query)).await?;
+    candidates.retain(|candidate| !blacklisted.contains(&candidate.key()));
+    let mut fallback = None;
+    let mut error = None;
+
+    for candidate in rank(candidates, query, chain.duration_tolerance)
+        .into_iter()
+        .take(MAX_CANDIDATES)
+    {
+        let lyrics = match candidate.lyrics.clone() {
+            Some(lyrics) => Some(lyrics),
+            None => match with_retries(provider, || provider.lyrics(&candidate)).await {
+                Ok(lyrics) => lyrics,
+                // The next candidate may still have lyrics
+                Err(e) => {
+                    eprintln!("Failed to fetch lyrics for {}: {}", candidate.key(), e);
+                    error = Some(e);
+                    continue;
+                }
+            },
+        };
+        let Some(lyrics) = lyrics else {
+            continue;
+        };
+        let lyrics = from_candidate(lyrics, &candidate);
+        if lyrics.synced.is_some() || lyrics.instrumental {
+            return Ok(Some(lyrics));
+        }
+        fallback.get_or_insert(lyrics);
+    }
+    // A provider that couldn't fetch any of them failed, rather than missed
+    match (fallback, error) {
+        (None, Some(e)) => Err(e),
+        (fallback, _) => Ok(fallback),
+    }
+}
+
+// Marks lyrics with the candidate they came from, parsing them if the
+// provider didn't.
+fn from_candidate(mut lyrics: LyricsResult, candidate: &Candidate) -> LyricsResult {
+    lyrics.score = Some(candidate.score);
+    lyrics.candidate = Some(candidate.key());
+    if lyrics.parsed.is_none() {
+        lyrics.parsed = lyrics
+            .synced
+            .as_deref()
+            .map(|synced| lrc::parse(synced).lyrics);
+    }
+    lyrics
+}
+
+// Best first, without the rejected ones.
+fn rank(candidates: Vec<Candidate>, query: &TrackQuery, tolerance: f64) -> Vec<Candidate> {
+    let mut ranked: Vec<Candidate> = candidates
+        .into_iter()
+        .filter_map(|mut candidate| {
+            candidate.score = scoring::score(query, &candidate, tolerance)?;
+            Some(candidate)
+        })
+        .collect();
+    // Stable, so equal scores keep the provider's order
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked
+}
+
+async fn with_retries<T, F, Fut>(provider: &dyn LyricsProvider, request: F) -> Result<T, String>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, ProviderError>>,
+{
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut attempt = 0;
+
+    loop {
+        match request().await {
+            Ok(value) => return Ok(value),
+            Err(ProviderError::Transient(_)) if attempt < MAX_RETRIES => {
+                attempt += 1;
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "{} request failed: {}",
+                    provider.name(),
+                    e.message()
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    enum Answer {
+        Synced,
+        Plain,
+        Nothing,
+        Fails,
+        // Two candidates; the first one's lyrics fail to load
+        Flaky,
+        // Every candidate's lyrics fail to load
+        Broken,
+    }
+
+    // A provider with a candidate for any track, answering after `delay`
+    // milliseconds.
+    struct Fake {
+        name: &'static str,
+        answer: Answer,
+        delay: u64,
+    }
+
+    #[async_trait]
+    impl LyricsProvider for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+            tokio::time::sleep(Duration::from_millis(self.delay)).await;
+            if let Answer::Fails = self.answer {
+                return Err(ProviderError::Fatal("down".to_string()));
+            }
+            let count = if let Answer::Flaky | Answer::Broken = self.answer {
+                2
+            } else {
+                1
+            };
+            Ok((1..=count)
+                .map(|id| Candidate {
+                    provider: self.name.to_string(),
+                    id: id.to_string(),
+                    artist: query.artist.clone(),
+                    title: query.title.clone(),
+                    ..Default::default()
+                })
+                .collect())
+        }
+
+        async fn lyrics(
+            &self,
+            candidate: &Candidate,
+        ) -> Result<Option<LyricsResult>, ProviderError> {
+            Ok(match self.answer {
+                Answer::Flaky if candidate.id == "1" => {
+                    return Err(ProviderError::Fatal("gone".to_string()))
+                }
+                Answer::Broken => return Err(ProviderError::Fatal("gone".to_string())),
+                Answer::Synced | Answer::Flaky => {
+                    lyrics_from_text("[00:01.00]Hello".to_string(), self.name)
+                }
+                Answer::Plain => lyrics_from_text("Hello".to_string(), self.name),
+                Answer::Nothing | Answer::Fails => None,
+            })
+        }
+    }
+
+    fn chain(strategy: FetchStrategy, providers: &[(&'static str, Answer, u64)]) -> ProviderChain {
+        ProviderChain {
+            local: None,
+            providers: providers
+                .iter()
+                .map(|&(name, answer, delay)| {
+                    Arc::new(Fake {
+                        name,
+                        answer,
+                        delay,
+                    }) as Arc<dyn LyricsProvider>
+                })
+                .collect(),
+            strategy,
+            duration_tolerance: 3.0,
+            offline: false,
+        }
+    }
+
+    fn query() -> TrackQuery {
+        TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn fetch(chain: &ProviderChain) -> Result<Option<LyricsResult>, String> {
+        fetch_blacklisted(chain, &[]).await
+    }
+
+    async fn fetch_blacklisted(
+        chain: &ProviderChain,
+        blacklisted: &[&str],
+    ) -> Result<Option<LyricsResult>, String> {
+        let blacklisted = blacklisted.iter().map(|key| key.to_string()).collect();
+        match chain.strategy {
+            FetchStrategy::Sequential => fetch_sequential(chain, &query(), &blacklisted).await,
+            FetchStrategy::Race => fetch_race(chain, &query(), &blacklisted).await,
+        }
+    }
+
+    fn provider(result: Result<Option<LyricsResult>, String>) -> Option<String> {
+        result.unwrap().map(|lyrics| lyrics.provider)
+    }
+
+    #[tokio::test]
+    async fn sequential_keeps_looking_for_synced_lyrics() {
+        use Answer::*;
+        let chain = chain(
+            FetchStrategy::Sequential,
+            &[("a", Plain, 0), ("b", Synced, 0)],
+        );
+        let result = fetch(&chain).await.unwrap().unwrap();
+        assert_eq!(result.provider, "b");
+        assert_eq!(result.candidate.as_deref(), Some("b:1"));
+        assert_eq!(result.parsed.unwrap().lines[0].text, "Hello");
+    }
+
+    #[tokio::test]
+    async fn sequential_falls_back_to_the_first_plain_lyrics() {
+        use Answer::*;
+        let chain = chain(
+            FetchStrategy::Sequential,
+            &[("a", Nothing, 0), ("b", Plain, 0), ("c", Plain, 0)],
+        );
+        assert_eq!(provider(fetch(&chain).await).as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn blacklisted_candidates_are_skipped() {
+        use Answer::*;
+        let chain = chain(
+            FetchStrategy::Sequential,
+            &[("a", Synced, 0), ("b", Synced, 0)],
+        );
+        let result = fetch_blacklisted(&chain, &["a:1"]).await;
+        assert_eq!(provider(result).as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn errors_only_surface_when_every_provider_fails() {
+        use Answer::*;
+        for strategy in [FetchStrategy::Sequential, FetchStrategy::Race] {
+            let missed = chain(strategy, &[("a", Fails, 0), ("b", Nothing, 0)]);
+            assert_eq!(provider(fetch(&missed).await), None);
+
+            let failed = chain(strategy, &[("a", Fails, 0), ("b", Fails, 0)]);
+            let error = fetch(&failed).await.unwrap_err();
+            assert!(error.contains("a request failed: down"), "{}", error);
+            assert!(error.contains("b request failed: down"), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_candidate_moves_on_to_the_next() {
+        use Answer::*;
+        let chain = chain(FetchStrategy::Sequential, &[("a", Flaky, 0)]);
+        let result = fetch(&chain).await.unwrap().unwrap();
+        assert_eq!(result.candidate.as_deref(), Some("a:2"));
+    }
+
+    #[tokio::test]
+    async fn a_provider_whose_candidates_all_fail_has_failed() {
+        use Answer::*;
+        let broken = chain(FetchStrategy::Sequential, &[("a", Broken, 0)]);
+        let error = fetch(&broken).await.unwrap_err();
+        assert!(error.contains("gone"), "{}", error);
+
+        let fallback = chain(
+            FetchStrategy::Sequential,
+            &[("a", Broken, 0), ("b", Plain, 0)],
+        );
+        assert_eq!(provider(fetch(&fallback).await).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn rank_drops_rejected_candidates_and_orders_the_rest() {
+        let query = TrackQuery {
+            duration: Some(200.0),
+            ..query()
+        };
+        let candidate = |id: &str, title: &str, duration: f64| Candidate {
+            id: id.to_string(),
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            duration: Some(duration),
+            ..Default::default()
+        };
+        let ranked = rank(
+            vec![
+                candidate("remix", "Song (Remix)", 200.0),
+                candidate("live", "Song", 260.0),
+                candidate("first", "Song", 201.0),
+                candidate("second", "Song", 201.0),
+            ],
+            &query,
+            3.0,
+        );
+        let ids: Vec<&str> = ranked.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second", "remix"]);
+        assert!(ranked.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[tokio::test]
+    async fn race_takes_the_first_synced_answer() {
+        use Answer::*;
+        let chain = chain(
+            FetchStrategy::Race,
+            &[
+                ("slow", Synced, 200),
+                ("plain", Plain, 0),
+                ("fast", Synced, 20),
+            ],
+        );
+        assert_eq!(provider(fetch(&chain).await).as_deref(), Some("fast"));
+    }
+
+    #[tokio::test]
+    async fn race_falls_back_in_chain_order() {
+        use Answer::*;
+        let chain = chain(
+            FetchStrategy::Race,
+            &[
+                ("slow", Plain, 100),
+                ("fast", Plain, 0),
+                ("none", Nothing, 0),
+            ],
+        );
+        assert_eq!(provider(fetch(&chain).await).as_deref(), Some("slow"));
+    }
+}
@@ -0,0 +1,502 @@
+// Client for Musixmatch's desktop API. Every call needs a user token; one can
+// be set in the settings, otherwise an anonymous token is requested and kept
+// until the API stops accepting it. Tracks with "richsync" come with word
+// timings, which are preferred over the line-synced subtitles.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use super::lrc::{Line, Lyrics, Word};
+use super::{
+    lyrics_from_model, lyrics_from_text, Candidate, LyricsProvider, LyricsResult, ProviderError,
+    TrackQuery,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://apic-desktop.musixmatch.com";
+
+const APP_ID: &str = "web-desktop-app-v1.0";
+
+// Sub-responses of `macro.subtitles.get`
+const MATCHER: &str = "/message/body/macro_calls/matcher.track.get/message";
+const SUBTITLES: &str = "/message/body/macro_calls/track.subtitles.get/message";
+const LYRICS: &str = "/message/body/macro_calls/track.lyrics.get/message";
+
+pub struct Musixmatch {
+    client: reqwest::Client,
+    base_url: String,
+    configured_token: Option<String>,
+    token: Mutex<Option<String>>,
+}
+
+impl Musixmatch {
+    pub fn new(client: reqwest::Client, token: Option<String>) -> Self {
+        Self::with_base_url(client, DEFAULT_BASE_URL, token)
+    }
+
+    // Like `new`, against another host; `/ws/1.1/` is added to `base_url`.
+    pub fn with_base_url(
+        client: reqwest::Client,
+        base_url: impl Into<String>,
+        token: Option<String>,
+    ) -> Self {
+        let token = token.filter(|token| !token.trim().is_empty());
+        Musixmatch {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: Mutex::new(token.clone()),
+            configured_token: token,
+        }
+    }
+
+    async fn get(&self, method: &str, params: &[(&str, &str)]) -> Result<Value, ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/ws/1.1/{}", self.base_url, method))
+            .header(reqwest::header::COOKIE, "x-mxm-token-guid=")
+            .query(&[("format", "json"), ("app_id", APP_ID)])
+            .query(params)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    async fn user_token(&self) -> Result<String, ProviderError> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+
+        let response = self.get("token.get", &[("user_language", "en")]).await?;
+        let fresh = response
+            .pointer("/message/body/user_token")
+            .and_then(Value::as_str)
+            // Handed out while the API wants a captcha solved
+            .filter(|fresh| !fresh.is_empty() && !fresh.starts_with("UpgradeOnly"))
+            .ok_or_else(|| ProviderError::Transient("No user token available".to_string()))?
+            .to_string();
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    // A method call with the user token attached. Statuses come back in the
+    // body rather than as HTTP errors.
+    async fn call(&self, method: &str, params: &[(&str, &str)]) -> Result<Value, ProviderError> {
+        let token = self.user_token().await?;
+        let mut params = params.to_vec();
+        params.push(("usertoken", token.as_str()));
+        let response = self.get(method, &params).await?;
+
+        match status_at(&response, "/message") {
+            // The token expired or was revoked; drop it so the retry asks
+            // for a new one
+            Some(401) => {
+                if self.configured_token.is_none() {
+                    *self.token.lock().await = None;
+                }
+                Err(ProviderError::Transient("User token rejected".to_string()))
+            }
+            Some(200) | None => Ok(response),
+            Some(status) => Err(ProviderError::Fatal(format!("Status {}", status))),
+        }
+    }
+
+    // Musixmatch matches the track itself and answers with the lyrics in the
+    // same response, so there's at most one candidate.
+    pub async fn matched(&self, query: &TrackQuery) -> Result<Option<Candidate>, ProviderError> {
+        let duration = query
+            .duration
+            .map(|d| (d.round() as i64).to_string())
+            .unwrap_or_default();
+        let response = self
+            .call(
+                "macro.subtitles.get",
+                &[
+                    ("namespace", "lyrics_richsynched"),
+                    ("subtitle_format", "lrc"),
+                    ("q_artist", query.artist.as_str()),
+                    ("q_track", query.title.as_str()),
+                    ("q_album", query.album.as_deref().unwrap_or_default()),
+                    ("q_duration", duration.as_str()),
+                ],
+            )
+            .await?;
+
+        if status_at(&response, MATCHER) != Some(200) {
+            return Ok(None);
+        }
+        let track = |field: &str| response.pointer(&format!("{}/body/track/{}", MATCHER, field));
+        let Some(id) = track("commontrack_id").and_then(Value::as_i64) else {
+            return Ok(None);
+        };
+
+        let instrumental = track("instrumental").and_then(Value::as_i64) == Some(1);
+        let has_richsync = track("has_richsync").and_then(Value::as_i64) == Some(1);
+        let richsync = if has_richsync && !instrumental {
+            self.richsync(&id.to_string()).await
+        } else {
+            None
+        };
+
+        let lyrics = if instrumental {
+            Some(LyricsResult {
+                instrumental: true,
+                provider: self.name().to_string(),
+                ..Default::default()
+            })
+        } else if let Some(richsync) = richsync {
+            Some(lyrics_from_model(richsync, self.name()))
+        } else {
+            self.lyrics_from(
+                text_at(
+                    &response,
+                    &format!("{}/body/subtitle_list/0/subtitle/subtitle_body", SUBTITLES),
+                ),
+                text_at(&response, &format!("{}/body/lyrics/lyrics_body", LYRICS)),
+            )
+        };
+        let text = |field: &str| track(field).and_then(Value::as_str).map(str::to_string);
+
+        Ok(Some(Candidate {
+            provider: self.name().to_string(),
+            id: id.to_string(),
+            artist: text("artist_name").unwrap_or_default(),
+            title: text("track_name").unwrap_or_default(),
+            album: text("album_name"),
+            duration: track("track_length").and_then(Value::as_f64),
+            synced: Some(track("has_subtitles").and_then(Value::as_i64) == Some(1)),
+            score: 0.0,
+            lyrics,
+        }))
+    }
+
+    // Word-timed lyrics. Missing richsync isn't an error; the caller falls
+    // back to line timings.
+    pub async fn richsync(&self, id: &str) -> Option<Lyrics> {
+        let response = self
+            .call("track.richsync.get", &[("commontrack_id", id)])
+            .await
+            .ok()?;
+        parse_richsync(&text_at(&response, "/message/body/richsync/richsync_body")?)
+    }
+
+    pub async fn track_lyrics(&self, id: &str) -> Result<Option<LyricsResult>, ProviderError> {
+        if let Some(richsync) = self.richsync(id).await {
+            return Ok(Some(lyrics_from_model(richsync, self.name())));
+        }
+
+        let subtitles = self
+            .call(
+                "track.subtitles.get",
+                &[("commontrack_id", id), ("subtitle_format", "lrc")],
+            )
+            .await?;
+        let synced = text_at(
+            &subtitles,
+            "/message/body/subtitle_list/0/subtitle/subtitle_body",
+        );
+        if synced.is_some() {
+            return Ok(self.lyrics_from(synced, None));
+        }
+
+        let lyrics = self
+            .call("track.lyrics.get", &[("commontrack_id", id)])
+            .await?;
+        Ok(self.lyrics_from(None, text_at(&lyrics, "/message/body/lyrics/lyrics_body")))
+    }
+
+    fn lyrics_from(&self, synced: Option<String>, plain: Option<String>) -> Option<LyricsResult> {
+        synced
+            .and_then(|synced| lyrics_from_text(synced, self.name()))
+            .or_else(|| plain.and_then(|plain| lyrics_from_text(plain, self.name())))
+    }
+}
+
+#[derive(Deserialize)]
+struct RichsyncLine {
+    // Line start and end, in seconds
+    ts: f64,
+    te: f64,
+    #[serde(default)]
+    l: Vec<RichsyncWord>,
+}
+
+#[derive(Deserialize)]
+struct RichsyncWord {
+    c: String,
+    // Seconds from the start of the line
+    o: f64,
+}
+
+// The body is itself JSON: lines with their words, where the spaces between
+// words come through as words of their own.
+fn parse_richsync(body: &str) -> Option<Lyrics> {
+    let lines: Vec<RichsyncLine> = serde_json::from_str(body).ok()?;
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .map(|line| {
+            let mut words: Vec<Word> = Vec::new();
+            for word in line.l {
+                let start = line.ts + word.o;
+                if let Some(previous) = words.last_mut() {
+                    // A word ends where the space after it begins
+                    if !previous.text.ends_with(char::is_whitespace) {
+                        previous.end = start;
+                    }
+                    if word.c.trim().is_empty() {
+                        previous.text.push_str(&word.c);
+                        continue;
+                    }
+                } else if word.c.trim().is_empty() {
+                    continue;
+                }
+                words.push(Word {
+                    start,
+                    end: line.te,
+                    text: word.c,
+                });
+            }
+
+            let text: String = words.iter().map(|word| word.text.as_str()).collect();
+            Line {
+                time: line.ts,
+                text: text.trim().to_string(),
+                words,
+            }
+        })
+        .collect();
+    (!lines.is_empty()).then(|| Lyrics {
+        lines,
+        ..Default::default()
+    })
+}
+
+fn status_at(value: &Value, path: &str) -> Option<i64> {
+    value
+        .pointer(&format!("{}/header/status_code", path))
+        .and_then(Value::as_i64)
+}
+
+fn text_at(value: &Value, path: &str) -> Option<String> {
+    value
+        .pointer(path)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[async_trait]
+impl LyricsProvider for Musixmatch {
+    fn name(&self) -> &'static str {
+        "musixmatch"
+    }
+
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+        Ok(self.matched(query).await?.into_iter().collect())
+    }
+
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError> {
+        self.track_lyrics(&candidate.id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+    use crate::test_server::{self, Response, TestServer};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn message(status: i64, body: Value) -> Value {
+        json!({ "message": { "header": { "status_code": status }, "body": body } })
+    }
+
+    fn matched() -> Value {
+        message(
+            200,
+            json!({ "macro_calls": {
+                "matcher.track.get": message(200, json!({ "track": {
+                    "commontrack_id": 9,
+                    "track_name": "Song",
+                    "artist_name": "Artist",
+                    "album_name": "Album",
+                    "track_length": 200,
+                    "has_subtitles": 1,
+                    "has_richsync": 0,
+                    "instrumental": 0,
+                } })),
+                "track.subtitles.get": message(200, json!({ "subtitle_list": [
+                    { "subtitle": { "subtitle_body": "[00:01.00]Hello" } }
+                ] })),
+            } }),
+        )
+    }
+
+    fn query() -> TrackQuery {
+        TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn the_match_comes_with_its_lyrics() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/ws/1.1/token.get" => Response::json(message(200, json!({ "user_token": "tok" }))),
+            _ => Response::json(matched()),
+        })
+        .await;
+        let musixmatch = Musixmatch::with_base_url(test_server::client(), &server.url, None);
+
+        let candidates = musixmatch.search(&query()).await.ok().unwrap();
+        assert_eq!(candidates.len(), 1);
+        let candidate = &candidates[0];
+        assert_eq!(candidate.id, "9");
+        assert_eq!(candidate.duration, Some(200.0));
+        assert_eq!(candidate.synced, Some(true));
+        let lyrics = candidate.lyrics.as_ref().unwrap();
+        assert_eq!(lyrics.synced.as_deref(), Some("[00:01.00]Hello"));
+
+        // The token is asked for once and sent with every call after
+        musixmatch.search(&query()).await.ok().unwrap();
+        let requests = server.requests();
+        let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/ws/1.1/token.get",
+                "/ws/1.1/macro.subtitles.get",
+                "/ws/1.1/macro.subtitles.get"
+            ]
+        );
+        assert_eq!(requests[1].query["usertoken"], "tok");
+        assert_eq!(requests[1].query["q_track"], "Song");
+    }
+
+    #[tokio::test]
+    async fn no_match_is_no_candidate() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/ws/1.1/token.get" => Response::json(message(200, json!({ "user_token": "tok" }))),
+            _ => Response::json(message(
+                200,
+                json!({ "macro_calls": { "matcher.track.get": message(404, json!({})) } }),
+            )),
+        })
+        .await;
+        let musixmatch = Musixmatch::with_base_url(test_server::client(), &server.url, None);
+        assert!(musixmatch.search(&query()).await.ok().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_rejected_token_is_replaced() {
+        let tokens = Arc::new(AtomicUsize::new(0));
+        let issued = tokens.clone();
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/ws/1.1/token.get" => {
+                let n = issued.fetch_add(1, Ordering::SeqCst);
+                Response::json(message(200, json!({ "user_token": format!("tok{}", n) })))
+            }
+            _ if request.query["usertoken"] == "tok0" => Response::json(message(401, json!({}))),
+            _ => Response::json(matched()),
+        })
+        .await;
+        let musixmatch = Musixmatch::with_base_url(test_server::client(), &server.url, None);
+
+        assert!(matches!(
+            musixmatch.search(&query()).await,
+            Err(ProviderError::Transient(_))
+        ));
+        assert_eq!(musixmatch.search(&query()).await.ok().unwrap().len(), 1);
+        assert_eq!(tokens.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_configured_token_is_kept() {
+        let server = TestServer::start(|_| Response::json(message(401, json!({})))).await;
+        let musixmatch =
+            Musixmatch::with_base_url(test_server::client(), &server.url, Some("mine".to_string()));
+
+        for _ in 0..2 {
+            assert!(musixmatch.search(&query()).await.is_err());
+        }
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| request.query["usertoken"] == "mine"));
+    }
+
+    #[tokio::test]
+    async fn other_statuses_are_fatal() {
+        let server = TestServer::start(|_| Response::json(message(402, json!({})))).await;
+        let musixmatch =
+            Musixmatch::with_base_url(test_server::client(), &server.url, Some("mine".to_string()));
+        assert!(matches!(
+            musixmatch.track_lyrics("9").await,
+            Err(ProviderError::Fatal(_))
+        ));
+    }
+
+    #[test]
+    fn richsync_words_carry_their_spaces() {
+        let body = json!([
+            { "ts": 1.0, "te": 3.0, "l": [
+                { "c": "Hello", "o": 0.0 },
+                { "c": " ", "o": 0.4 },
+                { "c": "world", "o": 0.5 },
+            ] },
+            { "ts": 4.0, "te": 5.0, "l": [
+                { "c": " ", "o": 0.0 },
+                { "c": "Solo", "o": 0.2 },
+            ] },
+        ]);
+        let lyrics = parse_richsync(&body.to_string()).unwrap();
+        let words: Vec<Vec<(f64, f64, &str)>> = lyrics
+            .lines
+            .iter()
+            .map(|line| {
+                line.words
+                    .iter()
+                    .map(|word| (word.start, word.end, word.text.as_str()))
+                    .collect()
+            })
+            .collect();
+        // A word ends where the space after it starts; the last one runs to
+        // the end of the line
+        assert_eq!(
+            words,
+            [
+                vec![(1.0, 1.4, "Hello "), (1.5, 3.0, "world")],
+                vec![(4.2, 5.0, "Solo")]
+            ]
+        );
+        assert_eq!(lyrics.lines[0].text, "Hello world");
+        assert_eq!(lyrics.lines[1].time, 4.0);
+    }
+
+    #[test]
+    fn broken_richsync_is_none() {
+        assert!(parse_richsync("[]").is_none());
+        assert!(parse_richsync("not json").is_none());
+    }
+
+    #[test]
+    fn richsync_survives_a_trip_through_lrc() {
+        let body = json!([{ "ts": 1.0, "te": 3.0, "l": [
+            { "c": "Hello", "o": 0.0 },
+            { "c": " ", "o": 0.5 },
+            { "c": "world", "o": 0.5 },
+        ] }]);
+        let lyrics = parse_richsync(&body.to_string()).unwrap();
+        let written = lrc::to_lrc(&lyrics);
+        assert_eq!(
+            written,
+            "[00:01.00]<00:01.00>Hello <00:01.50>world<00:03.00>\n"
+        );
+        assert_eq!(lrc::parse(&written).lyrics, lyrics);
+    }
+}
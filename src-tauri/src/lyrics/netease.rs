@@ -0,0 +1,375 @@
+// Client for NetEase Cloud Music's web API. A search turns the track into
+// song ids, and lyrics are then fetched per id. Songs with word timings
+// (the "yrc" format) come back with those as well as plain LRC.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::lrc::{Line, Lyrics, Word};
+use super::{
+    lyrics_from_model, lyrics_from_text, Candidate, LyricsProvider, LyricsResult, ProviderError,
+    TrackQuery,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://music.163.com";
+
+// The API turns away requests that don't look like they came from the site
+const REFERER: &str = "https://music.163.com/";
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    result: Option<SearchResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResult {
+    #[serde(default)]
+    songs: Vec<NeteaseSong>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NeteaseSong {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub artists: Vec<NeteaseName>,
+    pub album: Option<NeteaseName>,
+    // Milliseconds
+    pub duration: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NeteaseName {
+    pub name: String,
+}
+
+impl NeteaseSong {
+    fn into_candidate(self) -> Candidate {
+        Candidate {
+            provider: "netease".to_string(),
+            id: self.id.to_string(),
+            artist: self
+                .artists
+                .iter()
+                .map(|artist| artist.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            title: self.name,
+            album: self.album.map(|album| album.name),
+            duration: self.duration.map(|ms| ms as f64 / 1000.0),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LyricResponse {
+    lrc: Option<LyricBody>,
+    yrc: Option<LyricBody>,
+    // Set for instrumentals
+    #[serde(default)]
+    nolyric: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct LyricBody {
+    lyric: Option<String>,
+}
+
+pub struct Netease {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl Netease {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self::with_base_url(client, DEFAULT_BASE_URL)
+    }
+
+    // Sends requests to `base_url` instead of music.163.com, keeping the
+    // paths; the Referer stays the real site's.
+    pub fn with_base_url(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Netease {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn search_songs(
+        &self,
+        query: &TrackQuery,
+    ) -> Result<Vec<NeteaseSong>, ProviderError> {
+        let terms = format!("{} {}", query.artist, query.title);
+        let response: SearchResponse = self
+            .client
+            .get(format!("{}/api/search/get/web", self.base_url))
+            .header(reqwest::header::REFERER, REFERER)
+            .query(&[
+                ("s", terms.as_str()),
+                ("type", "1"),
+                ("limit", "10"),
+                ("offset", "0"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response.result.map(|r| r.songs).unwrap_or_default())
+    }
+
+    pub async fn song_lyrics(&self, id: &str) -> Result<Option<LyricsResult>, ProviderError> {
+        let response: LyricResponse = self
+            .client
+            .get(format!("{}/api/song/lyric/v1", self.base_url))
+            .header(reqwest::header::REFERER, REFERER)
+            .query(&[
+                ("id", id),
+                ("lv", "-1"),
+                ("kv", "-1"),
+                ("tv", "-1"),
+                ("yv", "-1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response.nolyric {
+            return Ok(Some(LyricsResult {
+                instrumental: true,
+                provider: self.name().to_string(),
+                ..Default::default()
+            }));
+        }
+        let yrc = response.yrc.and_then(|yrc| yrc.lyric);
+        if let Some(lyrics) = yrc.as_deref().and_then(parse_yrc) {
+            return Ok(Some(lyrics_from_model(lyrics, self.name())));
+        }
+        let text = response.lrc.and_then(|lrc| lrc.lyric).unwrap_or_default();
+        Ok(lyrics_from_text(text, self.name()))
+    }
+}
+
+// Lines look like `[start,duration](start,duration,0)word(start,duration,0)word`
+// with times in milliseconds. Credits are mixed in as lines of JSON, which
+// are skipped.
+fn parse_yrc(text: &str) -> Option<Lyrics> {
+    let mut lines = Vec::new();
+    for raw in text.lines() {
+        let Some((timing, mut rest)) = raw.trim().strip_prefix('[').and_then(|r| r.split_once(']'))
+        else {
+            continue;
+        };
+        let Some((time, _)) = parse_timing(timing) else {
+            continue;
+        };
+
+        let mut words = Vec::new();
+        while let Some(body) = rest.strip_prefix('(') {
+            let Some(((start, duration), after)) = body
+                .split_once(')')
+                .and_then(|(timing, after)| Some((parse_timing(timing)?, after)))
+            else {
+                break;
+            };
+            let end = next_marker(after);
+            words.push(Word {
+                start,
+                end: start + duration,
+                text: after[..end].to_string(),
+            });
+            rest = &after[end..];
+        }
+
+        let text: String = words.iter().map(|word| word.text.as_str()).collect();
+        lines.push(Line {
+            time,
+            text: text.trim().to_string(),
+            words,
+        });
+    }
+    (!lines.is_empty()).then(|| Lyrics {
+        lines,
+        ..Default::default()
+    })
+}
+
+// `start,duration[,...]` in milliseconds, as seconds.
+fn parse_timing(timing: &str) -> Option<(f64, f64)> {
+    let mut parts = timing.split(',').map(|part| part.trim().parse::<f64>());
+    let start = parts.next()?.ok()?;
+    let duration = parts.next()?.ok()?;
+    Some((start / 1000.0, duration / 1000.0))
+}
+
+// Where the next word's `(` marker starts, skipping brackets that are part
+// of the lyrics.
+fn next_marker(text: &str) -> usize {
+    text.match_indices('(')
+        .map(|(i, _)| i)
+        .find(|&i| {
+            text[i + 1..]
+                .split_once(')')
+                .is_some_and(|(timing, _)| parse_timing(timing).is_some())
+        })
+        .unwrap_or(text.len())
+}
+
+#[async_trait]
+impl LyricsProvider for Netease {
+    fn name(&self) -> &'static str {
+        "netease"
+    }
+
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+        let songs = self.search_songs(query).await?;
+        Ok(songs.into_iter().map(NeteaseSong::into_candidate).collect())
+    }
+
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError> {
+        self.song_lyrics(&candidate.id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+    use crate::test_server::{self, Response, TestServer};
+    use serde_json::json;
+
+    fn query() -> TrackQuery {
+        TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn search_sends_the_referer_and_reads_songs() {
+        let server = TestServer::start(|_| {
+            Response::json(json!({ "result": { "songs": [{
+                "id": 42,
+                "name": "Song",
+                "artists": [{ "name": "A" }, { "name": "B" }],
+                "album": { "name": "Album" },
+                "duration": 201500,
+            }] } }))
+        })
+        .await;
+        let netease = Netease::with_base_url(test_server::client(), &server.url);
+
+        let candidates = netease.search(&query()).await.ok().unwrap();
+        assert_eq!(candidates.len(), 1);
+        let candidate = &candidates[0];
+        assert_eq!(candidate.id, "42");
+        assert_eq!(candidate.artist, "A, B");
+        assert_eq!(candidate.album.as_deref(), Some("Album"));
+        assert_eq!(candidate.duration, Some(201.5));
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/api/search/get/web");
+        assert_eq!(request.query["s"], "Artist Song");
+        assert_eq!(request.headers["referer"], REFERER);
+    }
+
+    #[tokio::test]
+    async fn no_results_is_an_empty_search() {
+        let server = TestServer::start(|_| Response::json(json!({ "code": 200 }))).await;
+        let netease = Netease::with_base_url(test_server::client(), &server.url);
+        assert!(netease.search(&query()).await.ok().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn lyrics_prefer_word_timings() {
+        let server = TestServer::start(|request| {
+            Response::json(match request.query["id"].as_str() {
+                "1" => json!({
+                    "lrc": { "lyric": "[00:01.00]Hello world" },
+                    "yrc": { "lyric": "[1000,2000](1000,500,0)Hello (1500,1500,0)world" },
+                }),
+                "2" => json!({ "lrc": { "lyric": "[00:01.00]Hello world" } }),
+                _ => json!({ "nolyric": true }),
+            })
+        })
+        .await;
+        let netease = Netease::with_base_url(test_server::client(), &server.url);
+
+        let worded = netease.song_lyrics("1").await.ok().unwrap().unwrap();
+        let parsed = worded.parsed.unwrap();
+        assert_eq!(parsed.lines[0].text, "Hello world");
+        assert_eq!(parsed.lines[0].words.len(), 2);
+
+        let lined = netease.song_lyrics("2").await.ok().unwrap().unwrap();
+        assert_eq!(lined.synced.as_deref(), Some("[00:01.00]Hello world"));
+        assert!(lined.parsed.is_none());
+
+        let instrumental = netease.song_lyrics("3").await.ok().unwrap().unwrap();
+        assert!(instrumental.instrumental);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_worth_retrying() {
+        let server = TestServer::start(|_| Response::status(502, json!({}))).await;
+        let netease = Netease::with_base_url(test_server::client(), &server.url);
+        assert!(matches!(
+            netease.song_lyrics("1").await,
+            Err(ProviderError::Transient(_))
+        ));
+    }
+
+    #[test]
+    fn yrc_lines_and_words() {
+        let text = "{\"t\":0,\"c\":[{\"tx\":\"Lyrics by: \"}]}\n\
+                    [1000,2500](1000,500,0)Hello (1500,1000,0)(world) (2500,1000,0)again\n\
+                    [bad]ignored\n\
+                    [4000,1000](4000,1000,0)Solo";
+        let lyrics = parse_yrc(text).unwrap();
+        assert_eq!(lyrics.lines.len(), 2);
+
+        let line = &lyrics.lines[0];
+        assert_eq!(line.time, 1.0);
+        // Brackets that aren't timings are part of the lyrics
+        assert_eq!(line.text, "Hello (world) again");
+        let words: Vec<(f64, f64, &str)> = line
+            .words
+            .iter()
+            .map(|word| (word.start, word.end, word.text.as_str()))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                (1.0, 1.5, "Hello "),
+                (1.5, 2.5, "(world) "),
+                (2.5, 3.5, "again")
+            ]
+        );
+        assert_eq!(lyrics.lines[1].text, "Solo");
+    }
+
+    #[test]
+    fn yrc_without_lines_is_none() {
+        assert!(parse_yrc("").is_none());
+        assert!(parse_yrc("{\"t\":0,\"c\":[]}").is_none());
+    }
+
+    #[test]
+    fn yrc_survives_a_trip_through_lrc() {
+        let text = "[1000,2500](1000,500,0)Hello (1500,1000,0)(world) (2500,1000,0)again\n\
+                    [4000,1000](4000,1000,0)Solo";
+        let lyrics = parse_yrc(text).unwrap();
+        let written = lrc::to_lrc(&lyrics);
+        assert_eq!(
+            written,
+            "[00:01.00]<00:01.00>Hello <00:01.50>(world) <00:02.50>again<00:03.50>\n\
+             [00:04.00]<00:04.00>Solo<00:05.00>\n"
+        );
+        let parsed = lrc::parse(&written);
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.lyrics, lyrics);
+    }
+}
@@ -0,0 +1,138 @@
+// Timing corrections the user made, for lyrics that run early or late
+// against their copy of a track: one per track, by its cache id, and one for
+// every track. Milliseconds, added to the lyrics' own `[offset:]`, so
+// positive values show lines earlier. Kept in a single JSON file, read once
+// at startup.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+use crate::json_file;
+
+// What a nudge moves an offset by
+pub const STEP_MS: i64 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Saved {
+    global: i64,
+    tracks: HashMap<String, i64>,
+}
+
+// The offsets in effect for a track.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackOffset {
+    pub track_key: String,
+    pub track: i64,
+    pub global: i64,
+}
+
+impl TrackOffset {
+    // Both together, in seconds
+    pub fn seconds(&self) -> f64 {
+        (self.track + self.global) as f64 / 1000.0
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OffsetScope {
+    Track,
+    Global,
+}
+
+pub struct Offsets {
+    path: PathBuf,
+    saved: Mutex<Saved>,
+}
+
+impl Offsets {
+    pub fn new(path: PathBuf) -> Self {
+        Offsets {
+            saved: Mutex::new(json_file::load(&path)),
+            path,
+        }
+    }
+
+    pub async fn get(&self, track_key: &str) -> TrackOffset {
+        let saved = self.saved.lock().await;
+        offset_for(&saved, track_key)
+    }
+
+    // Moves an offset by `steps` of `STEP_MS`, or back to 0 when `steps` is
+    // `None`.
+    pub async fn adjust(
+        &self,
+        track_key: &str,
+        scope: OffsetScope,
+        steps: Option<i64>,
+    ) -> Result<TrackOffset, String> {
+        let mut saved = self.saved.lock().await;
+        let current = match scope {
+            OffsetScope::Global => saved.global,
+            OffsetScope::Track => saved.tracks.get(track_key).copied().unwrap_or_default(),
+        };
+        let value = steps.map_or(0, |steps| current + steps * STEP_MS);
+        match scope {
+            OffsetScope::Global => saved.global = value,
+            // Tracks back in step with their lyrics aren't kept around
+            OffsetScope::Track if value == 0 => {
+                saved.tracks.remove(track_key);
+            }
+            OffsetScope::Track => {
+                saved.tracks.insert(track_key.to_string(), value);
+            }
+        }
+
+        json_file::save(&self.path, &*saved).await?;
+        Ok(offset_for(&saved, track_key))
+    }
+}
+
+fn offset_for(saved: &Saved, track_key: &str) -> TrackOffset {
+    TrackOffset {
+        track_key: track_key.to_string(),
+        track: saved.tracks.get(track_key).copied().unwrap_or_default(),
+        global: saved.global,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::fixture_dir;
+
+    #[tokio::test]
+    async fn nudges_add_up_and_resets_clear_them() {
+        let dir = fixture_dir("offsets", &[]);
+        let offsets = Offsets::new(dir.join("offsets.json"));
+
+        offsets
+            .adjust("a", OffsetScope::Track, Some(2))
+            .await
+            .unwrap();
+        offsets
+            .adjust("a", OffsetScope::Track, Some(1))
+            .await
+            .unwrap();
+        let offset = offsets
+            .adjust("b", OffsetScope::Global, Some(-1))
+            .await
+            .unwrap();
+        assert_eq!((offset.track, offset.global), (0, -100));
+        let offset = offsets.get("a").await;
+        assert_eq!((offset.track, offset.global), (300, -100));
+        assert!((offset.seconds() - 0.2).abs() < 1e-9);
+
+        // Back in step, and kept that way after a restart
+        let offset = offsets.adjust("a", OffsetScope::Track, None).await.unwrap();
+        assert_eq!((offset.track, offset.global), (0, -100));
+        let reloaded = Offsets::new(dir.join("offsets.json"));
+        assert_eq!(reloaded.get("a").await.track, 0);
+        assert_eq!(reloaded.get("a").await.global, -100);
+        assert!(reloaded.saved.lock().await.tracks.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+// Publishing synced lyrics to lrclib. Nothing is sent until the user has seen
+// exactly what will be: `prepare` builds a draft for them to look over, and
+// only that draft, once confirmed by its id, goes out.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use super::lrc::{self, Lyrics};
+use super::lrclib::{Lrclib, PublishRequest};
+use super::TrackQuery;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishDraft {
+    pub id: u64,
+    // Where it'll be published
+    pub server: String,
+    #[serde(flatten)]
+    pub request: PublishRequest,
+}
+
+#[derive(Default)]
+pub struct Publisher {
+    next_id: AtomicU64,
+    // The draft waiting on the user, if any
+    pending: Mutex<Option<PublishDraft>>,
+}
+
+impl Publisher {
+    // Builds the request for `lyrics` and holds it until it's confirmed or
+    // another draft replaces it. The lyrics' offset is applied to the times,
+    // and header tags are left out, since the track details go alongside.
+    pub fn prepare(
+        &self,
+        lrclib: &Lrclib,
+        track: &TrackQuery,
+        lyrics: &Lyrics,
+    ) -> Result<PublishDraft, String> {
+        if lyrics.lines.is_empty() {
+            return Err("No synced lyrics to publish".to_string());
+        }
+        let duration = track
+            .duration
+            .filter(|duration| *duration > 0.0)
+            .ok_or("lrclib needs the track's length, and the player didn't report it")?;
+
+        let lines = lyrics.without_offset().lines;
+        let plain_lyrics = lines
+            .iter()
+            .map(|line| line.text.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let synced_lyrics = lrc::to_lrc(&Lyrics {
+            lines,
+            ..Default::default()
+        });
+        let draft = PublishDraft {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            server: lrclib.base_url().to_string(),
+            request: PublishRequest {
+                track_name: track.title.trim().to_string(),
+                artist_name: track.artist.trim().to_string(),
+                album_name: track
+                    .album
+                    .as_deref()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                duration: duration.round(),
+                plain_lyrics,
+                synced_lyrics,
+            },
+        };
+        *self.lock()? = Some(draft.clone());
+        Ok(draft)
+    }
+
+    // Publishes the draft with `id`, first solving the challenge lrclib
+    // hands out. A draft that fails to publish can be confirmed again.
+    pub async fn publish(&self, lrclib: &Lrclib, id: u64) -> Result<(), String> {
+        let draft = {
+            let mut pending = self.lock()?;
+            match pending.take() {
+                Some(draft) if draft.id == id => draft,
+                other => {
+                    *pending = other;
+                    return Err(
+                        "Those lyrics are no longer waiting to be published; review them again"
+                            .to_string(),
+                    );
+                }
+            }
+        };
+        if draft.server != lrclib.base_url() {
+            return Err("The lrclib server changed; review the lyrics again".to_string());
+        }
+
+        let result = send(lrclib, &draft.request).await;
+        if result.is_err() {
+            // Unless a newer draft took its place meanwhile
+            self.lock()?.get_or_insert(draft);
+        }
+        result
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Option<PublishDraft>>, String> {
+        self.pending
+            .lock()
+            .map_err(|_| "Failed to lock the publish draft".to_string())
+    }
+}
+
+async fn send(lrclib: &Lrclib, request: &PublishRequest) -> Result<(), String> {
+    let challenge = lrclib
+        .request_challenge()
+        .await
+        .map_err(|e| e.message().to_string())?;
+    let token = tokio::task::spawn_blocking(move || challenge.solve())
+        .await
+        .map_err(|e| e.to_string())??;
+    lrclib
+        .publish(request, &token)
+        .await
+        .map_err(|e| format!("lrclib didn't accept the lyrics: {}", e.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Request, Response, TestServer};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    // Easy enough to solve in a few dozen tries
+    const TARGET: &str = "07ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    fn track() -> TrackQuery {
+        TrackQuery {
+            artist: " Artist ".to_string(),
+            title: "Song".to_string(),
+            album: None,
+            duration: Some(199.6),
+            path: None,
+        }
+    }
+
+    fn lyrics() -> Lyrics {
+        lrc::parse("[ti:Song]\n[offset:500]\n[00:01.50]Hello\n[00:03.00]World").lyrics
+    }
+
+    // Whether `token` solves the challenge the server handed out.
+    fn solves(request: &Request) -> bool {
+        let Some((prefix, nonce)) = request
+            .headers
+            .get("x-publish-token")
+            .and_then(|token| token.split_once(':'))
+        else {
+            return false;
+        };
+        let hash = Sha256::digest(format!("{}{}", prefix, nonce));
+        prefix == "challenge" && format!("{:x}", hash).as_str() <= TARGET
+    }
+
+    // An lrclib that accepts solved tokens once `accepting` is set, and
+    // turns everything down until then.
+    async fn server(accepting: Arc<AtomicBool>) -> TestServer {
+        TestServer::start(move |request| match request.path.as_str() {
+            "/api/request-challenge" => Response::json(json!({
+                "prefix": "challenge",
+                "target": TARGET,
+            })),
+            "/api/publish" if accepting.load(Ordering::Relaxed) && solves(request) => {
+                Response::status(201, json!({}))
+            }
+            "/api/publish" => Response::status(
+                400,
+                json!({ "code": 400, "name": "IncorrectPublishToken", "message": "The provided publish token is incorrect" }),
+            ),
+            _ => Response::status(404, json!({})),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn confirmed_drafts_are_sent_with_a_solved_token() {
+        let server = server(Arc::new(AtomicBool::new(true))).await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), &server.url);
+        let publisher = Publisher::default();
+
+        let draft = publisher.prepare(&lrclib, &track(), &lyrics()).unwrap();
+        assert_eq!(draft.server, server.url);
+        publisher.publish(&lrclib, draft.id).await.unwrap();
+
+        let requests = server.requests();
+        let paths: Vec<(&str, &str)> = requests
+            .iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            [("POST", "/api/request-challenge"), ("POST", "/api/publish")]
+        );
+        assert!(solves(&requests[1]));
+        let body: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "trackName": "Song",
+                "artistName": "Artist",
+                "albumName": "",
+                "duration": 200.0,
+                "plainLyrics": "Hello\nWorld",
+                "syncedLyrics": "[00:01.00]Hello\n[00:02.50]World\n",
+            })
+        );
+
+        // Sent once only
+        assert!(publisher.publish(&lrclib, draft.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejected_drafts_can_be_confirmed_again() {
+        let accepting = Arc::new(AtomicBool::new(false));
+        let server = server(accepting.clone()).await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), &server.url);
+        let publisher = Publisher::default();
+
+        let draft = publisher.prepare(&lrclib, &track(), &lyrics()).unwrap();
+        assert_eq!(
+            publisher.publish(&lrclib, draft.id).await,
+            Err(
+                "lrclib didn't accept the lyrics: The provided publish token is incorrect"
+                    .to_string()
+            )
+        );
+
+        accepting.store(true, Ordering::Relaxed);
+        publisher.publish(&lrclib, draft.id).await.unwrap();
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn only_the_latest_draft_is_published() {
+        let server = server(Arc::new(AtomicBool::new(true))).await;
+        let lrclib = Lrclib::with_base_url(test_server::client(), &server.url);
+        let publisher = Publisher::default();
+
+        let first = publisher.prepare(&lrclib, &track(), &lyrics()).unwrap();
+        let second = publisher.prepare(&lrclib, &track(), &lyrics()).unwrap();
+        assert!(publisher.publish(&lrclib, first.id).await.is_err());
+        assert!(server.requests().is_empty());
+        publisher.publish(&lrclib, second.id).await.unwrap();
+
+        // Nor to a server other than the one the user was shown
+        let draft = publisher.prepare(&lrclib, &track(), &lyrics()).unwrap();
+        let elsewhere = Lrclib::with_base_url(test_server::client(), "http://127.0.0.1:9");
+        assert_eq!(
+            publisher.publish(&elsewhere, draft.id).await,
+            Err("The lrclib server changed; review the lyrics again".to_string())
+        );
+    }
+
+    #[test]
+    fn drafts_need_synced_lines_and_a_length() {
+        let lrclib = Lrclib::with_base_url(test_server::client(), "http://127.0.0.1:9");
+        let publisher = Publisher::default();
+        assert!(publisher
+            .prepare(&lrclib, &track(), &Lyrics::default())
+            .is_err());
+
+        let track = TrackQuery {
+            duration: None,
+            ..track()
+        };
+        assert!(publisher.prepare(&lrclib, &track, &lyrics()).is_err());
+    }
+}
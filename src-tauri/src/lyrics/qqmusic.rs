@@ -0,0 +1,212 @@
+// Client for QQ Music's web API. Search returns song mids, and lyrics come
+// back base64 encoded per mid.
+
+use async_trait::async_trait;
+use base64::Engine;
+use serde::Deserialize;
+
+use super::{lyrics_from_text, Candidate, LyricsProvider, LyricsResult, ProviderError, TrackQuery};
+
+pub const DEFAULT_BASE_URL: &str = "https://c.y.qq.com";
+
+// The lyric endpoint refuses requests without it
+const REFERER: &str = "https://y.qq.com/";
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    data: Option<SearchData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchData {
+    song: Option<SongList>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SongList {
+    #[serde(default)]
+    list: Vec<QqSong>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QqSong {
+    pub songmid: String,
+    pub songname: String,
+    #[serde(default)]
+    pub singer: Vec<QqSinger>,
+    pub albumname: Option<String>,
+    // Seconds
+    pub interval: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QqSinger {
+    pub name: String,
+}
+
+impl QqSong {
+    fn into_candidate(self) -> Candidate {
+        Candidate {
+            provider: "qqmusic".to_string(),
+            id: self.songmid,
+            artist: self
+                .singer
+                .iter()
+                .map(|singer| singer.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            title: self.songname,
+            album: self.albumname,
+            duration: self.interval.map(|seconds| seconds as f64),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LyricResponse {
+    #[serde(default)]
+    lyric: String,
+}
+
+pub struct QqMusic {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl QqMusic {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self::with_base_url(client, DEFAULT_BASE_URL)
+    }
+
+    // `base_url` has to serve both the search and the lyric endpoint, as
+    // c.y.qq.com does.
+    pub fn with_base_url(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        QqMusic {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn search_songs(&self, query: &TrackQuery) -> Result<Vec<QqSong>, ProviderError> {
+        let terms = format!("{} {}", query.artist, query.title);
+        let response: SearchResponse = self
+            .client
+            .get(format!("{}/soso/fcgi-bin/client_search_cp", self.base_url))
+            .query(&[
+                ("w", terms.as_str()),
+                ("p", "1"),
+                ("n", "10"),
+                ("format", "json"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .data
+            .and_then(|data| data.song)
+            .map(|song| song.list)
+            .unwrap_or_default())
+    }
+
+    pub async fn song_lyrics(&self, songmid: &str) -> Result<Option<LyricsResult>, ProviderError> {
+        let response: LyricResponse = self
+            .client
+            .get(format!(
+                "{}/lyric/fcgi-bin/fcg_query_lyric_new.fcg",
+                self.base_url
+            ))
+            .header(reqwest::header::REFERER, REFERER)
+            .query(&[("songmid", songmid), ("format", "json"), ("g_tk", "5381")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(response.lyric.trim())
+            .map_err(|e| ProviderError::Fatal(e.to_string()))?;
+        Ok(lyrics_from_text(
+            String::from_utf8_lossy(&bytes).into_owned(),
+            self.name(),
+        ))
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for QqMusic {
+    fn name(&self) -> &'static str {
+        "qqmusic"
+    }
+
+    async fn search(&self, query: &TrackQuery) -> Result<Vec<Candidate>, ProviderError> {
+        let songs = self.search_songs(query).await?;
+        Ok(songs.into_iter().map(QqSong::into_candidate).collect())
+    }
+
+    async fn lyrics(&self, candidate: &Candidate) -> Result<Option<LyricsResult>, ProviderError> {
+        self.song_lyrics(&candidate.id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response, TestServer};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn search_reads_the_song_list() {
+        let server = TestServer::start(|_| {
+            Response::json(json!({ "data": { "song": { "list": [{
+                "songmid": "abc",
+                "songname": "Song",
+                "singer": [{ "name": "Artist" }],
+                "albumname": "Album",
+                "interval": 200,
+            }] } } }))
+        })
+        .await;
+        let qq = QqMusic::with_base_url(test_server::client(), &server.url);
+        let query = TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        };
+
+        let candidates = qq.search(&query).await.ok().unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].id, "abc");
+        assert_eq!(candidates[0].artist, "Artist");
+        assert_eq!(candidates[0].duration, Some(200.0));
+        assert_eq!(server.requests()[0].query["w"], "Artist Song");
+    }
+
+    #[tokio::test]
+    async fn lyrics_are_decoded() {
+        let server = TestServer::start(|request| {
+            let lyric = match request.query["songmid"].as_str() {
+                "synced" => base64::engine::general_purpose::STANDARD.encode("[00:01.00]Hello"),
+                "empty" => String::new(),
+                _ => "not base64!".to_string(),
+            };
+            Response::json(json!({ "lyric": lyric }))
+        })
+        .await;
+        let qq = QqMusic::with_base_url(test_server::client(), &server.url);
+
+        let lyrics = qq.song_lyrics("synced").await.ok().unwrap().unwrap();
+        assert_eq!(lyrics.synced.as_deref(), Some("[00:01.00]Hello"));
+        assert_eq!(server.requests()[0].headers["referer"], REFERER);
+
+        assert!(qq.song_lyrics("empty").await.ok().unwrap().is_none());
+        assert!(matches!(
+            qq.song_lyrics("garbled").await,
+            Err(ProviderError::Fatal(_))
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+// Ranks provider candidates against the track that's playing. Text is
+// compared fuzzily since providers disagree on punctuation, casing and
+// featured artists. Durations are compared against a tolerance; a candidate
+// outside it is a different recording (a live take, a radio edit) whose
+// timings won't line up, so it's rejected outright.
+
+use super::{Candidate, TrackQuery};
+
+const TITLE_WEIGHT: f64 = 0.4;
+const ARTIST_WEIGHT: f64 = 0.3;
+const ALBUM_WEIGHT: f64 = 0.1;
+const DURATION_WEIGHT: f64 = 0.2;
+
+// Between 0 and 1, or `None` when the durations are too far apart. Fields
+// missing on either side are left out rather than counted against the
+// candidate.
+pub fn score(query: &TrackQuery, candidate: &Candidate, tolerance: f64) -> Option<f64> {
+    let mut parts = vec![
+        (TITLE_WEIGHT, similarity(&query.title, &candidate.title)),
+        (ARTIST_WEIGHT, similarity(&query.artist, &candidate.artist)),
+    ];
+
+    let albums = (
+        query.album.as_deref().filter(|album| !album.is_empty()),
+        candidate.album.as_deref().filter(|album| !album.is_empty()),
+    );
+    if let (Some(expected), Some(actual)) = albums {
+        parts.push((ALBUM_WEIGHT, similarity(expected, actual)));
+    }
+
+    if let (Some(expected), Some(actual)) = (query.duration, candidate.duration) {
+        let difference = (expected - actual).abs();
+        if difference > tolerance {
+            return None;
+        }
+        let closeness = if tolerance > 0.0 {
+            1.0 - difference / tolerance
+        } else {
+            1.0
+        };
+        parts.push((DURATION_WEIGHT, closeness));
+    }
+
+    let weights: f64 = parts.iter().map(|(weight, _)| weight).sum();
+    let total: f64 = parts.iter().map(|(weight, value)| weight * value).sum();
+    Some(total / weights)
+}
+
+// Sørensen–Dice coefficient over character pairs, from 0 to 1.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = normalize(a);
+    let b = normalize(b);
+    if a == b {
+        return 1.0;
+    }
+
+    let a_pairs = pairs(&a);
+    let mut b_pairs = pairs(&b);
+    let total = a_pairs.len() + b_pairs.len();
+    let mut matches = 0;
+    for pair in a_pairs {
+        if let Some(i) = b_pairs.iter().position(|other| *other == pair) {
+            b_pairs.swap_remove(i);
+            matches += 1;
+        }
+    }
+    2.0 * matches as f64 / total as f64
+}
+
+// Lowercase words with punctuation dropped, so "AC/DC" and "ac dc" agree.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Padded with spaces so single characters (common in CJK titles) still
+// produce pairs.
+fn pairs(text: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = format!(" {} ", text).chars().collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(duration: Option<f64>, album: Option<&str>) -> TrackQuery {
+        TrackQuery {
+            artist: "AC/DC".to_string(),
+            title: "Back in Black".to_string(),
+            album: album.map(str::to_string),
+            duration,
+            path: None,
+        }
+    }
+
+    fn candidate(title: &str, duration: Option<f64>, album: Option<&str>) -> Candidate {
+        Candidate {
+            artist: "ac dc".to_string(),
+            title: title.to_string(),
+            album: album.map(str::to_string),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn an_exact_match_scores_one() {
+        let query = query(Some(255.0), Some("Back in Black"));
+        let candidate = candidate("Back In Black", Some(255.0), Some("Back in Black"));
+        assert_eq!(score(&query, &candidate, 3.0), Some(1.0));
+    }
+
+    #[test]
+    fn durations_outside_the_tolerance_are_rejected() {
+        let query = query(Some(255.0), None);
+        assert_eq!(
+            score(&query, &candidate("Back in Black", Some(260.0), None), 3.0),
+            None
+        );
+
+        let close = score(&query, &candidate("Back in Black", Some(257.0), None), 3.0).unwrap();
+        assert!(close < 1.0 && close > 0.8, "{}", close);
+
+        // No tolerance means only an exact duration is accepted
+        assert_eq!(
+            score(&query, &candidate("Back in Black", Some(255.0), None), 0.0),
+            Some(1.0)
+        );
+        assert_eq!(
+            score(&query, &candidate("Back in Black", Some(255.5), None), 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_left_out() {
+        // Neither a missing duration nor a missing or empty album costs anything
+        let query = query(Some(255.0), Some("Back in Black"));
+        assert_eq!(
+            score(&query, &candidate("Back in Black", None, None), 3.0),
+            Some(1.0)
+        );
+        assert_eq!(
+            score(&query, &candidate("Back in Black", None, Some("")), 3.0),
+            Some(1.0)
+        );
+
+        let different = score(&query, &candidate("Hells Bells", None, None), 3.0).unwrap();
+        assert!(different < 0.8, "{}", different);
+    }
+
+    #[test]
+    fn cjk_titles_are_compared_by_character() {
+        assert_eq!(similarity("夜に駆ける", "夜に駆ける"), 1.0);
+        let partial = similarity("夜に駆ける", "夜に駆ける (Live)");
+        assert!(partial > 0.5 && partial < 1.0, "{}", partial);
+        // Single characters still produce pairs to compare
+        assert!(similarity("雪", "雪") == 1.0 && similarity("雪", "雨") == 0.0);
+    }
+
+    #[test]
+    fn punctuation_and_case_are_ignored() {
+        assert_eq!(similarity("Don't Stop Me Now!", "don’t stop me now"), 1.0);
+        assert_eq!(similarity("AC/DC", "ac dc"), 1.0);
+    }
+}
@@ -1,12 +1,32 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod active_player;
+mod art_cache;
+mod events;
+mod http;
+mod json_file;
+mod library;
+mod lyric_sync;
+mod lyrics;
+mod migration;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod position;
+mod settings;
+mod tags;
+#[cfg(test)]
+mod test_files;
+#[cfg(test)]
+mod test_server;
+
 use serde::Serialize;
 use std::collections::HashMap;
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "macos")]
 use std::process::Command;
 use std::sync::Mutex;
-#[cfg(target_os = "linux")]
-use tauri_plugin_shell::ShellExt;
+use std::time::Duration;
+use tauri::Manager;
 #[cfg(target_os = "windows")]
 use windows::{
     core::Result as WindowsResult,
@@ -18,41 +38,175 @@ use windows::{
     },
 };
 
-struct AppState {
-    previous_positions: Mutex<HashMap<usize, f64>>,
-    selected_player: Mutex<Option<String>>,
-}
+use position::{PositionClock, PositionSample};
+use settings::Settings;
 
-#[cfg(target_os = "linux")]
-async fn command(app_handle: &tauri::AppHandle, command: &str) -> Result<String, String> {
-    let mut parts = command.split_whitespace().collect::<Vec<&str>>();
-
-    let output = if parts[0] == "playerctl" {
-        app_handle
-            .shell()
-            .sidecar("playerctl")
-            .map_err(|e| format!("Failed to create playerctl sidecar: {}", e))?
-            .args(&parts[1..])
-            .output()
-            .await
-            .map_err(|e| format!("Failed to execute command '{}': {}", command, e))?
-            .stdout
-    } else {
-        Command::new(parts.remove(0))
-            .args(parts)
-            .output()
-            .map_err(|e| format!("Failed to execute command '{}': {}", command, e))?
-            .stdout
-    };
+// How long a position clock is trusted before the player is asked again
+const POSITION_RESYNC_INTERVAL: Duration = Duration::from_secs(3);
 
-    String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 output: {}", e))
+struct AppState {
+    positions: Mutex<HashMap<String, PositionClock>>,
+    selected_player: Mutex<Option<String>>,
+    active_player: active_player::ActivePlayer,
+    #[cfg(target_os = "linux")]
+    mpris: mpris::MprisClient,
+    lyrics: lyrics::LyricsService,
+    offsets: lyrics::offsets::Offsets,
+    art: art_cache::ArtCache,
+    lyric_sync: lyric_sync::LyricSync,
+    sync_editor: lyrics::editor::SyncEditor,
+    publisher: lyrics::publish::Publisher,
+    library: library::LibraryJob,
+    settings: Mutex<Settings>,
+    config_dir: PathBuf,
+    data_dir: PathBuf,
+    http: http::Http,
 }
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
 struct Metadata {
     artist: String,
     title: String,
     album: String,
+    // Track length in seconds
+    duration: Option<f64>,
+    art_url: Option<String>,
+    track_id: Option<String>,
+    url: Option<String>,
+    album_artists: Vec<String>,
+    track_number: Option<i32>,
+    // Every artist on the track; `artist` is these joined for display
+    artists: Vec<String>,
+}
+
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Capabilities {
+    can_play: bool,
+    can_pause: bool,
+    can_go_next: bool,
+    can_go_previous: bool,
+    can_seek: bool,
+    can_control: bool,
+}
+
+// Everything one UI refresh needs, gathered in one go
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PlayerSnapshot {
+    player: String,
+    metadata: Metadata,
+    // Position in seconds
+    position: f64,
+    // "Playing", "Paused" or "Stopped"
+    status: String,
+    // 0.0 to 1.0, when the player exposes it
+    volume: Option<f64>,
+    rate: f64,
+    capabilities: Capabilities,
+}
+
+#[tauri::command]
+async fn get_player_snapshot(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<PlayerSnapshot, String> {
+    let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+
+    #[cfg(target_os = "linux")]
+    let snapshot = state.mpris.snapshot(&active_player).await?;
+
+    // Everything from the one session, so the parts agree with each other
+    #[cfg(target_os = "windows")]
+    let snapshot = {
+        let session = current_session_windows().await?;
+        let metadata = metadata_windows(&session).await?;
+        let sample = position_sample_windows(&session)?;
+        let playback_info = session.GetPlaybackInfo().map_err(|e| e.to_string())?;
+        let controls = playback_info.Controls().map_err(|e| e.to_string())?;
+        let status = match playback_info.PlaybackStatus().map_err(|e| e.to_string())? {
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing => "Playing",
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Paused => "Paused",
+            _ => "Stopped",
+        };
+
+        PlayerSnapshot {
+            player: active_player.clone(),
+            metadata,
+            position: sample.position,
+            status: status.to_string(),
+            volume: None,
+            rate: sample.rate,
+            capabilities: Capabilities {
+                can_play: controls.IsPlayEnabled().unwrap_or(false),
+                can_pause: controls.IsPauseEnabled().unwrap_or(false),
+                can_go_next: controls.IsNextEnabled().unwrap_or(false),
+                can_go_previous: controls.IsPreviousEnabled().unwrap_or(false),
+                can_seek: controls.IsPlaybackPositionEnabled().unwrap_or(false),
+                can_control: true,
+            },
+        }
+    };
+
+    #[cfg(target_os = "macos")]
+    let snapshot = {
+        let metadata = get_current_playing_song(app_handle.clone(), state.clone()).await?;
+        let sample = sample_position(&state, &active_player).await?;
+        let output = Command::new("osascript")
+            .arg("-e")
+            .arg(format!(
+                "tell application \"{}\" to sound volume as string",
+                active_player
+            ))
+            .output()
+            .map_err(|e| e.to_string())?;
+        let volume = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|v| v / 100.0);
+
+        PlayerSnapshot {
+            player: active_player.clone(),
+            metadata,
+            position: sample.position,
+            status: if sample.playing { "Playing" } else { "Paused" }.to_string(),
+            volume,
+            rate: sample.rate,
+            capabilities: Capabilities {
+                can_play: true,
+                can_pause: true,
+                can_go_next: true,
+                can_go_previous: true,
+                can_seek: true,
+                can_control: true,
+            },
+        }
+    };
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+    let snapshot = PlayerSnapshot {
+        player: active_player.clone(),
+        metadata: Metadata::default(),
+        position: 0.0,
+        status: "Stopped".to_string(),
+        volume: None,
+        rate: 1.0,
+        capabilities: Capabilities::default(),
+    };
+
+    // Keep the position clock in step with what we just read
+    let sample = PositionSample {
+        position: snapshot.position,
+        rate: snapshot.rate,
+        playing: snapshot.status == "Playing",
+    };
+    let (position, _) = sync_position(&state, &active_player, sample)?;
+    Ok(PlayerSnapshot {
+        position,
+        ..snapshot
+    })
 }
 
 #[tauri::command]
@@ -73,6 +227,14 @@ async fn get_current_playing_song(
     #[cfg(target_os = "macos")]
     {
         let active_player = get_active_player(app_handle.clone(), state).await?;
+        let is_spotify = active_player == "Spotify";
+
+        // Only Spotify knows about artwork URLs and track ids
+        let spotify_fields = if is_spotify {
+            " & \"\\n\" & (artwork url of current track) & \"\\n\" & (id of current track)"
+        } else {
+            ""
+        };
         let output = Command::new("osascript")
             .arg("-e")
             .arg(format!(
@@ -80,9 +242,12 @@ async fn get_current_playing_song(
                     set t_artist to artist of current track
                     set t_title to name of current track
                     set t_album to album of current track
-                    return t_artist & \"\\n\" & t_title & \"\\n\" & t_album
+                    set t_album_artist to album artist of current track
+                    set t_number to track number of current track
+                    set t_duration to duration of current track
+                    return t_artist & \"\\n\" & t_title & \"\\n\" & t_album & \"\\n\" & t_album_artist & \"\\n\" & t_number & \"\\n\" & t_duration{}
                 end tell",
-                active_player
+                active_player, spotify_fields
             ))
             .output()
             .map_err(|e| e.to_string())?;
@@ -90,11 +255,33 @@ async fn get_current_playing_song(
         let result = String::from_utf8_lossy(&output.stdout).to_string();
         let parts: Vec<&str> = result.trim().split('\n').collect();
 
-        if parts.len() >= 3 {
+        if parts.len() >= 6 {
+            // Spotify reports the duration in milliseconds, Music in seconds
+            let duration = parts[5]
+                .trim()
+                .replace(",", ".")
+                .parse::<f64>()
+                .ok()
+                .map(|d| if is_spotify { d / 1000.0 } else { d })
+                .filter(|d| *d > 0.0);
+            let optional = |i: usize| {
+                parts
+                    .get(i)
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty() && s != "missing value")
+            };
+
             Ok(Metadata {
                 artist: parts[0].to_string(),
                 title: parts[1].to_string(),
                 album: parts[2].to_string(),
+                duration,
+                art_url: optional(6),
+                track_id: optional(7),
+                album_artists: optional(3).into_iter().collect(),
+                track_number: parts[4].trim().parse::<i32>().ok().filter(|n| *n > 0),
+                artists: vec![parts[0].to_string()],
+                ..Default::default()
             })
         } else {
             Err("Failed to fetch metadata".to_string())
@@ -113,42 +300,31 @@ async fn get_current_playing_song_linux(
     state: tauri::State<'_, AppState>,
 ) -> Result<Metadata, String> {
     // Get the active player first
-    let active_player = get_active_player(app_handle.clone(), state).await?;
-
-    // Use the active player for all metadata commands
-    let artist = command(
-        app_handle,
-        &format!("playerctl -p {} metadata artist", active_player),
-    )
-    .await?;
-    let title = command(
-        app_handle,
-        &format!("playerctl -p {} metadata title", active_player),
-    )
-    .await?;
-    let album = command(
-        app_handle,
-        &format!("playerctl -p {} metadata album", active_player),
-    )
-    .await?;
+    let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
 
-    Ok(Metadata {
-        artist: artist.trim().to_string(),
-        title: title.trim().to_string(),
-        album: album.trim().to_string(),
-    })
+    // A single Metadata property read covers artist, title and album
+    state.mpris.metadata(&active_player).await
 }
 
 #[cfg(target_os = "windows")]
 async fn get_current_playing_song_windows() -> Result<Metadata, String> {
-    let gsmtcsm = get_system_media_transport_controls_session_manager()
-        .await
-        .map_err(|_| "Play a song to see the lyrics".to_string())?;
+    let session = current_session_windows().await?;
+    metadata_windows(&session).await
+}
 
-    let session = gsmtcsm
+#[cfg(target_os = "windows")]
+async fn current_session_windows() -> Result<GlobalSystemMediaTransportControlsSession, String> {
+    get_system_media_transport_controls_session_manager()
+        .await
+        .map_err(|_| "Play a song to see the lyrics".to_string())?
         .GetCurrentSession()
-        .map_err(|_| "Play a song to see the lyrics".to_string())?;
+        .map_err(|_| "Play a song to see the lyrics".to_string())
+}
 
+#[cfg(target_os = "windows")]
+async fn metadata_windows(
+    session: &GlobalSystemMediaTransportControlsSession,
+) -> Result<Metadata, String> {
     let props = session
         .TryGetMediaPropertiesAsync()
         .map_err(|_| "Play a song to see the lyrics".to_string())?
@@ -161,15 +337,73 @@ async fn get_current_playing_song_windows() -> Result<Metadata, String> {
         .AlbumTitle()
         .map(|s| s.to_string())
         .unwrap_or_default();
+    let album_artist = props
+        .AlbumArtist()
+        .map(|s| s.to_string())
+        .unwrap_or_default();
 
     if artist.is_empty() && title.is_empty() {
         return Err("Play a song to see the lyrics".to_string());
     }
 
+    // Convert Windows time (in 100-nanosecond units) to seconds
+    let duration = session.GetTimelineProperties().ok().and_then(|timeline| {
+        let start = timeline.StartTime().ok()?.Duration;
+        let end = timeline.EndTime().ok()?.Duration;
+        Some((end - start) as f64 / 10_000_000.0).filter(|d| *d > 0.0)
+    });
+
     Ok(Metadata {
+        artists: vec![artist.clone()],
         artist,
         title,
         album,
+        duration,
+        album_artists: Some(album_artist)
+            .filter(|s| !s.is_empty())
+            .into_iter()
+            .collect(),
+        track_number: props.TrackNumber().ok().filter(|n| *n > 0),
+        ..Default::default()
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn position_sample_windows(
+    session: &GlobalSystemMediaTransportControlsSession,
+) -> Result<PositionSample, String> {
+    let timeline = session.GetTimelineProperties().map_err(|e| e.to_string())?;
+    let playback_info = session.GetPlaybackInfo().map_err(|e| e.to_string())?;
+    let playing = playback_info.PlaybackStatus().map_err(|e| e.to_string())?
+        == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing;
+    let rate = playback_info
+        .PlaybackRate()
+        .and_then(|rate| rate.Value())
+        .unwrap_or(1.0);
+
+    // Convert Windows time (in 100-nanosecond units) to seconds
+    let mut position =
+        timeline.Position().map_err(|e| e.to_string())?.Duration as f64 / 10_000_000.0;
+
+    // Sessions only refresh the timeline now and then, so account for the
+    // time since the last update
+    if playing {
+        if let Ok(updated) = timeline.LastUpdatedTime() {
+            let since_epoch = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(|e| e.to_string())?;
+            // Windows timestamps count from 1601-01-01
+            let now = (since_epoch.as_secs() + 11_644_473_600) as i64 * 10_000_000
+                + since_epoch.subsec_nanos() as i64 / 100;
+            let elapsed = (now - updated.UniversalTime).max(0) as f64 / 10_000_000.0;
+            position += elapsed * rate;
+        }
+    }
+
+    Ok(PositionSample {
+        position,
+        rate,
+        playing,
     })
 }
 
@@ -217,63 +451,103 @@ async fn get_current_audio_time(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
 ) -> Result<f64, String> {
-    #[cfg(target_os = "linux")]
+    // Get the active player
+    let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+
+    // Between resyncs the clock extrapolates, so most reads never reach the player
     {
-        // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
+        let clocks = state.positions.lock().map_err(|_| "Failed to lock state")?;
+        if let Some(clock) = clocks.get(&active_player) {
+            if !clock.needs_sync(POSITION_RESYNC_INTERVAL) {
+                return Ok(clock.position());
+            }
+        }
+    }
 
-        // Get position only for the active player
-        let output = command(
-            &app_handle,
-            &format!("playerctl -p {} position", active_player),
-        )
-        .await?;
-        let trimmed_output = output.trim();
+    let sample = sample_position(&state, &active_player).await?;
+    let (position, _) = sync_position(&state, &active_player, sample)?;
+    Ok(position)
+}
 
-        if trimmed_output.is_empty() {
-            return Ok(0.00);
+// Feeds a fresh sample into the player's clock. Also reports whether the
+// sample was a discontinuity, i.e. a seek nobody told us about.
+fn sync_position(
+    state: &AppState,
+    player: &str,
+    sample: PositionSample,
+) -> Result<(f64, bool), String> {
+    let mut clocks = state.positions.lock().map_err(|_| "Failed to lock state")?;
+    let (position, jumped, created) = match clocks.get_mut(player) {
+        Some(clock) => {
+            let jumped = clock.sync(sample);
+            (clock.position(), jumped, false)
         }
-
-        match trimmed_output.parse::<f64>() {
-            Ok(position) => Ok(position),
-            Err(_) => Ok(0.00),
+        None => {
+            clocks.insert(player.to_string(), PositionClock::new(sample));
+            (sample.position, false, true)
         }
+    };
+    drop(clocks);
+
+    // Lyric timing runs off the clock, so it has to hear about both
+    if jumped || created {
+        state.lyric_sync.wake();
     }
+    Ok((position, jumped))
+}
 
-    #[cfg(target_os = "windows")]
+async fn sample_position(state: &AppState, player: &str) -> Result<PositionSample, String> {
+    #[cfg(target_os = "linux")]
     {
-        let gsmtcsm = get_system_media_transport_controls_session_manager()
-            .await
-            .map_err(|e| e.to_string())?;
-        let session = gsmtcsm.GetCurrentSession().map_err(|e| e.to_string())?;
-        let timeline = session.GetTimelineProperties().map_err(|e| e.to_string())?;
-        let position = timeline.Position().map_err(|e| e.to_string())?;
+        state.mpris.position_sample(player).await
+    }
 
-        // Convert Windows time (in 100-nanosecond units) to seconds
-        Ok(position.Duration as f64 / 10_000_000.0)
+    #[cfg(target_os = "windows")]
+    {
+        let _ = (state, player);
+        let session = current_session_windows().await?;
+        position_sample_windows(&session)
     }
 
     #[cfg(target_os = "macos")]
     {
-        let active_player = get_active_player(app_handle.clone(), state).await?;
+        let _ = state;
         let output = Command::new("osascript")
             .arg("-e")
             .arg(format!(
-                "tell application \"{}\" to player position as string",
-                active_player
+                "tell application \"{}\"
+                    return (player position as string) & \"\\n\" & (player state as string)
+                end tell",
+                player
             ))
             .output()
             .map_err(|e| e.to_string())?;
 
-        let s = String::from_utf8_lossy(&output.stdout)
+        let result = String::from_utf8_lossy(&output.stdout).to_string();
+        let mut parts = result.trim().split('\n');
+        let position = parts
+            .next()
+            .unwrap_or_default()
             .trim()
-            .replace(",", ".");
-        s.parse::<f64>().map_err(|e| e.to_string())
+            .replace(",", ".")
+            .parse::<f64>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(PositionSample {
+            position,
+            rate: 1.0,
+            playing: parts.next().map(|s| s.trim()) == Some("playing"),
+        })
     }
 
     #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
     {
-        Ok(0.0)
+        let _ = (state, player);
+        Ok(PositionSample {
+            position: 0.0,
+            rate: 1.0,
+            playing: false,
+        })
     }
 }
 
@@ -285,9 +559,8 @@ async fn next_song(
     #[cfg(target_os = "linux")]
     {
         // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
-        command(&app_handle, &format!("playerctl -p {} next", active_player)).await?;
-        Ok(())
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+        state.mpris.next(&active_player).await
     }
 
     #[cfg(target_os = "windows")]
@@ -332,13 +605,8 @@ async fn previous_song(
     #[cfg(target_os = "linux")]
     {
         // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
-        command(
-            &app_handle,
-            &format!("playerctl -p {} previous", active_player),
-        )
-        .await?;
-        Ok(())
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+        state.mpris.previous(&active_player).await
     }
 
     #[cfg(target_os = "windows")]
@@ -383,13 +651,8 @@ async fn toggle_play(
     #[cfg(target_os = "linux")]
     {
         // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
-        command(
-            &app_handle,
-            &format!("playerctl -p {} play-pause", active_player),
-        )
-        .await?;
-        Ok(())
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+        state.mpris.play_pause(&active_player).await
     }
 
     #[cfg(target_os = "windows")]
@@ -434,13 +697,9 @@ async fn is_playing(
     #[cfg(target_os = "linux")]
     {
         // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
-        let status = command(
-            &app_handle,
-            &format!("playerctl -p {} status", active_player),
-        )
-        .await?;
-        Ok(status.trim() == "Playing")
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+        let status = state.mpris.playback_status(&active_player).await?;
+        Ok(status == "Playing")
     }
 
     #[cfg(target_os = "windows")]
@@ -483,13 +742,9 @@ async fn go_to_time(
     #[cfg(target_os = "linux")]
     {
         // Get the active player
-        let active_player = get_active_player(app_handle.clone(), state).await?;
-        command(
-            &app_handle,
-            &format!("playerctl -p {} position {}", active_player, time),
-        )
-        .await?;
-        Ok(())
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
+        state.mpris.set_position(&active_player, time).await?;
+        state.sync_editor.seeked(time)
     }
 
     #[cfg(target_os = "windows")]
@@ -500,7 +755,7 @@ async fn go_to_time(
 
     #[cfg(target_os = "macos")]
     {
-        let active_player = get_active_player(app_handle.clone(), state).await?;
+        let active_player = get_active_player(app_handle.clone(), state.clone()).await?;
         Command::new("osascript")
             .arg("-e")
             .arg(format!(
@@ -509,7 +764,7 @@ async fn go_to_time(
             ))
             .output()
             .map_err(|e| e.to_string())?;
-        Ok(())
+        state.sync_editor.seeked(time)
     }
 
     #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
@@ -520,13 +775,9 @@ async fn go_to_time(
 
 #[tauri::command]
 #[cfg(target_os = "linux")]
-async fn check_if_playerctl_exists() -> Result<bool, String> {
-    let output = Command::new("playerctl")
-        .arg("--version")
-        .output()
-        .map_err(|e| e.to_string())?;
-
-    Ok(output.status.success())
+async fn check_if_playerctl_exists(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    // Media control goes through MPRIS directly, so all that's needed is a session bus
+    Ok(state.mpris.connection().await.is_ok())
 }
 
 #[tauri::command]
@@ -546,6 +797,21 @@ async fn check_if_playerctl_exists() -> Result<bool, String> {
 async fn get_active_player(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let (cached, generation) = state.active_player.get();
+    if let Some(player) = cached {
+        return Ok(player);
+    }
+    let player = resolve_active_player(app_handle, state.clone()).await?;
+    state.active_player.store(generation, &player);
+    Ok(player)
+}
+
+// Works out the active player from scratch, asking every player for its
+// status.
+async fn resolve_active_player(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
 ) -> Result<String, String> {
     // Check if a player is manually selected
     let selected = {
@@ -558,7 +824,7 @@ async fn get_active_player(
 
     if let Some(player) = selected {
         // Validate if the selected player is actually available
-        let available = get_available_players(app_handle.clone()).await?;
+        let available = get_available_players(app_handle.clone(), state.clone()).await?;
         if available.contains(&player) {
             return Ok(player);
         }
@@ -567,8 +833,7 @@ async fn get_active_player(
     #[cfg(target_os = "linux")]
     {
         // First try to get the player that's currently playing
-        let status_output = command(&app_handle, "playerctl -l").await?;
-        let players = status_output.trim().lines().collect::<Vec<&str>>();
+        let players = state.mpris.list_players().await?;
 
         // If no players are available, return an error
         if players.is_empty() {
@@ -577,10 +842,10 @@ async fn get_active_player(
 
         // Try to find a player that's currently playing
         for player in &players {
-            let status = command(&app_handle, &format!("playerctl -p {} status", player)).await;
+            let status = state.mpris.playback_status(player).await;
 
             if let Ok(status) = status {
-                if status.trim() == "Playing" {
+                if status == "Playing" {
                     return Ok(player.to_string());
                 }
             }
@@ -657,23 +922,22 @@ async fn set_active_player(
         .lock()
         .map_err(|_| "Failed to lock state")?;
     *selected_player = Some(player);
+    state.active_player.invalidate();
     Ok(())
 }
 
 #[tauri::command]
-async fn get_available_players(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
-    #[cfg(target_os = "macos")]
-    let _ = app_handle;
+async fn get_available_players(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    #[cfg(not(target_os = "linux"))]
+    let _ = (app_handle, state);
 
     #[cfg(target_os = "linux")]
     {
-        let status_output = command(&app_handle, "playerctl -l").await?;
-        let players: Vec<String> = status_output
-            .trim()
-            .lines()
-            .map(|s| s.to_string())
-            .collect();
-        Ok(players)
+        let _ = app_handle;
+        state.mpris.list_players().await
     }
 
     #[cfg(target_os = "windows")]
@@ -707,38 +971,603 @@ async fn get_available_players(app_handle: tauri::AppHandle) -> Result<Vec<Strin
 }
 
 #[tauri::command]
-async fn fetch_url(url: String) -> Result<String, String> {
-    let response = reqwest::get(&url).await.map_err(|e| e.to_string())?;
-    response.text().await.map_err(|e| e.to_string())
+async fn fetch_lyrics(
+    artist: String,
+    title: String,
+    album: Option<String>,
+    duration: Option<f64>,
+    url: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::LyricsResult, String> {
+    let query = lyrics::TrackQuery {
+        artist,
+        title,
+        album,
+        duration,
+        path: url.as_deref().and_then(local_path),
+    };
+    let result = state.lyrics.fetch(&query).await?;
+
+    // Followed only if they're for what's playing, which may have changed
+    // while they were fetched. Not being able to follow them doesn't make
+    // them any less worth showing.
+    let track_key = lyrics::cache::entry_id(&query);
+    let playing = playing_track_key(app_handle.clone(), state.clone()).await;
+    if playing.as_deref() == Ok(track_key.as_str()) {
+        let parsed = result.as_ref().and_then(|result| result.parsed.clone());
+        if let Err(e) = follow_lyrics(app_handle, &state, &track_key, parsed).await {
+            eprintln!("Failed to follow lyrics: {}", e);
+        }
+    }
+
+    result.ok_or_else(|| "No lyrics found for this track".to_string())
+}
+
+// Every candidate the providers have for the search terms, for when the
+// automatic match is wrong. Picks are pinned to the playing track, even if
+// the terms were reworded to find it.
+#[tauri::command]
+async fn search_lyrics(
+    artist: String,
+    title: String,
+    album: Option<String>,
+    duration: Option<f64>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::SearchResults, String> {
+    let playing = get_current_playing_song(app_handle, state.clone())
+        .await
+        .ok()
+        .filter(|metadata| !metadata.title.trim().is_empty())
+        .map(|metadata| track_query(&metadata));
+    let query = lyrics::TrackQuery {
+        artist,
+        title,
+        album,
+        duration,
+        path: playing.as_ref().and_then(|track| track.path.clone()),
+    };
+    let track = playing.unwrap_or_else(|| query.clone());
+    state.lyrics.search(track, &query).await
+}
+
+// Uses a candidate from the last search for its track from now on, and
+// starts following its lyrics.
+#[tauri::command]
+async fn pin_lyrics(
+    track_key: String,
+    candidate_id: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::LyricsResult, String> {
+    let result = state.lyrics.pin(&track_key, &candidate_id).await?;
+    follow_lyrics(app_handle, &state, &track_key, result.parsed.clone()).await?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn blacklist_lyrics(
+    track_key: String,
+    candidate_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.lyrics.blacklist(&track_key, &candidate_id).await
+}
+
+// Takes lyrics the user already has, from a file or pasted text, and pins
+// them to the playing track so they're used for it from now on.
+#[tauri::command]
+async fn import_lyrics(
+    path: Option<String>,
+    text: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::LyricsResult, String> {
+    let text = match (path, text) {
+        (Some(path), _) => lyrics::import::read_file(Path::new(&path)).await?,
+        (None, Some(text)) => text,
+        (None, None) => return Err("Nothing to import".to_string()),
+    };
+    let result = lyrics::import::import(&text)?;
+
+    let metadata = get_current_playing_song(app_handle.clone(), state.clone()).await?;
+    if metadata.artist.trim().is_empty() || metadata.title.trim().is_empty() {
+        return Err("Play a song to import lyrics for it".to_string());
+    }
+    let track = track_query(&metadata);
+    state.lyrics.cache().pin(&track, &result).await?;
+
+    let track_key = lyrics::cache::entry_id(&track);
+    follow_lyrics(app_handle, &state, &track_key, result.parsed.clone()).await?;
+    Ok(result)
+}
+
+// Follows lyrics the webview already had, e.g. from its own cache, as if
+// they'd just been fetched.
+#[tauri::command]
+async fn set_current_lyrics(
+    synced: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let parsed = synced.map(|synced| lyrics::lrc::parse(&synced).lyrics);
+    let track_key = playing_track_key(app_handle.clone(), state.clone()).await?;
+    follow_lyrics(app_handle, &state, &track_key, parsed).await
+}
+
+// Starts following `lyrics` on the active player, shifted by the offsets
+// saved for the track.
+async fn follow_lyrics(
+    app_handle: tauri::AppHandle,
+    state: &tauri::State<'_, AppState>,
+    track_key: &str,
+    lyrics: Option<lyrics::lrc::Lyrics>,
+) -> Result<(), String> {
+    let player = get_active_player(app_handle, state.clone()).await?;
+    let offset = state.offsets.get(track_key).await.seconds();
+    state.lyric_sync.set_lyrics(player, lyrics, offset);
+    Ok(())
+}
+
+async fn playing_track_key(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let metadata = get_current_playing_song(app_handle, state).await?;
+    Ok(lyrics::cache::entry_id(&track_query(&metadata)))
+}
+
+#[tauri::command]
+async fn get_lyrics_offset(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::offsets::TrackOffset, String> {
+    let track_key = playing_track_key(app_handle, state.clone()).await?;
+    Ok(state.offsets.get(&track_key).await)
+}
+
+// Moves the playing track's lyrics, or every track's, by `steps` of 100ms.
+// Positive steps show lines earlier.
+#[tauri::command]
+async fn nudge_lyrics_offset(
+    scope: lyrics::offsets::OffsetScope,
+    steps: i64,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::offsets::TrackOffset, String> {
+    adjust_lyrics_offset(scope, Some(steps), app_handle, state).await
+}
+
+#[tauri::command]
+async fn reset_lyrics_offset(
+    scope: lyrics::offsets::OffsetScope,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::offsets::TrackOffset, String> {
+    adjust_lyrics_offset(scope, None, app_handle, state).await
+}
+
+async fn adjust_lyrics_offset(
+    scope: lyrics::offsets::OffsetScope,
+    steps: Option<i64>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::offsets::TrackOffset, String> {
+    let track_key = playing_track_key(app_handle, state.clone()).await?;
+    let offset = state.offsets.adjust(&track_key, scope, steps).await?;
+    state.lyric_sync.set_offset(offset.seconds());
+    Ok(offset)
+}
+
+// Opens the sync editor on `plain` lyrics for the playing track.
+#[tauri::command]
+async fn start_sync_editor(
+    plain: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::editor::EditorState, String> {
+    let metadata = get_current_playing_song(app_handle, state.clone()).await?;
+    if metadata.artist.trim().is_empty() || metadata.title.trim().is_empty() {
+        return Err("Play a song to sync lyrics for it".to_string());
+    }
+    state.sync_editor.start(track_query(&metadata), &plain)
 }
 
+// Stamps the next line with the position lyrics are shown against.
 #[tauri::command]
-async fn fetch_image_base64(url: String) -> Result<String, String> {
-    let response = reqwest::get(&url).await.map_err(|e| e.to_string())?;
-    let content_type = response
-        .headers()
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("image/jpeg")
-        .to_string();
-    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+async fn tap_sync_editor(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::editor::EditorState, String> {
+    let position = get_current_audio_time(app_handle, state.clone()).await?;
+    state.sync_editor.tap(position)
+}
+
+#[tauri::command]
+fn undo_sync_editor(
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::editor::EditorState, String> {
+    state.sync_editor.undo()
+}
+
+#[tauri::command]
+fn close_sync_editor(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.sync_editor.close()
+}
+
+// Saves the editor's lyrics as LRC: to `path` when given, otherwise pinned in
+// the cache for the track they were made for, and followed if it's still
+// playing.
+#[tauri::command]
+async fn save_sync_editor(
+    path: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::LyricsResult, String> {
+    let (track, lyrics) = state.sync_editor.lyrics()?;
+    let result = lyrics::lyrics_from_model(lyrics, lyrics::editor::PROVIDER);
+
+    if let Some(path) = path {
+        let mut path = PathBuf::from(path);
+        if path.extension().is_none() {
+            path.set_extension("lrc");
+        }
+        let text = result.synced.clone().unwrap_or_default();
+        tokio::fs::write(&path, text)
+            .await
+            .map_err(|e| e.to_string())?;
+        return Ok(result);
+    }
+
+    state.lyrics.cache().pin(&track, &result).await?;
+    let track_key = lyrics::cache::entry_id(&track);
+    if playing_track_key(app_handle.clone(), state.clone()).await? == track_key {
+        follow_lyrics(app_handle, &state, &track_key, result.parsed.clone()).await?;
+    }
+    Ok(result)
+}
+
+// The first half of publishing `synced` (LRC) to lrclib for the playing
+// track, with its saved offsets applied: shows what would be sent, without
+// sending anything.
+#[tauri::command]
+async fn prepare_publish(
+    synced: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::publish::PublishDraft, String> {
+    let metadata = get_current_playing_song(app_handle, state.clone()).await?;
+    if metadata.artist.trim().is_empty() || metadata.title.trim().is_empty() {
+        return Err("Play a song to publish lyrics for it".to_string());
+    }
+    let track = track_query(&metadata);
+    let mut parsed = lyrics::lrc::parse(&synced).lyrics;
+    let offset = state.offsets.get(&lyrics::cache::entry_id(&track)).await;
+    parsed.offset += offset.seconds();
+
+    let lrclib = lrclib_client(&state)?;
+    state.publisher.prepare(&lrclib, &track, &parsed)
+}
+
+// Sends the draft the user confirmed.
+#[tauri::command]
+async fn publish_lyrics(draft_id: u64, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.http.ensure_online()?;
+    let lrclib = lrclib_client(&state)?;
+    state.publisher.publish(&lrclib, draft_id).await
+}
+
+fn lrclib_client(state: &AppState) -> Result<lyrics::Lrclib, String> {
+    let settings = state.settings.lock().map_err(|_| "Failed to lock settings")?;
+    Ok(lyrics::Lrclib::from_settings(&settings, &state.http.client()?))
+}
+
+// Parses LRC text with the same parser the backend uses, reporting any lines
+// it had to skip.
+#[tauri::command]
+fn parse_lyrics(text: String) -> lyrics::lrc::Parsed {
+    lyrics::lrc::parse(&text)
+}
+
+// Writes `lyrics` (LRC) to `path` as `format`, with the offsets saved for
+// `track` added to theirs. The track's details fill in header tags the
+// lyrics don't have. A path without an extension gets the format's. Returns
+// the path written.
+#[tauri::command]
+async fn export_lyrics(
+    lyrics: String,
+    format: lyrics::export::ExportFormat,
+    path: String,
+    track: lyrics::export::ExportTrack,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let mut parsed = lyrics::lrc::parse(&lyrics).lyrics;
+    if parsed.lines.is_empty() {
+        return Err("No synced lyrics to export".to_string());
+    }
+    let query = lyrics::TrackQuery {
+        artist: track.artist.clone().unwrap_or_default(),
+        title: track.title.clone().unwrap_or_default(),
+        album: track.album.clone(),
+        duration: track.duration,
+        path: None,
+    };
+    let offset = state.offsets.get(&lyrics::cache::entry_id(&query)).await;
+    parsed.offset += offset.seconds();
+    let present = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+    parsed.title = parsed.title.or(present(track.title));
+    parsed.artist = parsed.artist.or(present(track.artist));
+    parsed.album = parsed.album.or(present(track.album));
+    parsed.length = parsed.length.or(track.duration.filter(|d| *d > 0.0));
+    parsed
+        .tags
+        .entry("re".to_string())
+        .or_insert_with(|| "ohun".to_string());
+
+    let mut path = PathBuf::from(path);
+    if path.extension().is_none() {
+        path.set_extension(format.extension());
+    }
+    let text = lyrics::export::export(&parsed, format);
+    tokio::fs::write(&path, text)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+async fn list_cached_lyrics(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<lyrics::cache::CacheSummary>, String> {
+    state.lyrics.cache().list().await
+}
+
+#[tauri::command]
+async fn inspect_cached_lyrics(
+    id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<lyrics::cache::CacheEntry, String> {
+    state.lyrics.cache().inspect(&id).await
+}
+
+// Drops one cached track, or the whole cache without an id. Returns how many
+// entries were removed.
+#[tauri::command]
+async fn purge_cached_lyrics(
+    id: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize, String> {
+    state.lyrics.cache().purge(id.as_deref()).await
+}
+
+// Takes over the caches older versions kept in localStorage, given all of
+// its items. Safe to run more than once; entries already imported are
+// counted as duplicates.
+#[tauri::command]
+async fn import_legacy_cache(
+    storage: HashMap<String, String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<migration::ImportReport, String> {
+    Ok(migration::import(storage, state.lyrics.cache(), &state.art).await)
+}
+
+#[tauri::command]
+async fn get_cached_art(
+    key: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    Ok(state.art.art(&key).await)
+}
+
+#[tauri::command]
+async fn cache_art(
+    key: String,
+    data_url: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.art.put_art(&key, &data_url, true).await.map(|_| ())
+}
+
+#[tauri::command]
+async fn get_cached_accent_color(
+    key: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    Ok(state.art.accent_color(&key).await)
+}
+
+#[tauri::command]
+async fn cache_accent_color(
+    key: String,
+    color: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state
+        .art
+        .put_accent_colors([(key, color)], true)
+        .await
+        .map(|_| ())
+}
+
+// Writes synced lyrics into the tags of the local file behind `url`, backing
+// the file up first. Returns where the backup is.
+#[tauri::command]
+async fn embed_lyrics(
+    url: String,
+    lyrics: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let path = local_path(&url).ok_or("Lyrics can only be embedded in local files")?;
+    let parsed = lyrics::lrc::parse(&lyrics).lyrics;
+    if parsed.lines.is_empty() {
+        return Err("No synced lyrics to embed".to_string());
+    }
+    let backups = state.data_dir.join(tags::BACKUP_DIR);
+
+    tokio::task::spawn_blocking(move || {
+        let backup = tags::backup(&path, &backups)?;
+        tags::write_lyrics(&path, &parsed)?;
+        Ok(backup.to_string_lossy().into_owned())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Starts going through every audio file under `dir`, doing `action` for
+// each. Progress comes as `library-scan-progress` events.
+#[tauri::command]
+async fn start_library_scan(
+    dir: String,
+    action: library::LibraryAction,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    library::start(app_handle, PathBuf::from(dir), action)
+}
+
+#[tauri::command]
+async fn cancel_library_scan(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.library.cancel();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_settings(state: tauri::State<'_, AppState>) -> Result<Settings, String> {
+    let settings = state.settings.lock().map_err(|_| "Failed to lock settings")?;
+    Ok(settings.clone())
+}
+
+#[tauri::command]
+async fn update_settings(
+    settings: Settings,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    // First, so a bad proxy is turned down before it's saved
+    state.http.configure(&settings)?;
+    settings::save(&state.config_dir, &settings)?;
+    state
+        .lyrics
+        .configure(lyrics::build_chain(&settings, &state.http.client()?));
+    state
+        .lyrics
+        .cache()
+        .set_max_size(settings.lyrics_cache_size());
+    *state.settings.lock().map_err(|_| "Failed to lock settings")? = settings;
+    Ok(())
+}
+
+// Only reaches lyric and art hosts; see `http`.
+#[tauri::command]
+async fn fetch_url(url: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    state.http.fetch_text(&url).await
+}
+
+#[tauri::command]
+async fn fetch_image_base64(
+    url: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    state.http.fetch_image(&url).await
+}
+
+// Cover art published by the player itself. Local `file://` art is read from
+// disk, anything else is fetched. Returns `None` when the current track has
+// no art, so the caller can fall back to searching for it.
+#[tauri::command]
+async fn get_track_art(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    let metadata = get_current_playing_song(app_handle, state.clone()).await?;
+    let Some(art_url) = metadata.art_url else {
+        return Ok(None);
+    };
+
+    let Some(path) = local_path(&art_url) else {
+        return state.http.fetch_image(&art_url).await.map(Some);
+    };
+    let bytes = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let content_type = match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    };
     use base64::Engine;
     let b64 = base64::engine::general_purpose::STANDARD.encode(&bytes);
-    Ok(format!("data:{};base64,{}", content_type, b64))
+    Ok(Some(format!("data:{};base64,{}", content_type, b64)))
+}
+
+// The lookup the webview makes for `metadata` when it fetches lyrics.
+fn track_query(metadata: &Metadata) -> lyrics::TrackQuery {
+    lyrics::TrackQuery {
+        artist: metadata.artist.clone(),
+        title: metadata.title.clone(),
+        album: Some(metadata.album.clone()).filter(|album| !album.is_empty()),
+        duration: metadata.duration.filter(|duration| *duration > 0.0),
+        path: metadata.url.as_deref().and_then(local_path),
+    }
+}
+
+// The file behind a `file://` URL, as players report for local tracks.
+fn local_path(url: &str) -> Option<PathBuf> {
+    url::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "file")?
+        .to_file_path()
+        .ok()
 }
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_os::init())
-        .manage(AppState {
-            previous_positions: Mutex::new(HashMap::new()),
-            selected_player: Mutex::new(None),
-        })
         .plugin(tauri_plugin_shell::init())
 		.plugin(tauri_plugin_notification::init())
 		.plugin(tauri_plugin_liquid_glass::init())
+        .setup(|app| {
+            // Settings live in the config dir, so the state can only be
+            // built once the app knows where that is
+            let config_dir = app.path().app_config_dir()?;
+            let settings = settings::load(&config_dir);
+            let http = http::Http::new(&settings);
+            let data_dir = app.path().app_data_dir()?;
+            let lyrics_cache = lyrics::cache::LyricsCache::new(
+                data_dir.join("lyrics"),
+                settings.lyrics_cache_size(),
+            );
+            app.manage(AppState {
+                positions: Mutex::new(HashMap::new()),
+                selected_player: Mutex::new(None),
+                active_player: active_player::ActivePlayer::default(),
+                #[cfg(target_os = "linux")]
+                mpris: mpris::MprisClient::default(),
+                lyrics: lyrics::LyricsService::new(
+                    lyrics::build_chain(&settings, &http.client()?),
+                    lyrics_cache,
+                    lyrics::blacklist::Blacklist::new(data_dir.join("lyrics-blacklist.json")),
+                ),
+                offsets: lyrics::offsets::Offsets::new(data_dir.join("lyric-offsets.json")),
+                art: art_cache::ArtCache::new(data_dir.join("art")),
+                lyric_sync: lyric_sync::LyricSync::default(),
+                sync_editor: lyrics::editor::SyncEditor::default(),
+                publisher: lyrics::publish::Publisher::default(),
+                library: library::LibraryJob::default(),
+                settings: Mutex::new(settings),
+                config_dir,
+                data_dir,
+                http,
+            });
+            events::spawn_watcher(app.handle().clone());
+            lyric_sync::spawn(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_current_playing_song,
+            get_player_snapshot,
             get_current_audio_time,
             next_song,
             previous_song,
@@ -750,7 +1579,39 @@ fn main() {
             set_active_player,
             get_available_players,
             fetch_url,
-            fetch_image_base64
+            fetch_image_base64,
+            get_track_art,
+            fetch_lyrics,
+            set_current_lyrics,
+            import_lyrics,
+            search_lyrics,
+            pin_lyrics,
+            blacklist_lyrics,
+            get_lyrics_offset,
+            nudge_lyrics_offset,
+            start_sync_editor,
+            tap_sync_editor,
+            undo_sync_editor,
+            close_sync_editor,
+            save_sync_editor,
+            prepare_publish,
+            publish_lyrics,
+            reset_lyrics_offset,
+            parse_lyrics,
+            export_lyrics,
+            list_cached_lyrics,
+            inspect_cached_lyrics,
+            purge_cached_lyrics,
+            import_legacy_cache,
+            get_cached_art,
+            cache_art,
+            get_cached_accent_color,
+            cache_accent_color,
+            embed_lyrics,
+            start_library_scan,
+            cancel_library_scan,
+            get_settings,
+            update_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,300 @@
+// One-time import of the caches older versions kept in the webview's
+// localStorage: lyrics under `lyrics_<artist><title>`, accent colors under
+// `offline_<artist><title>accentColor`, both stored by lockr, and album art
+// in the persisted `cachedAlbumArt` store. The webview hands over its whole
+// localStorage in one batch and removes the keys picked out here once
+// they're imported; anything the backend already has is left as it is.
+
+use base64::Engine;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::art_cache::ArtCache;
+use crate::lyrics::{self, cache::LyricsCache};
+
+const LYRICS_PREFIX: &str = "lyrics_";
+const ACCENT_COLOR_PREFIX: &str = "offline_";
+const ACCENT_COLOR_SUFFIX: &str = "accentColor";
+const ALBUM_ART_STORE: &str = "cachedAlbumArt";
+
+// The old caches, with the localStorage prefixes and suffixes stripped from
+// the keys
+#[derive(Default)]
+struct LegacyCache {
+    // `<artist><title>` to LRC text
+    lyrics: HashMap<String, String>,
+    // `<artist><title>` to a CSS color
+    accent_colors: HashMap<String, String>,
+    // `<artist>-<title>-<album>` to a data URL
+    album_art: HashMap<String, String>,
+    // The localStorage keys they came from
+    keys: Vec<String>,
+}
+
+impl LegacyCache {
+    // Picks the caches out of localStorage's items, raw values by key.
+    // Values that can't be read are kept empty, to be counted as invalid.
+    fn from_storage(storage: HashMap<String, String>) -> Self {
+        let mut cache = LegacyCache::default();
+        for (key, raw) in storage {
+            if let Some(name) = key.strip_prefix(LYRICS_PREFIX) {
+                let value = lockr_value(&raw).unwrap_or_default();
+                cache.lyrics.insert(name.to_string(), value);
+            } else if let Some(name) = key
+                .strip_prefix(ACCENT_COLOR_PREFIX)
+                .and_then(|rest| rest.strip_suffix(ACCENT_COLOR_SUFFIX))
+            {
+                let value = lockr_value(&raw).unwrap_or_default();
+                cache.accent_colors.insert(name.to_string(), value);
+            } else if key == ALBUM_ART_STORE {
+                match serde_json::from_str::<HashMap<String, serde_json::Value>>(&raw) {
+                    Ok(art) => {
+                        cache.album_art = art
+                            .into_iter()
+                            .map(|(key, url)| (key, url.as_str().unwrap_or_default().to_string()))
+                            .collect()
+                    }
+                    Err(e) => eprintln!("Failed to read cached album art: {}", e),
+                }
+            } else {
+                continue;
+            }
+            cache.keys.push(key);
+        }
+        cache
+    }
+}
+
+// lockr keeps each value as JSON wrapped in `{"data": ...}`; older releases
+// stored it bare. Only strings were ever cached.
+fn lockr_value(raw: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(raw).ok()?;
+    let value = match value {
+        serde_json::Value::Object(mut object) => object.remove("data")?,
+        value => value,
+    };
+    value.as_str().map(str::to_string)
+}
+
+// `data:image/<type>;base64,<payload>`, with a payload that decodes.
+fn is_image_data_url(url: &str) -> bool {
+    let payload = url
+        .strip_prefix("data:image/")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .map(|(_, payload)| payload);
+    payload.is_some_and(|payload| {
+        !payload.is_empty()
+            && base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .is_ok()
+    })
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub lyrics: usize,
+    pub accent_colors: usize,
+    pub album_art: usize,
+    // Already in the backend's caches, or repeated within the batch
+    pub duplicates: usize,
+    // Empty or unreadable entries, e.g. art that isn't a data URL
+    pub invalid: usize,
+    // Entries that couldn't be written. The webview keeps its copy when
+    // this isn't empty.
+    pub errors: Vec<String>,
+    // The localStorage keys the caches were under, for the webview to
+    // remove
+    pub keys: Vec<String>,
+}
+
+// Imports the caches found among `storage`'s items.
+pub async fn import(
+    storage: HashMap<String, String>,
+    lyrics_cache: &LyricsCache,
+    art_cache: &ArtCache,
+) -> ImportReport {
+    let legacy = LegacyCache::from_storage(storage);
+    let mut report = ImportReport {
+        keys: legacy.keys,
+        ..Default::default()
+    };
+
+    for (key, text) in legacy.lyrics {
+        let Some(mut result) = lyrics::lyrics_from_text(text, "imported") else {
+            report.invalid += 1;
+            continue;
+        };
+        result.parsed = result
+            .synced
+            .as_deref()
+            .map(|synced| lyrics::lrc::parse(synced).lyrics);
+        match lyrics_cache.import_legacy(&key, result).await {
+            Ok(true) => report.lyrics += 1,
+            Ok(false) => report.duplicates += 1,
+            Err(e) => report.errors.push(format!("Lyrics for '{}': {}", key, e)),
+        }
+    }
+
+    let total = legacy.accent_colors.len();
+    let colors: Vec<(String, String)> = legacy
+        .accent_colors
+        .into_iter()
+        .filter(|(_, color)| !color.trim().is_empty())
+        .collect();
+    report.invalid += total - colors.len();
+    let valid = colors.len();
+    match art_cache.put_accent_colors(colors, false).await {
+        Ok(stored) => {
+            report.accent_colors = stored;
+            report.duplicates += valid - stored;
+        }
+        Err(e) => report.errors.push(format!("Accent colors: {}", e)),
+    }
+
+    for (key, data_url) in legacy.album_art {
+        if !is_image_data_url(&data_url) {
+            report.invalid += 1;
+            continue;
+        }
+        match art_cache.put_art(&key, &data_url, false).await {
+            Ok(true) => report.album_art += 1,
+            Ok(false) => report.duplicates += 1,
+            Err(e) => report
+                .errors
+                .push(format!("Album art for '{}': {}", key, e)),
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::TrackQuery;
+    use crate::test_files::fixture_dir;
+
+    fn storage(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn lockr_keys_are_picked_out_of_local_storage() {
+        let legacy = LegacyCache::from_storage(storage(&[
+            ("lyrics_ArtistSong", r#"{"data":"[00:01.00]Hello"}"#),
+            ("lyrics_Bare", r#""Plain words""#),
+            ("lyrics_Broken", "{"),
+            ("offline_ArtistSongaccentColor", r##"{"data":"#123456"}"##),
+            ("offline_Other", r##"{"data":"#654321"}"##),
+            ("settings", r#"{"data":"kept"}"#),
+        ]));
+
+        assert_eq!(legacy.lyrics.len(), 3);
+        assert_eq!(legacy.lyrics["ArtistSong"], "[00:01.00]Hello");
+        assert_eq!(legacy.lyrics["Bare"], "Plain words");
+        assert_eq!(legacy.lyrics["Broken"], "");
+        assert_eq!(
+            legacy.accent_colors,
+            HashMap::from([("ArtistSong".to_string(), "#123456".to_string())])
+        );
+        let mut keys = legacy.keys;
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "lyrics_ArtistSong",
+                "lyrics_Bare",
+                "lyrics_Broken",
+                "offline_ArtistSongaccentColor"
+            ]
+        );
+
+        assert_eq!(lockr_value(r#"{"data":3}"#), None);
+        assert_eq!(lockr_value(r#"{"other":"x"}"#), None);
+    }
+
+    #[test]
+    fn album_art_must_be_a_decodable_image_data_url() {
+        assert!(is_image_data_url("data:image/png;base64,iVBORw0KGgo="));
+        assert!(!is_image_data_url("data:image/png;base64,"));
+        assert!(!is_image_data_url("data:image/png;base64,not base64!"));
+        assert!(!is_image_data_url("data:image/png,iVBORw0KGgo="));
+        assert!(!is_image_data_url("data:text/plain;base64,aGVsbG8="));
+        assert!(!is_image_data_url("https://example.com/art.png"));
+
+        let legacy = LegacyCache::from_storage(storage(&[(
+            "cachedAlbumArt",
+            r#"{"A-B-C":"data:image/png;base64,iVBORw0KGgo=","D-E-F":7}"#,
+        )]));
+        assert_eq!(
+            legacy.album_art["A-B-C"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+        assert_eq!(legacy.album_art["D-E-F"], "");
+        assert_eq!(legacy.keys, ["cachedAlbumArt"]);
+    }
+
+    #[tokio::test]
+    async fn entries_already_there_are_left_alone() {
+        let dir = fixture_dir("migration", &[]);
+        let lyrics_cache = LyricsCache::new(dir.join("lyrics"), u64::MAX);
+        let art_cache = ArtCache::new(dir.join("art"));
+        let existing = lyrics::lyrics_from_text("Newer".to_string(), "local").unwrap();
+        lyrics_cache
+            .import_legacy("ArtistSong", existing)
+            .await
+            .unwrap();
+        art_cache
+            .put_art("A-B-C", "data:image/png;base64,AAAA", false)
+            .await
+            .unwrap();
+        art_cache
+            .put_accent_colors([("ArtistSong".to_string(), "#000000".to_string())], false)
+            .await
+            .unwrap();
+
+        let report = import(
+            storage(&[
+                ("lyrics_ArtistSong", r#"{"data":"Older"}"#),
+                ("lyrics_OtherSong", r#"{"data":"[00:01.00]Hello"}"#),
+                ("lyrics_Empty", r#"{"data":"  "}"#),
+                ("offline_ArtistSongaccentColor", r##"{"data":"#ffffff"}"##),
+                (
+                    "cachedAlbumArt",
+                    r#"{"A-B-C":"data:image/png;base64,iVBORw0KGgo=","G-H-I":"nope"}"#,
+                ),
+            ]),
+            &lyrics_cache,
+            &art_cache,
+        )
+        .await;
+
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.lyrics, 1);
+        assert_eq!(report.accent_colors, 0);
+        assert_eq!(report.album_art, 0);
+        assert_eq!(report.duplicates, 3);
+        assert_eq!(report.invalid, 2);
+        assert_eq!(report.keys.len(), 5);
+
+        let query = TrackQuery {
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            ..Default::default()
+        };
+        let kept = lyrics_cache.get(&query).await.unwrap();
+        assert_eq!(kept.plain.as_deref(), Some("Newer"));
+        assert_eq!(
+            art_cache.art("A-B-C").await.as_deref(),
+            Some("data:image/png;base64,AAAA")
+        );
+        assert_eq!(
+            art_cache.accent_color("ArtistSong").await.as_deref(),
+            Some("#000000")
+        );
+    }
+}
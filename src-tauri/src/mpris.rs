@@ -0,0 +1,435 @@
+// Native MPRIS client used on Linux. Talks to `org.mpris.MediaPlayer2.*`
+// over the session bus directly instead of spawning a playerctl process for
+// every query.
+
+use futures_util::stream::{select_all, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+use zbus::fdo::{DBusProxy, PropertiesProxy};
+use zbus::message::Type as MessageType;
+use zbus::names::{BusName, InterfaceName};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{proxy, CacheProperties, Connection, MatchRule, MessageStream};
+
+use crate::events::PlayerEvent;
+use crate::position::PositionSample;
+use crate::{Capabilities, Metadata, PlayerSnapshot};
+
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+// Interface name, changed properties, invalidated properties
+type PropertiesChangedArgs = (String, HashMap<String, OwnedValue>, Vec<String>);
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait MediaPlayer {
+    fn next(&self) -> zbus::Result<()>;
+
+    fn previous(&self) -> zbus::Result<()>;
+
+    fn play_pause(&self) -> zbus::Result<()>;
+
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+
+    fn set_position(&self, track_id: &ObjectPath<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    #[zbus(property)]
+    fn position(&self) -> zbus::Result<i64>;
+
+    #[zbus(property)]
+    fn rate(&self) -> zbus::Result<f64>;
+}
+
+#[derive(Default)]
+pub struct MprisClient {
+    connection: OnceCell<Connection>,
+}
+
+impl MprisClient {
+    // The session bus connection is opened on first use and shared afterwards.
+    pub async fn connection(&self) -> Result<Connection, String> {
+        self.connection
+            .get_or_try_init(Connection::session)
+            .await
+            .cloned()
+            .map_err(|e| format!("Failed to connect to the session bus: {}", e))
+    }
+
+    // Player names are returned without the `org.mpris.MediaPlayer2.` prefix,
+    // matching what `playerctl -l` prints.
+    pub async fn list_players(&self) -> Result<Vec<String>, String> {
+        let connection = self.connection().await?;
+        let dbus = DBusProxy::new(&connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        let names = dbus.list_names().await.map_err(|e| e.to_string())?;
+
+        Ok(names
+            .iter()
+            .filter_map(|name| name.as_str().strip_prefix(BUS_NAME_PREFIX))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    async fn player(&self, player: &str) -> Result<MediaPlayerProxy<'static>, String> {
+        let connection = self.connection().await?;
+        MediaPlayerProxy::builder(&connection)
+            .destination(format!("{}{}", BUS_NAME_PREFIX, player))
+            .map_err(|e| e.to_string())?
+            // Position never emits PropertiesChanged, so a cache would go stale.
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn metadata(&self, player: &str) -> Result<Metadata, String> {
+        let metadata = self
+            .player(player)
+            .await?
+            .metadata()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(metadata_from_map(&metadata))
+    }
+
+    // Position in seconds along with what's needed to extrapolate it.
+    // Players that don't report a position or rate are treated as being at
+    // the start and playing at normal speed.
+    pub async fn position_sample(&self, player: &str) -> Result<PositionSample, String> {
+        let proxy = self.player(player).await?;
+        let status = proxy.playback_status().await.map_err(|e| e.to_string())?;
+        let position = proxy.position().await.unwrap_or(0);
+        let rate = proxy.rate().await.unwrap_or(1.0);
+
+        Ok(PositionSample {
+            position: position as f64 / 1_000_000.0,
+            rate,
+            playing: status == "Playing",
+        })
+    }
+
+    pub async fn playback_status(&self, player: &str) -> Result<String, String> {
+        self.player(player)
+            .await?
+            .playback_status()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // Everything the UI shows about a player, read with a single GetAll call.
+    pub async fn snapshot(&self, player: &str) -> Result<PlayerSnapshot, String> {
+        let connection = self.connection().await?;
+        let properties = PropertiesProxy::builder(&connection)
+            .destination(format!("{}{}", BUS_NAME_PREFIX, player))
+            .and_then(|b| b.path(OBJECT_PATH))
+            .map_err(|e| e.to_string())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .map_err(|e| e.to_string())?;
+        let interface = InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE);
+        let mut all = properties
+            .get_all(Some(interface).into())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let metadata = all
+            .remove("Metadata")
+            .and_then(|value| HashMap::<String, OwnedValue>::try_from(value).ok())
+            .map(|map| metadata_from_map(&map))
+            .unwrap_or_default();
+        let flag = |key: &str| matches!(all.get(key).map(|v| &**v), Some(Value::Bool(true)));
+
+        Ok(PlayerSnapshot {
+            player: player.to_string(),
+            metadata,
+            position: integer(all.get("Position")).unwrap_or(0) as f64 / 1_000_000.0,
+            status: string(all.get("PlaybackStatus")),
+            volume: float(all.get("Volume")),
+            rate: float(all.get("Rate")).unwrap_or(1.0),
+            capabilities: Capabilities {
+                can_play: flag("CanPlay"),
+                can_pause: flag("CanPause"),
+                can_go_next: flag("CanGoNext"),
+                can_go_previous: flag("CanGoPrevious"),
+                can_seek: flag("CanSeek"),
+                can_control: flag("CanControl"),
+            },
+        })
+    }
+
+    pub async fn next(&self, player: &str) -> Result<(), String> {
+        self.player(player)
+            .await?
+            .next()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn previous(&self, player: &str) -> Result<(), String> {
+        self.player(player)
+            .await?
+            .previous()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn play_pause(&self, player: &str) -> Result<(), String> {
+        self.player(player)
+            .await?
+            .play_pause()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // Seeks to an absolute position in seconds. SetPosition needs the current
+    // track id; players that don't publish one get a relative Seek instead.
+    pub async fn set_position(&self, player: &str, seconds: f64) -> Result<(), String> {
+        let proxy = self.player(player).await?;
+        let target = (seconds * 1_000_000.0) as i64;
+        let metadata = proxy.metadata().await.map_err(|e| e.to_string())?;
+
+        match object_path(metadata.get("mpris:trackid")) {
+            Some(track_id) => proxy
+                .set_position(&track_id, target)
+                .await
+                .map_err(|e| e.to_string()),
+            None => {
+                let current = proxy.position().await.map_err(|e| e.to_string())?;
+                proxy
+                    .seek(target - current)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    // Listens for player signals and hands them to `on_event` until the bus
+    // connection goes away. Players re-send Metadata and PlaybackStatus with
+    // unrelated property updates, so only actual changes are reported.
+    pub async fn watch<F>(&self, mut on_event: F) -> Result<(), String>
+    where
+        F: FnMut(PlayerEvent),
+    {
+        let connection = self.connection().await?;
+        let dbus = DBusProxy::new(&connection)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let rules = [
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface("org.freedesktop.DBus.Properties")
+                .and_then(|b| b.member("PropertiesChanged"))
+                .and_then(|b| b.path(OBJECT_PATH))
+                .and_then(|b| b.arg(0, PLAYER_INTERFACE))
+                .map(|b| b.build()),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface(PLAYER_INTERFACE)
+                .and_then(|b| b.member("Seeked"))
+                .and_then(|b| b.path(OBJECT_PATH))
+                .map(|b| b.build()),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface("org.freedesktop.DBus")
+                .and_then(|b| b.member("NameOwnerChanged"))
+                .and_then(|b| b.arg0ns("org.mpris.MediaPlayer2"))
+                .map(|b| b.build()),
+        ];
+
+        let mut streams = Vec::new();
+        for rule in rules {
+            let rule = rule.map_err(|e| e.to_string())?;
+            let stream = MessageStream::for_match_rule(rule, &connection, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            streams.push(stream);
+        }
+        let mut messages = select_all(streams);
+
+        // Signals carry the sender's unique name (":1.42"), so keep a map
+        // back to the player name for everything already on the bus.
+        let mut owners: HashMap<String, String> = HashMap::new();
+        for player in self.list_players().await? {
+            let name = format!("{}{}", BUS_NAME_PREFIX, player);
+            let Ok(bus_name) = BusName::try_from(name.as_str()) else {
+                continue;
+            };
+            if let Ok(owner) = dbus.get_name_owner(bus_name).await {
+                owners.insert(owner.to_string(), player);
+            }
+        }
+
+        let mut tracks: HashMap<String, Metadata> = HashMap::new();
+        let mut statuses: HashMap<String, String> = HashMap::new();
+
+        while let Some(message) = messages.next().await {
+            let Ok(message) = message else {
+                continue;
+            };
+            let header = message.header();
+            let member = header.member().map(|m| m.to_string()).unwrap_or_default();
+            let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+
+            match member.as_str() {
+                "NameOwnerChanged" => {
+                    let Ok((name, old_owner, new_owner)) =
+                        message.body().deserialize::<(String, String, String)>()
+                    else {
+                        continue;
+                    };
+                    let Some(player) = name.strip_prefix(BUS_NAME_PREFIX) else {
+                        continue;
+                    };
+
+                    if !old_owner.is_empty() {
+                        owners.remove(&old_owner);
+                        tracks.remove(player);
+                        statuses.remove(player);
+                        if new_owner.is_empty() {
+                            on_event(PlayerEvent::Removed(player.to_string()));
+                        }
+                    }
+                    if !new_owner.is_empty() {
+                        owners.insert(new_owner, player.to_string());
+                        if old_owner.is_empty() {
+                            on_event(PlayerEvent::Added(player.to_string()));
+                        }
+                    }
+                }
+                "PropertiesChanged" => {
+                    let Some(player) = owners.get(&sender).cloned() else {
+                        continue;
+                    };
+                    let Ok((_, mut changed, _)) =
+                        message.body().deserialize::<PropertiesChangedArgs>()
+                    else {
+                        continue;
+                    };
+
+                    let map = changed
+                        .remove("Metadata")
+                        .and_then(|value| HashMap::<String, OwnedValue>::try_from(value).ok());
+                    if let Some(map) = map {
+                        let metadata = metadata_from_map(&map);
+                        if tracks.get(&player) != Some(&metadata) {
+                            tracks.insert(player.clone(), metadata.clone());
+                            on_event(PlayerEvent::TrackChanged(player.clone(), metadata));
+                        }
+                    }
+
+                    if let Some(Value::Str(status)) = changed.get("PlaybackStatus").map(|v| &**v) {
+                        let status = status.to_string();
+                        if statuses.get(&player) != Some(&status) {
+                            statuses.insert(player.clone(), status.clone());
+                            on_event(PlayerEvent::PlaybackStatusChanged(player.clone(), status));
+                        }
+                    }
+
+                    if let Some(Value::F64(rate)) = changed.get("Rate").map(|v| &**v) {
+                        on_event(PlayerEvent::RateChanged(player, *rate));
+                    }
+                }
+                "Seeked" => {
+                    let Some(player) = owners.get(&sender).cloned() else {
+                        continue;
+                    };
+                    if let Ok(position) = message.body().deserialize::<i64>() {
+                        on_event(PlayerEvent::Seeked(player, position as f64 / 1_000_000.0));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err("Session bus connection closed".to_string())
+    }
+}
+
+fn metadata_from_map(metadata: &HashMap<String, OwnedValue>) -> Metadata {
+    let artists = string_list(metadata.get("xesam:artist"));
+    let optional = |key: &str| Some(string(metadata.get(key))).filter(|s| !s.is_empty());
+
+    Metadata {
+        artist: artists.join(", "),
+        title: string(metadata.get("xesam:title")),
+        album: string(metadata.get("xesam:album")),
+        // mpris:length is in microseconds
+        duration: integer(metadata.get("mpris:length"))
+            .filter(|length| *length > 0)
+            .map(|length| length as f64 / 1_000_000.0),
+        art_url: optional("mpris:artUrl"),
+        track_id: object_path(metadata.get("mpris:trackid"))
+            .map(|path| path.to_string())
+            .or_else(|| optional("mpris:trackid")),
+        url: optional("xesam:url"),
+        album_artists: string_list(metadata.get("xesam:albumArtist")),
+        track_number: integer(metadata.get("xesam:trackNumber")).map(|n| n as i32),
+        artists,
+    }
+}
+
+fn string(value: Option<&OwnedValue>) -> String {
+    match value.map(|v| &**v) {
+        Some(Value::Str(s)) => s.to_string(),
+        _ => String::new(),
+    }
+}
+
+// The spec says x (i64) for lengths and i (i32) for track numbers, but
+// players send whatever integer type they like, and sometimes a double.
+fn integer(value: Option<&OwnedValue>) -> Option<i64> {
+    match value.map(|v| &**v) {
+        Some(Value::I64(n)) => Some(*n),
+        Some(Value::U64(n)) => i64::try_from(*n).ok(),
+        Some(Value::I32(n)) => Some(i64::from(*n)),
+        Some(Value::U32(n)) => Some(i64::from(*n)),
+        Some(Value::I16(n)) => Some(i64::from(*n)),
+        Some(Value::U16(n)) => Some(i64::from(*n)),
+        Some(Value::U8(n)) => Some(i64::from(*n)),
+        Some(Value::F64(n)) => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn float(value: Option<&OwnedValue>) -> Option<f64> {
+    match value.map(|v| &**v) {
+        Some(Value::F64(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn object_path(value: Option<&OwnedValue>) -> Option<ObjectPath<'static>> {
+    match value.map(|v| &**v) {
+        Some(Value::ObjectPath(path)) => Some(path.to_owned()),
+        _ => None,
+    }
+}
+
+fn string_list(value: Option<&OwnedValue>) -> Vec<String> {
+    match value.map(|v| &**v) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect(),
+        // Some players send a single string where the spec asks for a list.
+        Some(Value::Str(s)) => vec![s.to_string()],
+        _ => Vec::new(),
+    }
+}
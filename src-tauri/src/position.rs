@@ -0,0 +1,181 @@
+// Playback position clock. Players only report a position when asked (or on
+// seeks), so we keep the last report with a monotonic timestamp and
+// extrapolate from it using the playback rate in between.
+
+use std::time::{Duration, Instant};
+
+// Reported positions within this many seconds of the prediction are treated
+// as jitter and don't move the anchor, which keeps the clock monotonic.
+const DRIFT_TOLERANCE: f64 = 0.3;
+
+#[derive(Clone, Copy)]
+pub struct PositionSample {
+    pub position: f64,
+    pub rate: f64,
+    pub playing: bool,
+}
+
+pub struct PositionClock {
+    anchor: f64,
+    anchored_at: Instant,
+    rate: f64,
+    playing: bool,
+    // When the player last confirmed the position. `None` forces a resync.
+    synced_at: Option<Instant>,
+}
+
+impl PositionClock {
+    pub fn new(sample: PositionSample) -> Self {
+        let now = Instant::now();
+        PositionClock {
+            anchor: sample.position,
+            anchored_at: now,
+            rate: sample.rate,
+            playing: sample.playing,
+            synced_at: Some(now),
+        }
+    }
+
+    // Current position in seconds.
+    pub fn position(&self) -> f64 {
+        self.position_at(Instant::now())
+    }
+
+    pub fn position_at(&self, at: Instant) -> f64 {
+        if !self.playing {
+            return self.anchor;
+        }
+        let elapsed = at.saturating_duration_since(self.anchored_at).as_secs_f64();
+        (self.anchor + elapsed * self.rate).max(0.0)
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn needs_sync(&self, max_age: Duration) -> bool {
+        self.synced_at
+            .map_or(true, |synced_at| synced_at.elapsed() >= max_age)
+    }
+
+    // Folds in a fresh report from the player. Returns true when the report
+    // was a discontinuity (a seek we weren't told about, a stall, a loop).
+    pub fn sync(&mut self, sample: PositionSample) -> bool {
+        let now = Instant::now();
+        let predicted = self.position_at(now);
+        let jumped = (sample.position - predicted).abs() > DRIFT_TOLERANCE;
+
+        if jumped || !sample.playing || !self.playing || sample.rate != self.rate {
+            self.anchor = sample.position;
+            self.anchored_at = now;
+        }
+        self.rate = sample.rate;
+        self.playing = sample.playing;
+        self.synced_at = Some(now);
+
+        jumped
+    }
+
+    pub fn seek(&mut self, position: f64) {
+        self.anchor = position;
+        self.anchored_at = Instant::now();
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.seek(self.position());
+        self.playing = playing;
+        // The player may have stopped a little before it told us, so check
+        // with it on the next read.
+        self.synced_at = None;
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.seek(self.position());
+        self.rate = rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(position: f64, rate: f64, playing: bool) -> PositionClock {
+        PositionClock::new(PositionSample {
+            position,
+            rate,
+            playing,
+        })
+    }
+
+    fn after(clock: &PositionClock, seconds: f64) -> f64 {
+        clock.position_at(clock.anchored_at + Duration::from_secs_f64(seconds))
+    }
+
+    #[test]
+    fn extrapolates_while_playing() {
+        let clock = clock(10.0, 1.0, true);
+        assert!((after(&clock, 2.0) - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn follows_the_playback_rate() {
+        let clock = clock(10.0, 1.5, true);
+        assert!((after(&clock, 2.0) - 13.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stands_still_while_paused() {
+        let clock = clock(10.0, 1.0, false);
+        assert_eq!(after(&clock, 5.0), 10.0);
+    }
+
+    #[test]
+    fn pausing_holds_the_position_and_asks_for_a_resync() {
+        let mut clock = clock(10.0, 1.0, true);
+        assert!(!clock.needs_sync(Duration::from_secs(60)));
+        clock.set_playing(false);
+        let held = clock.position();
+        assert!(held >= 10.0);
+        assert_eq!(after(&clock, 5.0), held);
+        assert!(clock.needs_sync(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn jitter_keeps_the_anchor() {
+        let mut clock = clock(10.0, 1.0, true);
+        let anchored_at = clock.anchored_at;
+        let jumped = clock.sync(PositionSample {
+            position: clock.position() + DRIFT_TOLERANCE / 2.0,
+            rate: 1.0,
+            playing: true,
+        });
+        assert!(!jumped);
+        assert_eq!(clock.anchored_at, anchored_at);
+        assert_eq!(clock.anchor, 10.0);
+    }
+
+    #[test]
+    fn a_jump_reanchors_and_is_reported() {
+        let mut clock = clock(10.0, 1.0, true);
+        let jumped = clock.sync(PositionSample {
+            position: 42.0,
+            rate: 1.0,
+            playing: true,
+        });
+        assert!(jumped);
+        assert_eq!(clock.anchor, 42.0);
+        assert!((after(&clock, 1.0) - 43.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_rate_change_reanchors() {
+        let mut clock = clock(10.0, 1.0, true);
+        clock.set_rate(2.0);
+        let anchor = clock.anchor;
+        assert!((after(&clock, 1.0) - (anchor + 2.0)).abs() < 1e-9);
+    }
+}
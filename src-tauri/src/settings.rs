@@ -0,0 +1,81 @@
+// Backend settings, persisted as JSON in the app config dir. Missing or
+// unreadable files fall back to the defaults, and fields added later get
+// their default value when an older file is loaded.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::lyrics::{FetchStrategy, ProviderKind};
+
+const FILE_NAME: &str = "settings.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    // Providers in the order they're asked. `local` (sidecar files and the
+    // lyrics folders) is always asked first, wherever it's listed.
+    pub lyrics_providers: Vec<ProviderKind>,
+    pub lyrics_strategy: FetchStrategy,
+    // Seconds a match's duration may differ from the track's
+    pub duration_tolerance: f64,
+    // Folders searched by the local provider
+    pub lyrics_folders: Vec<PathBuf>,
+    // Optional; an anonymous token is requested when this is unset
+    pub musixmatch_token: Option<String>,
+    // Megabytes of lyrics kept on disk
+    pub lyrics_cache_size_mb: u64,
+    // An lrclib server to use instead of lrclib.net, such as a self-hosted
+    // mirror or a local stand-in for trying out publishing
+    pub lrclib_url: Option<String>,
+    // Hosts on this machine or the local network that requests may reach,
+    // and the only IP addresses the webview may fetch from. Everything else
+    // has to resolve to a public address.
+    pub allowed_private_hosts: Vec<String>,
+    // An `http://`, `https://`, `socks5://` or `socks5h://` proxy for every
+    // request. Unset, the system's proxy variables apply.
+    pub proxy: Option<String>,
+    // No network requests at all: lyrics, art and colors only come from
+    // what's saved on this machine
+    pub offline: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            lyrics_providers: vec![
+                ProviderKind::Local,
+                ProviderKind::Lrclib,
+                ProviderKind::Netease,
+                ProviderKind::QqMusic,
+            ],
+            lyrics_strategy: FetchStrategy::Sequential,
+            duration_tolerance: 3.0,
+            lyrics_folders: Vec::new(),
+            musixmatch_token: None,
+            lyrics_cache_size_mb: 50,
+            lrclib_url: None,
+            allowed_private_hosts: Vec::new(),
+            proxy: None,
+            offline: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn lyrics_cache_size(&self) -> u64 {
+        self.lyrics_cache_size_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+pub fn load(config_dir: &Path) -> Settings {
+    std::fs::read_to_string(config_dir.join(FILE_NAME))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(config_dir: &Path, settings: &Settings) -> Result<(), String> {
+    std::fs::create_dir_all(config_dir).map_err(|e| e.to_string())?;
+    let contents = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(config_dir.join(FILE_NAME), contents).map_err(|e| e.to_string())
+}
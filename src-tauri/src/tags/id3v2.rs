@@ -0,0 +1,278 @@
+// ID3v2, through the `id3` crate. `SYLT` frames carry timed text, usually one
+// entry per line; karaoke taggers write one entry per syllable instead and
+// mark where lines break with a leading newline. Lyrics are written the same
+// way: per syllable when there are word timings, per line otherwise.
+
+use id3::frame::{SynchronisedLyrics, SynchronisedLyricsType, TimestampFormat};
+use id3::{Tag, TagLike, Version};
+use std::path::Path;
+
+use super::{plain_text, TrackTags};
+use crate::lyrics::lrc::{Line, Lyrics, Word};
+
+// The ISO 639-2 code for an undetermined language
+const LANGUAGE: &str = "XXX";
+
+pub fn read(path: &Path) -> Result<TrackTags, String> {
+    let Some(tag) = id3::no_tag_ok(id3::partial_tag_ok(Tag::read_from_path(path)))
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(TrackTags::default());
+    };
+
+    let mut tags = TrackTags {
+        artist: tag.artist().map(str::to_string),
+        title: tag.title().map(str::to_string),
+        album: tag.album().map(str::to_string),
+        duration: tag.duration().map(|ms| ms as f64 / 1000.0),
+        synced: tag
+            .synchronised_lyrics()
+            .filter(|sylt| sylt.content_type == SynchronisedLyricsType::Lyrics)
+            .find_map(from_sylt),
+        plain: None,
+    };
+    for uslt in tag.lyrics() {
+        tags.add_lyrics_text(&uslt.text);
+    }
+    Ok(tags)
+}
+
+fn from_sylt(sylt: &SynchronisedLyrics) -> Option<Lyrics> {
+    // MPEG frame counts would need the file's frame rate
+    if sylt.timestamp_format != TimestampFormat::Ms {
+        return None;
+    }
+    let seconds = |ms: u32| ms as f64 / 1000.0;
+    let by_syllable = sylt
+        .content
+        .iter()
+        .skip(1)
+        .any(|(_, text)| text.starts_with(['\n', '\r']));
+
+    let mut lines: Vec<Line> = Vec::new();
+    for (time, text) in &sylt.content {
+        let time = seconds(*time);
+        if !by_syllable {
+            if !text.trim().is_empty() {
+                lines.push(Line {
+                    time,
+                    text: text.trim().to_string(),
+                    words: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        if text.starts_with(['\n', '\r']) || lines.is_empty() {
+            lines.push(Line {
+                time,
+                ..Default::default()
+            });
+        }
+        let line = lines.last_mut()?;
+        let text = text.trim_start_matches(['\n', '\r']);
+        if let Some(previous) = line.words.last_mut() {
+            previous.end = time;
+        }
+        line.text.push_str(text);
+        line.words.push(Word {
+            start: time,
+            end: time,
+            text: text.to_string(),
+        });
+    }
+
+    // Syllables run until the next one starts, the last until the next line
+    let next_starts: Vec<Option<f64>> = (0..lines.len())
+        .map(|i| lines.get(i + 1).map(|line| line.time))
+        .collect();
+    for (line, next) in lines.iter_mut().zip(next_starts) {
+        line.text = line.text.trim().to_string();
+        line.words.retain(|word| !word.text.trim().is_empty());
+        if let Some(last) = line.words.last_mut() {
+            last.end = next.unwrap_or(last.start).max(last.start);
+        }
+    }
+    lines.retain(|line| !line.text.is_empty());
+    lines.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    (!lines.is_empty()).then(|| Lyrics {
+        lines,
+        ..Default::default()
+    })
+}
+
+pub fn write(path: &Path, lyrics: &Lyrics) -> Result<(), String> {
+    let existing = id3::no_tag_ok(id3::partial_tag_ok(Tag::read_from_path(path)))
+        .map_err(|e| e.to_string())?;
+    // Kept at 2.3 for files already tagged that way, for older players
+    let version = match existing.as_ref().map(Tag::version) {
+        Some(Version::Id3v23) => Version::Id3v23,
+        _ => Version::Id3v24,
+    };
+    let mut tag = existing.unwrap_or_else(Tag::new);
+
+    tag.remove_all_synchronised_lyrics();
+    tag.remove_all_lyrics();
+    tag.add_frame(SynchronisedLyrics {
+        lang: LANGUAGE.to_string(),
+        timestamp_format: TimestampFormat::Ms,
+        content_type: SynchronisedLyricsType::Lyrics,
+        description: String::new(),
+        content: to_sylt(lyrics),
+    });
+    tag.add_frame(id3::frame::Lyrics {
+        lang: LANGUAGE.to_string(),
+        description: String::new(),
+        text: plain_text(lyrics),
+    });
+    tag.write_to_path(path, version).map_err(|e| e.to_string())
+}
+
+fn to_sylt(lyrics: &Lyrics) -> Vec<(u32, String)> {
+    let ms = |seconds: f64| (seconds * 1000.0).round() as u32;
+    if lyrics.lines.iter().all(|line| line.words.is_empty()) {
+        return lyrics
+            .lines
+            .iter()
+            .map(|line| (ms(line.time), line.text.clone()))
+            .collect();
+    }
+
+    let mut content = Vec::new();
+    for (i, line) in lyrics.lines.iter().enumerate() {
+        let newline = if i == 0 { "" } else { "\n" };
+        if line.words.is_empty() {
+            content.push((ms(line.time), format!("{}{}", newline, line.text)));
+            continue;
+        }
+        for (j, word) in line.words.iter().enumerate() {
+            let prefix = if j == 0 { newline } else { "" };
+            content.push((ms(word.start), format!("{}{}", prefix, word.text)));
+        }
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+    use crate::test_files::{fixture, Fixture};
+
+    // An MPEG frame header and some silence, with `tag` in front
+    fn mp3(name: &str, tag: Tag) -> Fixture {
+        let mut audio = vec![0xff, 0xfb, 0x90, 0x00];
+        audio.resize(417, 0);
+        let file = fixture(name, &audio);
+        tag.write_to_path(&file.0, Version::Id3v24).unwrap();
+        file
+    }
+
+    fn sylt(content: &[(u32, &str)]) -> SynchronisedLyrics {
+        SynchronisedLyrics {
+            lang: LANGUAGE.to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: content
+                .iter()
+                .map(|(time, text)| (*time, text.to_string()))
+                .collect(),
+        }
+    }
+
+    fn uslt(text: &str) -> id3::frame::Lyrics {
+        id3::frame::Lyrics {
+            lang: LANGUAGE.to_string(),
+            description: String::new(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn sylt_lines_and_uslt_text() {
+        let mut tag = Tag::new();
+        tag.set_artist("Artist");
+        tag.set_title("Song");
+        tag.add_frame(sylt(&[(1000, "One"), (2500, "Two")]));
+        tag.add_frame(uslt("One\nTwo"));
+        let file = mp3("sylt-lines.mp3", tag);
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        let synced = tags.synced.unwrap();
+        let lines: Vec<(f64, &str)> = synced
+            .lines
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(1.0, "One"), (2.5, "Two")]);
+        assert_eq!(tags.plain.as_deref(), Some("One\nTwo"));
+    }
+
+    #[test]
+    fn sylt_syllables_become_words() {
+        let mut tag = Tag::new();
+        tag.add_frame(sylt(&[
+            (1000, "Hel"),
+            (1200, "lo "),
+            (1500, "there"),
+            (3000, "\nNext"),
+        ]));
+        let file = mp3("sylt-syllables.mp3", tag);
+
+        let synced = crate::tags::read(&file.0).unwrap().synced.unwrap();
+        assert_eq!(synced.lines.len(), 2);
+        let line = &synced.lines[0];
+        assert_eq!(line.text, "Hello there");
+        let words: Vec<(f64, f64, &str)> = line
+            .words
+            .iter()
+            .map(|word| (word.start, word.end, word.text.as_str()))
+            .collect();
+        assert_eq!(
+            words,
+            [(1.0, 1.2, "Hel"), (1.2, 1.5, "lo "), (1.5, 3.0, "there")]
+        );
+        assert_eq!(synced.lines[1].time, 3.0);
+    }
+
+    #[test]
+    fn lrc_in_uslt_counts_as_synced() {
+        let mut tag = Tag::new();
+        tag.add_frame(uslt("[00:01.00]One\n[00:02.00]Two"));
+        let file = mp3("uslt-lrc.mp3", tag);
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.synced.unwrap().lines.len(), 2);
+        assert!(tags.plain.is_none());
+    }
+
+    #[test]
+    fn written_lyrics_replace_the_old_ones() {
+        let mut tag = Tag::new();
+        tag.set_title("Song");
+        tag.add_frame(sylt(&[(1000, "Old")]));
+        tag.add_frame(uslt("Old"));
+        let file = mp3("write.mp3", tag);
+
+        let lyrics =
+            lrc::parse("[00:01.00]<00:01.00>New <00:01.50>words<00:02.00>\n[00:03.00]Line").lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        let synced = tags.synced.unwrap();
+        assert_eq!(synced.lines.len(), 2);
+        assert_eq!(synced.lines[0].text, "New words");
+        assert_eq!(synced.lines[0].words.len(), 2);
+        assert_eq!(synced.lines[1].text, "Line");
+        assert_eq!(tags.plain.as_deref(), Some("New words\nLine"));
+
+        let tag = Tag::read_from_path(&file.0).unwrap();
+        assert_eq!(tag.synchronised_lyrics().count(), 1);
+        assert_eq!(tag.lyrics().count(), 1);
+    }
+}
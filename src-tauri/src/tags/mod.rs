@@ -0,0 +1,217 @@
+// Lyrics and basic track info from audio files' own tags, and lyrics written
+// back into them. MP3, WAV and AIFF carry ID3v2, FLAC and Ogg carry Vorbis
+// comments, and MP4/M4A keeps them in its `ilst` atom. The format is told
+// from the file's first bytes rather than its extension. Everything here is
+// blocking file I/O, so callers run it off the async runtime.
+
+mod id3v2;
+mod mp4;
+mod vorbis;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::lyrics::cache::hash_key;
+use crate::lyrics::lrc::{self, Lyrics};
+
+#[derive(Clone, Debug, Default)]
+pub struct TrackTags {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    // Seconds
+    pub duration: Option<f64>,
+    // Time-synced lyrics, from ID3 `SYLT` or LRC text in a lyrics tag
+    pub synced: Option<Lyrics>,
+    // Lyrics without timings
+    pub plain: Option<String>,
+}
+
+impl TrackTags {
+    // Lyrics tags hold whatever the tagger put there, which is often LRC.
+    fn add_lyrics_text(&mut self, text: &str) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let parsed = lrc::parse(text).lyrics;
+        if !parsed.lines.is_empty() {
+            self.synced.get_or_insert(parsed);
+        } else {
+            self.plain.get_or_insert_with(|| text.trim().to_string());
+        }
+    }
+}
+
+// Under the app data dir
+pub const BACKUP_DIR: &str = "tag-backups";
+
+enum Format {
+    Id3,
+    // With the offset of the `fLaC` marker, which an ID3 tag may precede
+    Flac(u64),
+    Ogg,
+    Mp4,
+}
+
+pub fn read(path: &Path) -> Result<TrackTags, String> {
+    match detect(path)? {
+        Format::Id3 => id3v2::read(path),
+        Format::Flac(offset) => vorbis::read_flac(path, offset),
+        Format::Ogg => vorbis::read_ogg(path),
+        Format::Mp4 => mp4::read(path),
+    }
+}
+
+// Replaces whatever lyrics the file's tags hold with `lyrics`: `SYLT` and
+// `USLT` for ID3, a `LYRICS` comment for FLAC and Ogg, and `©lyr` for MP4. Any offset
+// is applied to the timestamps, since few players honor one.
+pub fn write_lyrics(path: &Path, lyrics: &Lyrics) -> Result<(), String> {
+    let lyrics = lyrics.without_offset();
+    match detect(path)? {
+        Format::Id3 => id3v2::write(path, &lyrics),
+        Format::Flac(offset) => vorbis::write_flac(path, offset, &lyrics),
+        Format::Ogg => vorbis::write_ogg(path, &lyrics),
+        Format::Mp4 => mp4::write(path, &lyrics),
+    }
+}
+
+// Copies `path` into `dir` before it's changed, and returns where the copy
+// is. A backup that's already there is kept, as it's the closest to the
+// original file.
+pub fn backup(path: &Path, dir: &Path) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .ok_or("Not a file")?
+        .to_string_lossy()
+        .into_owned();
+    // Prefixed with the full path's hash so same-named files don't collide
+    let backup = dir.join(format!("{}-{}", hash_key(&path.to_string_lossy()), name));
+    if backup.exists() {
+        return Ok(backup);
+    }
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let partial = backup.with_extension("partial");
+    std::fs::copy(path, &partial).map_err(|e| e.to_string())?;
+    std::fs::rename(&partial, &backup).map_err(|e| e.to_string())?;
+    Ok(backup)
+}
+
+// Line-timed LRC without header tags, which other players read most
+// reliably.
+fn plain_lrc(lyrics: &Lyrics) -> String {
+    let mut lines = lyrics.clone();
+    lines.lines.iter_mut().for_each(|line| line.words.clear());
+    lrc::to_lrc(&Lyrics {
+        lines: lines.lines,
+        ..Default::default()
+    })
+}
+
+fn plain_text(lyrics: &Lyrics) -> String {
+    lyrics
+        .lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Writes a new version of `path` alongside it and swaps it in, so a failure
+// part way never leaves a damaged file.
+fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&mut std::io::BufWriter<File>) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let result = (|| {
+        let mut writer =
+            std::io::BufWriter::new(File::create(&partial).map_err(|e| e.to_string())?);
+        write(&mut writer)?;
+        writer.flush().map_err(|e| e.to_string())?;
+        let permissions = std::fs::metadata(path)
+            .map_err(|e| e.to_string())?
+            .permissions();
+        std::fs::set_permissions(&partial, permissions).map_err(|e| e.to_string())?;
+        std::fs::rename(&partial, path).map_err(|e| e.to_string())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+fn detect(path: &Path) -> Result<Format, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut header = [0u8; 12];
+    let read = file.read(&mut header).map_err(|e| e.to_string())?;
+    let header = &header[..read];
+
+    if header.starts_with(b"fLaC") {
+        return Ok(Format::Flac(0));
+    }
+    if header.starts_with(b"OggS") {
+        return Ok(Format::Ogg);
+    }
+    if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        return Ok(Format::Mp4);
+    }
+    if header.starts_with(b"ID3") && header.len() >= 10 {
+        // Sizes are "synchsafe": seven bits to a byte
+        let size = header[6..10]
+            .iter()
+            .fold(0u64, |size, byte| (size << 7) | (*byte & 0x7f) as u64);
+        let offset = 10 + size;
+        let mut marker = [0u8; 4];
+        let flac = file.seek(SeekFrom::Start(offset)).is_ok()
+            && file.read_exact(&mut marker).is_ok()
+            && &marker == b"fLaC";
+        return Ok(if flac {
+            Format::Flac(offset)
+        } else {
+            Format::Id3
+        });
+    }
+    if header.starts_with(b"RIFF") || header.starts_with(b"FORM") || header.starts_with(&[0xff]) {
+        return Ok(Format::Id3);
+    }
+    Err("Unsupported audio file".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::fixture;
+
+    #[test]
+    fn formats_are_told_by_their_first_bytes() {
+        let format = |name: &str, bytes: &[u8]| detect(&fixture(name, bytes).0);
+        assert!(matches!(
+            format("detect.flac", b"fLaC\0\0\0\0"),
+            Ok(Format::Flac(0))
+        ));
+        assert!(matches!(
+            format("detect.ogg", b"OggS\0\0\0\0"),
+            Ok(Format::Ogg)
+        ));
+        assert!(matches!(
+            format("detect.m4a", b"\0\0\0\x0cftypM4A "),
+            Ok(Format::Mp4)
+        ));
+        assert!(matches!(
+            format("detect.mp3", &[0xff, 0xfb, 0x90, 0]),
+            Ok(Format::Id3)
+        ));
+        // An ID3 tag in front of FLAC
+        let mut tagged = b"ID3\x04\0\0\0\0\0\x02\0\0".to_vec();
+        tagged.extend_from_slice(b"fLaC");
+        assert!(matches!(
+            format("detect-id3.flac", &tagged),
+            Ok(Format::Flac(12))
+        ));
+        assert!(format("detect.txt", b"hello").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+// MP4/M4A metadata, which iTunes-style taggers keep in
+// `moov/udta/meta/ilst`. Each tag is an atom named after it (`©lyr` for
+// lyrics) holding a `data` atom with the value. Only `moov` is read; the
+// media data around it is skipped over. Writing rebuilds `moov`, and since
+// the sample tables point at absolute file offsets, media that comes after
+// it is pointed at its new place.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{plain_lrc, replace_file, TrackTags};
+use crate::lyrics::lrc::Lyrics;
+
+const LYRICS: &[u8; 4] = b"\xa9lyr";
+const TITLE: &[u8; 4] = b"\xa9nam";
+const ARTIST: &[u8; 4] = b"\xa9ART";
+const ALBUM: &[u8; 4] = b"\xa9alb";
+
+// Marks a `data` atom's value as UTF-8 text
+const TEXT: u32 = 1;
+
+// Containers on the way down to the sample tables
+const TABLE_PATH: [&[u8; 4]; 4] = [b"trak", b"mdia", b"minf", b"stbl"];
+
+struct Atom<'a> {
+    kind: [u8; 4],
+    // Where the body starts within the data the atom was read from
+    offset: usize,
+    body: &'a [u8],
+    // Header and body
+    raw: &'a [u8],
+}
+
+struct Moov {
+    // Where the whole atom starts and ends in the file
+    start: u64,
+    end: u64,
+    body: Vec<u8>,
+}
+
+pub fn read(path: &Path) -> Result<TrackTags, String> {
+    let moov = read_moov(path)?.body;
+    let mut tags = TrackTags {
+        duration: child(&moov, b"mvhd").and_then(|mvhd| movie_duration(mvhd.body)),
+        ..Default::default()
+    };
+
+    let ilst = child(&moov, b"udta")
+        .and_then(|udta| child(udta.body, b"meta"))
+        // `meta` has a version and flags before its children
+        .and_then(|meta| child(meta.body.get(4..)?, b"ilst"));
+    let Some(ilst) = ilst else {
+        return Ok(tags);
+    };
+    let text = |kind: &[u8; 4]| child(ilst.body, kind).and_then(|item| item_text(item.body));
+    tags.title = text(TITLE);
+    tags.artist = text(ARTIST);
+    tags.album = text(ALBUM);
+    if let Some(lyrics) = text(LYRICS) {
+        tags.add_lyrics_text(&lyrics);
+    }
+    Ok(tags)
+}
+
+pub fn write(path: &Path, lyrics: &Lyrics) -> Result<(), String> {
+    let moov = read_moov(path)?;
+    let mut body = with_lyrics(&moov.body, &plain_lrc(lyrics));
+    let old_size = moov.end - moov.start;
+    let new_size = atom_size(body.len()) as u64;
+    shift_chunk_offsets(&mut body, 0, moov.end, new_size as i64 - old_size as i64)?;
+    let atom = encode(b"moov", &body);
+
+    let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    replace_file(path, |out| {
+        let io = |e: std::io::Error| e.to_string();
+        std::io::copy(&mut (&mut file).take(moov.start), out).map_err(io)?;
+        out.write_all(&atom).map_err(io)?;
+        file.seek(SeekFrom::Start(moov.end)).map_err(io)?;
+        std::io::copy(&mut file, out).map_err(io)?;
+        Ok(())
+    })
+}
+
+// The top-level `moov` atom and where it sits.
+fn read_moov(path: &Path) -> Result<Moov, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let length = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut file = BufReader::new(file);
+    let mut start = 0;
+    loop {
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)
+            .map_err(|_| "No movie atom in MP4 file".to_string())?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large).map_err(|e| e.to_string())?;
+            size = u64::from_be_bytes(large);
+            header_size = 16;
+        }
+        if size != 0 && size < header_size {
+            return Err("Invalid MP4 atom".to_string());
+        }
+        // Checked before anything is allocated for it, as the size is
+        // whatever the file says
+        if size > length - start {
+            return Err("MP4 atom runs past the end of the file".to_string());
+        }
+
+        if &header[4..8] == b"moov" {
+            let mut body = Vec::new();
+            if size == 0 {
+                file.read_to_end(&mut body).map_err(|e| e.to_string())?;
+            } else {
+                body.resize((size - header_size) as usize, 0);
+                file.read_exact(&mut body).map_err(|e| e.to_string())?;
+            }
+            return Ok(Moov {
+                start,
+                end: start + header_size + body.len() as u64,
+                body,
+            });
+        }
+        if size == 0 {
+            return Err("No movie atom in MP4 file".to_string());
+        }
+        file.seek(SeekFrom::Current((size - header_size) as i64))
+            .map_err(|e| e.to_string())?;
+        start += size;
+    }
+}
+
+// The atoms directly inside `data`. Stops at the first malformed one.
+fn children(data: &[u8]) -> Vec<Atom<'_>> {
+    let mut atoms = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 8 {
+        let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let (header_size, size) = match size {
+            0 => (8, rest.len()),
+            1 if rest.len() >= 16 => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&rest[8..16]);
+                (16, u64::from_be_bytes(large) as usize)
+            }
+            _ => (8, size),
+        };
+        if size < header_size || size > rest.len() {
+            break;
+        }
+        atoms.push(Atom {
+            kind,
+            offset: data.len() - rest.len() + header_size,
+            body: &rest[header_size..size],
+            raw: &rest[..size],
+        });
+        rest = &rest[size..];
+    }
+    atoms
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<Atom<'a>> {
+    children(data).into_iter().find(|atom| &atom.kind == kind)
+}
+
+// A tag's value: its `data` atom, minus the type and locale fields.
+fn item_text(item: &[u8]) -> Option<String> {
+    let data = child(item, b"data")?;
+    let value = data.body.get(8..)?;
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+// Duration over time scale, from the movie header.
+fn movie_duration(mvhd: &[u8]) -> Option<f64> {
+    let be32 = |at: usize| -> Option<u64> {
+        let bytes = mvhd.get(at..at + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64)
+    };
+    let (time_scale, duration) = match mvhd.first()? {
+        // Version 1 has 64-bit times
+        1 => (be32(20)?, be32(24)? << 32 | be32(28)?),
+        _ => (be32(12)?, be32(16)?),
+    };
+    (time_scale > 0 && duration > 0).then(|| duration as f64 / time_scale as f64)
+}
+
+// `moov`'s body with the `©lyr` tag set to `text`, creating the containers
+// on the way when the file has no tags yet.
+fn with_lyrics(moov: &[u8], text: &str) -> Vec<u8> {
+    replace_child(moov, b"udta", |udta| {
+        replace_child(udta.unwrap_or_default(), b"meta", |meta| {
+            // Version and flags, then the children. A new `meta` needs a
+            // handler saying it holds iTunes-style tags.
+            let (head, children) = match meta.filter(|meta| meta.len() >= 4) {
+                Some(meta) => (meta[..4].to_vec(), meta[4..].to_vec()),
+                None => {
+                    let mut hdlr = vec![0u8; 8];
+                    hdlr.extend_from_slice(b"mdirappl");
+                    hdlr.extend_from_slice(&[0u8; 9]);
+                    (vec![0u8; 4], encode(b"hdlr", &hdlr))
+                }
+            };
+            let mut body = head;
+            body.extend(replace_child(&children, b"ilst", |ilst| {
+                replace_child(ilst.unwrap_or_default(), LYRICS, |_| {
+                    let mut data = TEXT.to_be_bytes().to_vec();
+                    data.extend_from_slice(&[0u8; 4]);
+                    data.extend_from_slice(text.as_bytes());
+                    encode(b"data", &data)
+                })
+            }));
+            body
+        })
+    })
+}
+
+// `container`'s children with the first `kind` atom's body replaced by what
+// `update` makes of it, or a new one added at the end.
+fn replace_child(
+    container: &[u8],
+    kind: &[u8; 4],
+    update: impl FnOnce(Option<&[u8]>) -> Vec<u8>,
+) -> Vec<u8> {
+    let atoms = children(container);
+    let mut out = Vec::with_capacity(container.len());
+    let mut update = Some(update);
+    for atom in &atoms {
+        if &atom.kind == kind {
+            if let Some(update) = update.take() {
+                out.extend(encode(kind, &update(Some(atom.body))));
+                continue;
+            }
+        }
+        out.extend_from_slice(atom.raw);
+    }
+    // Anything after the last readable atom is kept as it was
+    let parsed: usize = atoms.iter().map(|atom| atom.raw.len()).sum();
+    out.extend_from_slice(&container[parsed..]);
+    if let Some(update) = update {
+        out.extend(encode(kind, &update(None)));
+    }
+    out
+}
+
+fn atom_size(body: usize) -> usize {
+    if body + 8 > u32::MAX as usize {
+        body + 16
+    } else {
+        body + 8
+    }
+}
+
+fn encode(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let size = atom_size(body.len());
+    let mut atom = Vec::with_capacity(size);
+    if size - body.len() == 16 {
+        atom.extend_from_slice(&1u32.to_be_bytes());
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(&(size as u64).to_be_bytes());
+    } else {
+        atom.extend_from_slice(&(size as u32).to_be_bytes());
+        atom.extend_from_slice(kind);
+    }
+    atom.extend_from_slice(body);
+    atom
+}
+
+// Moves every chunk offset at or past `after` by `delta`, in the `stco` and
+// `co64` tables under `data`. `depth` is how far down `TABLE_PATH` `data`
+// is.
+fn shift_chunk_offsets(
+    data: &mut [u8],
+    depth: usize,
+    after: u64,
+    delta: i64,
+) -> Result<(), String> {
+    if delta == 0 {
+        return Ok(());
+    }
+    let atoms: Vec<([u8; 4], usize, usize)> = children(data)
+        .iter()
+        .map(|atom| (atom.kind, atom.offset, atom.body.len()))
+        .collect();
+
+    for (kind, offset, length) in atoms {
+        let body = &mut data[offset..offset + length];
+        if depth < TABLE_PATH.len() {
+            if &kind == TABLE_PATH[depth] {
+                shift_chunk_offsets(body, depth + 1, after, delta)?;
+            }
+            continue;
+        }
+
+        let width = match &kind {
+            b"stco" => 4,
+            b"co64" => 8,
+            _ => continue,
+        };
+        // Version and flags, then the entry count
+        let Some(count) = body.get(4..8) else {
+            continue;
+        };
+        let count = u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as usize;
+        for entry in body[8..].chunks_exact_mut(width).take(count) {
+            let value = match width {
+                4 => u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as u64,
+                _ => u64::from_be_bytes([
+                    entry[0], entry[1], entry[2], entry[3], entry[4], entry[5], entry[6], entry[7],
+                ]),
+            };
+            if value < after {
+                continue;
+            }
+            let shifted = value
+                .checked_add_signed(delta)
+                .ok_or("Invalid chunk offset")?;
+            match width {
+                4 => {
+                    let shifted = u32::try_from(shifted)
+                        .map_err(|_| "File too large to add lyrics to".to_string())?;
+                    entry.copy_from_slice(&shifted.to_be_bytes());
+                }
+                _ => entry.copy_from_slice(&shifted.to_be_bytes()),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+    use crate::test_files::{fixture, Fixture};
+
+    const AUDIO: &[u8] = b"media samples";
+
+    fn item(kind: &[u8; 4], text: &str) -> Vec<u8> {
+        let mut data = TEXT.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(text.as_bytes());
+        encode(kind, &encode(b"data", &data))
+    }
+
+    // 200 seconds
+    fn mvhd() -> Vec<u8> {
+        let mut mvhd = vec![0u8; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&200_000u32.to_be_bytes());
+        encode(b"mvhd", &mvhd)
+    }
+
+    // `moov/trak/.../stco` with a single chunk at `offset`
+    fn track(offset: u32) -> Vec<u8> {
+        let mut stco = vec![0u8; 4];
+        stco.extend_from_slice(&1u32.to_be_bytes());
+        stco.extend_from_slice(&offset.to_be_bytes());
+        TABLE_PATH
+            .iter()
+            .rev()
+            .fold(encode(b"stco", &stco), |inner, kind| encode(kind, &inner))
+    }
+
+    fn tags(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut meta = vec![0u8; 4];
+        meta.extend(encode(b"ilst", &items.concat()));
+        encode(b"udta", &encode(b"meta", &meta))
+    }
+
+    // `ftyp`, then `moov` and `mdat` in either order, with the chunk offset
+    // pointing at the samples.
+    fn m4a(name: &str, udta: Option<Vec<u8>>, media_first: bool) -> Fixture {
+        let ftyp = encode(b"ftyp", b"M4A \0\0\0\0M4A ");
+        let mdat = encode(b"mdat", AUDIO);
+        let moov = |offset: u32| {
+            let mut body = mvhd();
+            body.extend(track(offset));
+            body.extend(udta.clone().unwrap_or_default());
+            encode(b"moov", &body)
+        };
+        let bytes = if media_first {
+            [ftyp.clone(), mdat.clone(), moov(ftyp.len() as u32 + 8)].concat()
+        } else {
+            let offset = (ftyp.len() + moov(0).len() + 8) as u32;
+            [ftyp, moov(offset), mdat].concat()
+        };
+        fixture(name, &bytes)
+    }
+
+    // Where the chunk offset points, and what's there.
+    fn chunk(path: &Path) -> Vec<u8> {
+        let bytes = std::fs::read(path).unwrap();
+        let moov = child(&bytes, b"moov").unwrap();
+        let stco = TABLE_PATH
+            .iter()
+            .try_fold(moov.body, |data, kind| Some(child(data, kind)?.body))
+            .and_then(|stbl| child(stbl, b"stco"))
+            .unwrap();
+        let offset = u32::from_be_bytes(stco.body[8..12].try_into().unwrap()) as usize;
+        bytes[offset..offset + AUDIO.len()].to_vec()
+    }
+
+    #[test]
+    fn reads_tags_and_duration() {
+        let udta = tags(&[
+            item(TITLE, "Song"),
+            item(ARTIST, "Artist"),
+            item(LYRICS, "[00:01.00]One\n[00:02.00]Two"),
+        ]);
+        let file = m4a("read.m4a", Some(udta), false);
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.duration, Some(200.0));
+        assert_eq!(tags.synced.unwrap().lines.len(), 2);
+        assert_eq!(chunk(&file.0), AUDIO);
+    }
+
+    #[test]
+    fn writing_moves_the_chunk_offsets_after_moov() {
+        let file = m4a(
+            "write.m4a",
+            Some(tags(&[item(TITLE, "Song"), item(LYRICS, "Old")])),
+            false,
+        );
+        let lyrics = lrc::parse("[00:01.00]New lyrics that are longer").lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(
+            tags.synced.unwrap().lines[0].text,
+            "New lyrics that are longer"
+        );
+        assert_eq!(chunk(&file.0), AUDIO);
+    }
+
+    #[test]
+    fn writing_creates_the_tag_containers() {
+        let file = m4a("untagged.m4a", None, true);
+        let lyrics = lrc::parse("[00:01.00]One").lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.synced.unwrap().lines[0].text, "One");
+        // The media comes first, so it hasn't moved
+        assert_eq!(chunk(&file.0), AUDIO);
+    }
+
+    #[test]
+    fn atoms_larger_than_the_file_are_refused() {
+        let ftyp = encode(b"ftyp", b"M4A \0\0\0\0M4A ");
+        let huge = [ftyp.clone(), b"\xff\xff\xff\xf0moov\0\0\0\0".to_vec()].concat();
+        let file = fixture("huge.m4a", &huge);
+        assert!(crate::tags::read(&file.0).is_err());
+
+        let large = [
+            ftyp,
+            b"\0\0\0\x01moov\x7f\xff\xff\xff\xff\xff\xff\xff".to_vec(),
+        ]
+        .concat();
+        let file = fixture("large.m4a", &large);
+        assert!(crate::tags::read(&file.0).is_err());
+    }
+}
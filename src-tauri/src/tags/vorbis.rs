@@ -0,0 +1,733 @@
+// Vorbis comments, as found in FLAC's `VORBIS_COMMENT` metadata block and in
+// the second packet of an Ogg Vorbis or Opus stream. Lyrics are read from
+// `LYRICS` (often LRC) and `UNSYNCEDLYRICS`, and written to `LYRICS`. In Ogg
+// the new comment packet is laid out on pages of its own, and the pages
+// after it are renumbered when their count changes. Files with more than one
+// stream at a time (video, Ogg Skeleton) aren't written.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{plain_lrc, replace_file, TrackTags};
+use crate::lyrics::lrc::Lyrics;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+
+// Block lengths are 24-bit
+const MAX_BLOCK_SIZE: usize = 0xff_ffff;
+
+// Ogg page header flag for a page that carries on the previous one's packet
+const CONTINUED: u8 = 0x01;
+
+// Ogg's CRC-32: polynomial 0x04c11db7, unreflected, no final XOR
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+struct Comments {
+    vendor: Vec<u8>,
+    // `KEY=value` pairs in order, with keys as written
+    entries: Vec<(String, String)>,
+}
+
+pub fn read_flac(path: &Path, offset: u64) -> Result<TrackTags, String> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    file.seek(SeekFrom::Start(offset + 4))
+        .map_err(|e| e.to_string())?;
+
+    let mut tags = TrackTags::default();
+    for block in flac_blocks(&mut file)? {
+        match block.kind {
+            STREAMINFO => tags.duration = stream_duration(&block.data),
+            VORBIS_COMMENT => apply_comments(&mut tags, &parse_comments(&block.data)?.entries),
+            _ => {}
+        }
+    }
+    Ok(tags)
+}
+
+pub fn read_ogg(path: &Path) -> Result<TrackTags, String> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let headers = read_ogg_headers(&mut file, false)?;
+    let (_, body) = split_comment_packet(&headers.packets[0])?;
+    let mut tags = TrackTags::default();
+    apply_comments(&mut tags, &parse_comments(body)?.entries);
+    Ok(tags)
+}
+
+pub fn write_ogg(path: &Path, lyrics: &Lyrics) -> Result<(), String> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let mut headers = read_ogg_headers(&mut file, true)?;
+
+    let (magic, body) = split_comment_packet(&headers.packets[0])?;
+    let (mut comments, rest) = split_comments(body)?;
+    set_lyrics(&mut comments, lyrics);
+    // Whatever follows the comments, such as Vorbis's framing bit, is kept
+    headers.packets[0] = [magic, &encode_comments(&comments), rest].concat();
+
+    let pages = paginate(&headers.packets, headers.serial, 1);
+    let shift = pages.len() as i64 - headers.pages as i64;
+
+    // `file` is now at the first audio page
+    replace_file(path, |out| {
+        let io = |e: std::io::Error| e.to_string();
+        out.write_all(&headers.first).map_err(io)?;
+        for page in &pages {
+            out.write_all(page).map_err(io)?;
+        }
+        while let Some(mut page) = read_page(&mut file)? {
+            if shift != 0 && page.serial == headers.serial {
+                let sequence = (page.sequence() as i64 + shift) as u32;
+                page.raw[18..22].copy_from_slice(&sequence.to_le_bytes());
+                set_crc(&mut page.raw);
+            }
+            out.write_all(&page.raw).map_err(io)?;
+        }
+        Ok(())
+    })
+}
+
+// An Ogg page as read, with the parts rewriting needs.
+struct OggPage {
+    // Header, segment table and body
+    raw: Vec<u8>,
+    serial: u32,
+    // Lacing values: a segment shorter than 255 bytes ends a packet
+    segments: Vec<u8>,
+}
+
+impl OggPage {
+    fn sequence(&self) -> u32 {
+        u32::from_le_bytes([self.raw[18], self.raw[19], self.raw[20], self.raw[21]])
+    }
+
+    fn body(&self) -> &[u8] {
+        &self.raw[27 + self.segments.len()..]
+    }
+}
+
+// The next page, or `None` at the end of the file.
+fn read_page(reader: &mut impl Read) -> Result<Option<OggPage>, String> {
+    let mut header = [0u8; 27];
+    let read = reader.read(&mut header).map_err(|e| e.to_string())?;
+    if read == 0 {
+        return Ok(None);
+    }
+    reader
+        .read_exact(&mut header[read..])
+        .map_err(|_| "Ogg stream ended part way through a page".to_string())?;
+    if &header[..4] != b"OggS" {
+        return Err("Invalid Ogg page".to_string());
+    }
+    let mut segments = vec![0u8; header[26] as usize];
+    reader
+        .read_exact(&mut segments)
+        .map_err(|e| e.to_string())?;
+    let length: usize = segments.iter().map(|size| *size as usize).sum();
+
+    let mut raw = Vec::with_capacity(27 + segments.len() + length);
+    raw.extend_from_slice(&header);
+    raw.extend_from_slice(&segments);
+    raw.resize(raw.len() + length, 0);
+    let body = raw.len() - length;
+    reader
+        .read_exact(&mut raw[body..])
+        .map_err(|_| "Ogg stream ended part way through a page".to_string())?;
+    Ok(Some(OggPage {
+        serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+        raw,
+        segments,
+    }))
+}
+
+struct OggHeaders {
+    // The first page, which holds the identification header alone
+    first: Vec<u8>,
+    serial: u32,
+    // The header packets after it, the comment header first
+    packets: Vec<Vec<u8>>,
+    // How many pages they took
+    pages: usize,
+}
+
+// Reads the stream's headers, stopping after the comment header, or with
+// `whole` after every header: Vorbis has a setup header after the comments,
+// which may share their last page. Audio always starts on a page of its own.
+fn read_ogg_headers(reader: &mut impl Read, whole: bool) -> Result<OggHeaders, String> {
+    let ended = || "Ogg stream ended before its comment header".to_string();
+    let first = read_page(reader)?.ok_or_else(ended)?;
+    let count = if first.body().starts_with(b"\x01vorbis") {
+        2
+    } else if first.body().starts_with(b"OpusHead") {
+        1
+    } else {
+        return Err("Unsupported Ogg codec".to_string());
+    };
+    let count = if whole { count } else { 1 };
+
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut pages = 0;
+    while packets.len() < count || !packet.is_empty() {
+        let page = read_page(reader)?.ok_or_else(ended)?;
+        if page.serial != first.serial {
+            return Err("Ogg files with more than one stream aren't supported".to_string());
+        }
+        pages += 1;
+        let mut body = page.body();
+        for size in &page.segments {
+            let (segment, rest) = body.split_at(*size as usize);
+            body = rest;
+            if packets.len() < count {
+                packet.extend_from_slice(segment);
+            } else if whole {
+                return Err("Ogg audio starts part way through a header page".to_string());
+            }
+            if *size < 255 && packets.len() < count {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    Ok(OggHeaders {
+        first: first.raw,
+        serial: first.serial,
+        packets,
+        pages,
+    })
+}
+
+// The codec's magic, and the comments after it.
+fn split_comment_packet(packet: &[u8]) -> Result<(&[u8], &[u8]), String> {
+    [b"\x03vorbis".as_slice(), b"OpusTags"]
+        .into_iter()
+        .find(|magic| packet.starts_with(magic))
+        .map(|magic| packet.split_at(magic.len()))
+        .ok_or_else(|| "Unsupported Ogg codec".to_string())
+}
+
+// Lays `packets` out on as few pages as they fit, numbered from `sequence`.
+fn paginate(packets: &[Vec<u8>], serial: u32, sequence: u32) -> Vec<Vec<u8>> {
+    let mut pages = Vec::new();
+    let mut segments: Vec<u8> = Vec::new();
+    let mut body = Vec::new();
+    let mut continued = false;
+    let mut flush = |segments: &mut Vec<u8>, body: &mut Vec<u8>, continued: &mut bool| {
+        // Headers are at granule position 0; a page where no packet ends has
+        // none
+        let ends_packet = segments.iter().any(|size| *size < 255);
+        let granule = if ends_packet { 0 } else { u64::MAX };
+        let mut page = b"OggS\0".to_vec();
+        page.push(if *continued { CONTINUED } else { 0 });
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&(sequence + pages.len() as u32).to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(segments.len() as u8);
+        page.append(segments);
+        page.append(body);
+        set_crc(&mut page);
+        pages.push(page);
+        *continued = !ends_packet;
+    };
+
+    for packet in packets {
+        let mut rest = packet.as_slice();
+        for size in lacing(packet.len()) {
+            let (segment, remaining) = rest.split_at(size as usize);
+            rest = remaining;
+            segments.push(size);
+            body.extend_from_slice(segment);
+            if segments.len() == 255 {
+                flush(&mut segments, &mut body, &mut continued);
+            }
+        }
+    }
+    if !segments.is_empty() {
+        flush(&mut segments, &mut body, &mut continued);
+    }
+    pages
+}
+
+// Segment sizes for a packet of `length` bytes: 255 until the last, which is
+// shorter, even if that makes it empty.
+fn lacing(length: usize) -> Vec<u8> {
+    let mut sizes = vec![255; length / 255];
+    sizes.push((length % 255) as u8);
+    sizes
+}
+
+fn set_crc(page: &mut [u8]) {
+    page[22..26].fill(0);
+    let crc = page.iter().fold(0u32, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    });
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+}
+
+pub fn write_flac(path: &Path, offset: u64, lyrics: &Lyrics) -> Result<(), String> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    // Kept as it is: an ID3 tag some taggers put in front, and the marker
+    let mut head = vec![0u8; offset as usize + 4];
+    file.read_exact(&mut head).map_err(|e| e.to_string())?;
+    let mut blocks = flac_blocks(&mut file)?;
+
+    let existing = blocks.iter().position(|block| block.kind == VORBIS_COMMENT);
+    let mut comments = match existing {
+        Some(i) => parse_comments(&blocks[i].data)?,
+        None => Comments {
+            vendor: b"ohun".to_vec(),
+            entries: Vec::new(),
+        },
+    };
+    set_lyrics(&mut comments, lyrics);
+    let data = encode_comments(&comments);
+    if data.len() > MAX_BLOCK_SIZE {
+        return Err("Lyrics are too long for a FLAC comment block".to_string());
+    }
+    match existing {
+        Some(i) => blocks[i].data = data,
+        // STREAMINFO always comes first
+        None => blocks.insert(
+            1.min(blocks.len()),
+            FlacBlock {
+                kind: VORBIS_COMMENT,
+                data,
+            },
+        ),
+    }
+
+    // `file` is now at the first audio frame, which is copied as is
+    replace_file(path, |out| {
+        let io = |e: std::io::Error| e.to_string();
+        out.write_all(&head).map_err(io)?;
+        for (i, block) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            out.write_all(&[block.kind | last]).map_err(io)?;
+            out.write_all(&(block.data.len() as u32).to_be_bytes()[1..])
+                .map_err(io)?;
+            out.write_all(&block.data).map_err(io)?;
+        }
+        std::io::copy(&mut file, out).map_err(io)?;
+        Ok(())
+    })
+}
+
+struct FlacBlock {
+    kind: u8,
+    data: Vec<u8>,
+}
+
+// Reads metadata blocks up to and including the one marked last. `reader`
+// must be just past the `fLaC` marker.
+fn flac_blocks(reader: &mut impl Read) -> Result<Vec<FlacBlock>, String> {
+    let mut blocks = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut data = vec![0u8; length];
+        reader.read_exact(&mut data).map_err(|e| e.to_string())?;
+        blocks.push(FlacBlock {
+            kind: header[0] & 0x7f,
+            data,
+        });
+        if header[0] & 0x80 != 0 {
+            return Ok(blocks);
+        }
+    }
+}
+
+// Total samples over the sample rate, when the encoder recorded them.
+fn stream_duration(info: &[u8]) -> Option<f64> {
+    let info = info.get(..18)?;
+    let sample_rate =
+        ((info[10] as u32) << 12) | ((info[11] as u32) << 4) | ((info[12] as u32) >> 4);
+    let samples = ((info[13] as u64 & 0x0f) << 32)
+        | u32::from_be_bytes([info[14], info[15], info[16], info[17]]) as u64;
+    (sample_rate > 0 && samples > 0).then(|| samples as f64 / sample_rate as f64)
+}
+
+fn parse_comments(data: &[u8]) -> Result<Comments, String> {
+    Ok(split_comments(data)?.0)
+}
+
+// The comments, and whatever follows them.
+fn split_comments(data: &[u8]) -> Result<(Comments, &[u8]), String> {
+    let mut rest = data;
+    let vendor = take_length(&mut rest)?;
+    let vendor = take(&mut rest, vendor)?.to_vec();
+    let count = take_length(&mut rest)?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let length = take_length(&mut rest)?;
+        let comment = String::from_utf8_lossy(take(&mut rest, length)?);
+        if let Some((key, value)) = comment.split_once('=') {
+            entries.push((key.to_string(), value.to_string()));
+        }
+    }
+    Ok((Comments { vendor, entries }, rest))
+}
+
+// Replaces any lyrics with `lyrics`.
+fn set_lyrics(comments: &mut Comments, lyrics: &Lyrics) {
+    comments
+        .entries
+        .retain(|(key, _)| !key.eq_ignore_ascii_case("LYRICS"));
+    comments
+        .entries
+        .push(("LYRICS".to_string(), plain_lrc(lyrics)));
+}
+
+fn encode_comments(comments: &Comments) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(comments.vendor.len() as u32).to_le_bytes());
+    data.extend_from_slice(&comments.vendor);
+    data.extend_from_slice(&(comments.entries.len() as u32).to_le_bytes());
+    for (key, value) in &comments.entries {
+        let comment = format!("{}={}", key, value);
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment.as_bytes());
+    }
+    data
+}
+
+fn take<'a>(rest: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+    if rest.len() < length {
+        return Err("Truncated Vorbis comment".to_string());
+    }
+    let (taken, remaining) = rest.split_at(length);
+    *rest = remaining;
+    Ok(taken)
+}
+
+// Lengths are 32-bit little-endian.
+fn take_length(rest: &mut &[u8]) -> Result<usize, String> {
+    let bytes = take(rest, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+// Keys are case-insensitive.
+fn apply_comments(tags: &mut TrackTags, comments: &[(String, String)]) {
+    let first = |key: &str| {
+        comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.clone())
+    };
+    tags.artist = first("ARTIST");
+    tags.title = first("TITLE");
+    tags.album = first("ALBUM");
+    for key in ["LYRICS", "UNSYNCEDLYRICS"] {
+        if let Some(text) = first(key) {
+            tags.add_lyrics_text(&text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+    use crate::test_files::{fixture, Fixture};
+
+    const AUDIO: &[u8] = b"\xff\xf8 audio frames";
+
+    // Ogg page header flag for a stream's first page
+    const FIRST_PAGE: u8 = 0x02;
+
+    fn comments(entries: &[(&str, &str)]) -> Vec<u8> {
+        encode_comments(&Comments {
+            vendor: b"test".to_vec(),
+            entries: entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+
+    // 200 seconds at 44.1 kHz
+    fn streaminfo() -> Vec<u8> {
+        let mut info = vec![0u8; 34];
+        let sample_rate: u32 = 44100;
+        let samples: u64 = 44100 * 200;
+        info[10] = (sample_rate >> 12) as u8;
+        info[11] = (sample_rate >> 4) as u8;
+        info[12] = ((sample_rate & 0x0f) << 4) as u8;
+        info[13] = (samples >> 32) as u8 & 0x0f;
+        info[14..18].copy_from_slice(&(samples as u32).to_be_bytes());
+        info
+    }
+
+    fn flac(name: &str, blocks: &[(u8, Vec<u8>)]) -> Fixture {
+        let mut bytes = b"fLaC".to_vec();
+        for (i, (kind, data)) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            bytes.push(kind | last);
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            bytes.extend_from_slice(data);
+        }
+        bytes.extend_from_slice(AUDIO);
+        fixture(name, &bytes)
+    }
+
+    // An Ogg page holding `segments`, laced from the packets' lengths
+    fn ogg_page(segments: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.resize(26, 0);
+        page.push(segments.len() as u8);
+        page.extend_from_slice(segments);
+        page.extend_from_slice(data);
+        page
+    }
+
+    #[test]
+    fn flac_comments_and_duration() {
+        let file = flac(
+            "read.flac",
+            &[
+                (STREAMINFO, streaminfo()),
+                (
+                    VORBIS_COMMENT,
+                    comments(&[
+                        ("artist", "Artist"),
+                        ("TITLE", "Song"),
+                        ("LYRICS", "[00:01.00]One\n[00:02.00]Two"),
+                        ("UNSYNCEDLYRICS", "One\nTwo"),
+                    ]),
+                ),
+            ],
+        );
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.duration, Some(200.0));
+        assert_eq!(tags.synced.unwrap().lines.len(), 2);
+        assert_eq!(tags.plain.as_deref(), Some("One\nTwo"));
+    }
+
+    #[test]
+    fn flac_lyrics_are_written_in_place_of_the_old_ones() {
+        let file = flac(
+            "write.flac",
+            &[
+                (STREAMINFO, streaminfo()),
+                (
+                    VORBIS_COMMENT,
+                    comments(&[("TITLE", "Song"), ("lyrics", "Old")]),
+                ),
+                (1, vec![0; 16]),
+            ],
+        );
+        let lyrics = lrc::parse("[00:01.00]<00:01.00>New <00:01.50>words").lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        // Written line-timed, without the word timings
+        let synced = tags.synced.unwrap();
+        assert_eq!(synced.lines[0].text, "New words");
+        assert!(synced.lines[0].words.is_empty());
+        assert!(tags.plain.is_none());
+
+        let bytes = std::fs::read(&file.0).unwrap();
+        assert!(bytes.ends_with(AUDIO));
+        let mut reader = &bytes[4..];
+        let blocks = flac_blocks(&mut reader).unwrap();
+        let kinds: Vec<u8> = blocks.iter().map(|block| block.kind).collect();
+        assert_eq!(kinds, [STREAMINFO, VORBIS_COMMENT, 1]);
+        let entries = parse_comments(&blocks[1].data).unwrap().entries;
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn flac_without_comments_gets_a_block() {
+        let file = flac("new-block.flac", &[(STREAMINFO, streaminfo())]);
+        let lyrics = lrc::parse("[00:01.00]One").lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.synced.unwrap().lines[0].text, "One");
+        assert_eq!(tags.duration, Some(200.0));
+    }
+
+    #[test]
+    fn ogg_comments_spanning_pages() {
+        let identification = [b"\x01vorbis".as_slice(), &[0; 23]].concat();
+        let lyrics = (1..=40)
+            .map(|i| format!("[00:{:02}.00]Line {}", i, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut comment_packet = b"\x03vorbis".to_vec();
+        comment_packet.extend(comments(&[("ARTIST", "Artist"), ("LYRICS", &lyrics)]));
+        assert!(comment_packet.len() > 255 * 2);
+
+        // The comment packet's first two segments on one page, the rest on
+        // the next
+        let sizes = lacing(comment_packet.len());
+        let mut bytes = ogg_page(&lacing(identification.len()), &identification);
+        bytes.extend(ogg_page(&sizes[..2], &comment_packet[..510]));
+        bytes.extend(ogg_page(&sizes[2..], &comment_packet[510..]));
+        let file = fixture("comments.ogg", &bytes);
+
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.synced.unwrap().lines.len(), 40);
+    }
+
+    // A stream as encoders lay it out: the identification header alone on
+    // the first page, the comment header split over two more (sharing the
+    // second with Vorbis's setup header), then two audio pages.
+    fn ogg_stream(codec: &str, serial: u32) -> Vec<u8> {
+        let (identification, mut comment_packet, setup) = match codec {
+            "vorbis" => (
+                [b"\x01vorbis".as_slice(), &[0; 23]].concat(),
+                b"\x03vorbis".to_vec(),
+                Some(vec![5u8; 300]),
+            ),
+            _ => (
+                [b"OpusHead".as_slice(), &[1; 11]].concat(),
+                b"OpusTags".to_vec(),
+                None,
+            ),
+        };
+        comment_packet.extend(comments(&[
+            ("TITLE", "Song"),
+            ("LYRICS", &"Old lyrics\n".repeat(40)),
+        ]));
+        // Vorbis's framing bit, or Opus's padding
+        comment_packet.push(1);
+
+        let page = |flags: u8, sequence: u32, segments: &[u8], data: &[u8]| {
+            let mut page = ogg_page(segments, data);
+            page[5] = flags;
+            page[14..18].copy_from_slice(&serial.to_le_bytes());
+            page[18..22].copy_from_slice(&sequence.to_le_bytes());
+            set_crc(&mut page);
+            page
+        };
+        let mut sizes = lacing(comment_packet.len());
+        let mut rest = comment_packet[255..].to_vec();
+        if let Some(setup) = &setup {
+            sizes.extend(lacing(setup.len()));
+            rest.extend(setup);
+        }
+        let mut bytes = page(
+            FIRST_PAGE,
+            0,
+            &lacing(identification.len()),
+            &identification,
+        );
+        bytes.extend(page(0, 1, &sizes[..1], &comment_packet[..255]));
+        bytes.extend(page(CONTINUED, 2, &sizes[1..], &rest));
+        bytes.extend(page(0, 3, &[3], b"one"));
+        bytes.extend(page(0, 4, &[3], b"two"));
+        bytes
+    }
+
+    fn pages(bytes: &[u8]) -> Vec<OggPage> {
+        let mut reader = bytes;
+        std::iter::from_fn(|| read_page(&mut reader).unwrap()).collect()
+    }
+
+    // Pages numbered from 0 with valid checksums, and the audio pages'
+    // bodies.
+    fn check_pages(bytes: &[u8]) -> Vec<Vec<u8>> {
+        let pages = pages(bytes);
+        for (i, page) in pages.iter().enumerate() {
+            assert_eq!(page.sequence(), i as u32);
+            let mut copy = page.raw.clone();
+            set_crc(&mut copy);
+            assert_eq!(copy, page.raw);
+        }
+        pages[pages.len() - 2..]
+            .iter()
+            .map(|page| page.body().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn ogg_lyrics_are_written_on_pages_of_their_own() {
+        for (codec, name) in [("vorbis", "write.ogg"), ("opus", "write.opus")] {
+            let file = fixture(name, &ogg_stream(codec, 7));
+            let lyrics = lrc::parse("[00:01.00]New").lyrics;
+            crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+            let tags = crate::tags::read(&file.0).unwrap();
+            assert_eq!(tags.title.as_deref(), Some("Song"));
+            assert_eq!(tags.synced.unwrap().lines[0].text, "New");
+
+            // The comments now fit one page, so the audio moved up one
+            let bytes = std::fs::read(&file.0).unwrap();
+            assert_eq!(check_pages(&bytes), [b"one".to_vec(), b"two".to_vec()]);
+            let headers = read_ogg_headers(&mut bytes.as_slice(), true).unwrap();
+            assert_eq!(headers.pages, 1);
+            assert_eq!(headers.packets[0].last(), Some(&1));
+            if codec == "vorbis" {
+                assert_eq!(headers.packets[1], vec![5u8; 300]);
+            }
+        }
+    }
+
+    #[test]
+    fn long_ogg_lyrics_span_pages() {
+        let file = fixture("long.ogg", &ogg_stream("vorbis", 7));
+        let text = (0..6000)
+            .map(|i| format!("[{:02}:{:02}.00]Line number {}", i / 60, i % 60, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let lyrics = lrc::parse(&text).lyrics;
+        crate::tags::write_lyrics(&file.0, &lyrics).unwrap();
+
+        let bytes = std::fs::read(&file.0).unwrap();
+        assert_eq!(check_pages(&bytes), [b"one".to_vec(), b"two".to_vec()]);
+        let headers = read_ogg_headers(&mut bytes.as_slice(), true).unwrap();
+        assert_eq!(headers.pages, 3);
+        let tags = crate::tags::read(&file.0).unwrap();
+        assert_eq!(tags.synced.unwrap().lines.len(), 6000);
+    }
+
+    #[test]
+    fn ogg_files_with_other_streams_are_left_alone() {
+        let mut bytes = ogg_stream("vorbis", 7);
+        // A second stream's page among the headers
+        let other = ogg_stream("opus", 8);
+        let first = pages(&other)[0].raw.clone();
+        let at = pages(&bytes)[0].raw.len();
+        bytes.splice(at..at, first);
+        let file = fixture("multiplexed.ogg", &bytes);
+
+        let lyrics = lrc::parse("[00:01.00]New").lyrics;
+        assert_eq!(
+            crate::tags::write_lyrics(&file.0, &lyrics).unwrap_err(),
+            "Ogg files with more than one stream aren't supported"
+        );
+        assert_eq!(std::fs::read(&file.0).unwrap(), bytes);
+    }
+
+    #[test]
+    fn truncated_comments_are_an_error() {
+        let mut data = comments(&[("TITLE", "Song")]);
+        data.truncate(data.len() - 2);
+        assert!(parse_comments(&data).is_err());
+    }
+}
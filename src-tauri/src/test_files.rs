@@ -0,0 +1,66 @@
+// Files and folders in the temp dir for tests that touch the disk. Names
+// carry the process id so parallel runs don't collide, and each is removed
+// again when its guard drops.
+
+use std::path::{Path, PathBuf};
+
+pub struct Fixture(pub PathBuf);
+
+impl Fixture {
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        } else {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ohun-{}-{}", std::process::id(), name))
+}
+
+// `bytes` in a file of its own.
+pub fn fixture(name: &str, bytes: &[u8]) -> Fixture {
+    let path = temp_path(name);
+    std::fs::write(&path, bytes).unwrap();
+    Fixture(path)
+}
+
+// An empty folder, holding `files` (relative path and contents), if any.
+pub fn fixture_dir(name: &str, files: &[(&str, &str)]) -> Fixture {
+    let dir = Fixture(temp_path(name));
+    let _ = std::fs::remove_dir_all(&dir.0);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    std::fs::create_dir_all(&dir.0).unwrap();
+    dir
+}
+
+// A FLAC file with nothing but `comments` (`KEY=value`) and a frame.
+pub fn flac(comments: &[&str]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&4u32.to_le_bytes());
+    data.extend_from_slice(b"test");
+    data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment.as_bytes());
+    }
+    let mut bytes = b"fLaC".to_vec();
+    // The last and only metadata block, a `VORBIS_COMMENT`
+    bytes.push(0x80 | 4);
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+    bytes.extend_from_slice(&data);
+    bytes.extend_from_slice(b"\xff\xf8 audio");
+    bytes
+}
@@ -11,7 +11,7 @@ async function init() {
             return;
         }
 
-        // For Linux and macOS, check for playerctl
+        // On Linux, check that the MPRIS session bus is reachable
         const exists = await checkPlayerCtl();
         playerctlInstalled.set(exists);
     } catch (error) {
@@ -1,43 +1,43 @@
+import { invoke } from '@tauri-apps/api/core';
 import { appError } from './stores/error-store';
-import { plainLyrics, syncedLyrics } from './stores/lyricsStore';
-import * as lockr from 'lockr';
-import { replaceSpecialChars } from './utils';
+import { lyricsMatch, plainLyrics, syncedLyrics } from './stores/lyricsStore';
 import { getAccentColor } from './player';
-import { accentColor } from './stores/player-store';
+import { currentPlayingSong } from './stores/player-store';
+import { get } from 'svelte/store';
 import { requestCancellation } from './utils/request-cancellation';
-import { retryWithBackoff, isNetworkError, isTimeoutError } from './utils/retry';
+import { isNetworkError, isTimeoutError } from './utils/retry';
+import type {
+	LyricsOffset,
+	LyricsOffsetScope,
+	LyricsPublishDraft,
+	LyricsResult,
+	LyricsSearchResults,
+	SyncEditorState
+} from './types';
 
-const LYRICS_CACHE_PREFIX = 'lyrics_';
-const ACCENT_CACHE_PREFIX = 'offline_';
-const ACCENT_CACHE_SUFFIX = 'accentColor';
+// Matches scoring below this are shown with a warning
+const LOW_CONFIDENCE_SCORE = 0.6;
 
-// Get cache key for lyrics
-const getLyricsCacheKey = (artist: string, title: string): string => {
-	return `${LYRICS_CACHE_PREFIX}${artist}${title}`;
+// Accent colors are cached under the track's trimmed artist and title run
+// together, as older versions did
+export const getAccentCacheKey = (artist: string, title: string): string => {
+	return `${artist.trim()}${title.trim()}`;
 };
 
-// Get cache key for accent color
-const getAccentCacheKey = (artist: string, title: string): string => {
-	return `${ACCENT_CACHE_PREFIX}${artist}${title}${ACCENT_CACHE_SUFFIX}`;
-};
-
-// Validate lyrics data
-const isValidLyricsData = (data: unknown): boolean => {
-	return (
-		Array.isArray(data) &&
-		data.length > 0 &&
-		data[0] &&
-		typeof data[0] === 'object' &&
-		'syncedLyrics' in data[0]
-	);
-};
-
-export const getLyrics = async (artist: string, title: string, retries = 3): Promise<string | null> => {
+export const getLyrics = async (
+	artist: string,
+	title: string,
+	album = '',
+	duration: number | null = null,
+	// `xesam:url`, so lyrics next to a local file can be found
+	url: string | null = null
+): Promise<string | null> => {
 	const requestKey = 'getLyrics';
 	const signal = requestCancellation.getSignal(requestKey);
 
 	// Clear previous errors
 	appError.clear();
+	lyricsMatch.set(null);
 
 	// Validate inputs
 	if (!artist || !title) {
@@ -64,118 +64,54 @@ export const getLyrics = async (artist: string, title: string, retries = 3): Pro
 		return null;
 	}
 
-	// Try local storage first
+	// The backend owns provider lookups, retries and timeouts
 	try {
-		const cacheKey = getLyricsCacheKey(normalizedArtist, normalizedTitle);
-		const accentCacheKey = getAccentCacheKey(normalizedArtist, normalizedTitle);
-		const offlineLyrics = lockr.get(cacheKey);
-		const offlineAccent = lockr.get(accentCacheKey);
-
-		if (offlineLyrics && typeof offlineLyrics === 'string' && offlineLyrics.length > 0) {
-			syncedLyrics.set(offlineLyrics);
-			plainLyrics.set(offlineLyrics);
-			if (offlineAccent && typeof offlineAccent === 'string') {
-				accentColor.set(offlineAccent);
-			}
-			requestCancellation.cancel(requestKey);
-			return offlineLyrics;
+		if (signal.aborted) {
+			throw new Error('Request cancelled');
 		}
-	} catch (error) {
-		console.error('Error reading from cache:', error);
-		// Continue to fetch from API if cache read fails
-	}
 
-	// Fetch from API with retry logic
-	try {
-		const lyrics = await retryWithBackoff(
-			async () => {
-				if (signal.aborted) {
-					throw new Error('Request cancelled');
-				}
-
-				const cleanArtist = replaceSpecialChars(normalizedArtist);
-				const cleanTitle = replaceSpecialChars(normalizedTitle);
-
-				const url = `https://lrclib.net/api/search?artist_name=${encodeURIComponent(
-					cleanArtist
-				)}&track_name=${encodeURIComponent(cleanTitle)}`;
-
-				const response = await fetch(url, { signal });
-
-				if (!response.ok) {
-					if (response.status === 404) {
-						throw new Error(
-							'No lyrics found for this track'
-						);
-					}
-					if (response.status >= 500) {
-						throw new Error(
-							`Lyrics service unavailable (HTTP ${response.status})`
-						);
-					}
-					throw new Error(
-						`Failed to fetch lyrics: ${response.status} ${response.statusText}`
-					);
-				}
-
-				const data = await response.json();
-
-				if (!isValidLyricsData(data)) {
-					throw new Error(
-						'No lyrics found for this track'
-					);
-				}
-
-				const synced = data[0].syncedLyrics;
-				const plain = data[0].plainLyrics || synced;
-
-				if (!synced || typeof synced !== 'string' || synced.trim().length === 0) {
-					throw new Error(
-						'No lyrics found for this track'
-					);
-				}
-
-				return { synced, plain };
-			},
-			{
-				maxRetries: retries,
-				initialDelay: 1000,
-				maxDelay: 5000,
-				backoffMultiplier: 2,
-				shouldRetry: (error) => {
-					if (error instanceof Error && error.name === 'AbortError') {
-						return false; // Don't retry cancelled requests
-					}
-					if (error instanceof Error && error.message.includes('cancelled')) {
-						return false;
-					}
-					// Retry on network errors or timeouts
-					return isNetworkError(error) || isTimeoutError(error);
-				}
-			}
-		);
+		const result = await invoke<LyricsResult>('fetch_lyrics', {
+			artist: normalizedArtist,
+			title: normalizedTitle,
+			album: album || null,
+			duration: duration || null,
+			url
+		});
+
+		if (signal.aborted) {
+			throw new Error('Request cancelled');
+		}
+
+		const synced = result.synced;
+		if (!synced || synced.trim().length === 0) {
+			throw new Error('No lyrics found for this track');
+		}
+		const lyrics = { synced, plain: result.plain || synced };
 
 		// Set lyrics
 		syncedLyrics.set(lyrics.synced);
 		plainLyrics.set(lyrics.plain);
 
-		// Cache lyrics
+		const lowConfidence = result.score !== null && result.score < LOW_CONFIDENCE_SCORE;
+		lyricsMatch.set({ provider: result.provider, score: result.score, lowConfidence });
+		if (lowConfidence) {
+			appError.setError('These lyrics may not match this recording', {
+				severity: 'info',
+				category: 'lyrics',
+				recoverable: true
+			});
+		}
+
+		// The backend keeps the lyrics on disk; the accent color goes alongside
 		try {
-			const cacheKey = getLyricsCacheKey(normalizedArtist, normalizedTitle);
-			lockr.set(cacheKey, lyrics.synced);
-
-			// Cache accent color
-			try {
-				const accent = await getAccentColor();
-				const accentCacheKey = getAccentCacheKey(normalizedArtist, normalizedTitle);
-				lockr.set(accentCacheKey, accent);
-			} catch (error) {
-				console.error('Failed to cache accent color:', error);
-				// Don't fail the whole operation if accent color caching fails
-			}
+			const accent = await getAccentColor();
+			await invoke('cache_accent_color', {
+				key: getAccentCacheKey(normalizedArtist, normalizedTitle),
+				color: accent
+			});
 		} catch (error) {
-			console.error('Failed to cache lyrics:', error);
-			// Don't fail the whole operation if caching fails
+			console.error('Failed to cache accent color:', error);
+			// Don't fail the whole operation if accent color caching fails
 		}
 
 		requestCancellation.cancel(requestKey);
@@ -188,21 +124,25 @@ export const getLyrics = async (artist: string, title: string, retries = 3): Pro
 		let severity: 'error' | 'warning' | 'info' = 'error';
 		let recoverable = true;
 
-		if (error instanceof Error) {
-			if (error.name === 'AbortError' || error.message.includes('cancelled')) {
-				// Request was cancelled, don't show error
-				return null;
-			}
-
-			if (error.message.includes('No lyrics found') || error.message.includes('404')) {
-				errorMessage = 'No lyrics found for this track';
-				severity = 'info';
-			} else if (error.message.includes('HTTP 5')) {
-				errorMessage = 'Lyrics service unavailable. Try again shortly.';
-				severity = 'warning';
-			} else {
-				errorMessage = error.message || errorMessage;
-			}
+		// Backend commands reject with a plain string rather than an Error
+		const message = error instanceof Error ? error.message : String(error ?? '');
+
+		if (
+			(error instanceof Error && error.name === 'AbortError') ||
+			message.includes('cancelled')
+		) {
+			// Request was cancelled, don't show error
+			return null;
+		}
+
+		if (message.includes('No lyrics found') || message.includes('404')) {
+			errorMessage = 'No lyrics found for this track';
+			severity = 'info';
+		} else if (message.includes('request failed')) {
+			errorMessage = 'Lyrics service unavailable. Try again shortly.';
+			severity = 'warning';
+		} else if (message) {
+			errorMessage = message;
 		}
 
 		console.error('Error fetching lyrics:', error);
@@ -217,3 +157,235 @@ export const getLyrics = async (artist: string, title: string, retries = 3): Pro
 		return null;
 	}
 };
+
+// Writes the current synced lyrics into the playing file's own tags. Only
+// local files can be tagged; the backend backs the file up first.
+export const embedLyrics = async (): Promise<boolean> => {
+	const { url } = get(currentPlayingSong);
+	const lyrics = get(syncedLyrics);
+
+	if (!url || !url.startsWith('file://') || !lyrics) {
+		appError.setError('Lyrics can only be embedded in local files with synced lyrics', {
+			severity: 'info',
+			category: 'lyrics',
+			recoverable: false
+		});
+		return false;
+	}
+
+	try {
+		const backup = await invoke<string>('embed_lyrics', { url, lyrics });
+		console.info('Embedded lyrics; the original file was backed up to', backup);
+		return true;
+	} catch (error) {
+		console.error('Failed to embed lyrics:', error);
+		appError.setError(`Failed to embed lyrics: ${error}`, {
+			severity: 'error',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return false;
+	}
+};
+
+// Takes lyrics the user already has, from a dropped file or pasted text. The
+// backend works out the format and pins them to the playing track.
+export const importLyrics = async (source: { path?: string; text?: string }): Promise<boolean> => {
+	try {
+		const result = await invoke<LyricsResult>('import_lyrics', {
+			path: source.path ?? null,
+			text: source.text ?? null
+		});
+		const synced = result.synced ?? '';
+		syncedLyrics.set(synced);
+		plainLyrics.set(result.plain || synced);
+		lyricsMatch.set({ provider: result.provider, score: null, lowConfidence: false });
+		appError.clear();
+		return true;
+	} catch (error) {
+		console.error('Failed to import lyrics:', error);
+		appError.setError(`Failed to import lyrics: ${error}`, {
+			severity: 'error',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return false;
+	}
+};
+
+// Every candidate the providers have, for picking lyrics by hand when the
+// automatic match is wrong.
+export const searchLyrics = async (
+	artist: string,
+	title: string,
+	album = '',
+	duration: number | null = null
+): Promise<LyricsSearchResults | null> => {
+	try {
+		return await invoke<LyricsSearchResults>('search_lyrics', {
+			artist: artist.trim(),
+			title: title.trim(),
+			album: album || null,
+			duration: duration || null
+		});
+	} catch (error) {
+		console.error('Failed to search lyrics:', error);
+		appError.setError(`Failed to search lyrics: ${error}`, {
+			severity: 'warning',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return null;
+	}
+};
+
+// Uses a search candidate for the track from now on and shows its lyrics.
+export const pinLyrics = async (trackKey: string, candidateId: string): Promise<boolean> => {
+	try {
+		const result = await invoke<LyricsResult>('pin_lyrics', { trackKey, candidateId });
+		const synced = result.synced ?? '';
+		syncedLyrics.set(synced);
+		plainLyrics.set(result.plain || synced);
+		lyricsMatch.set({ provider: result.provider, score: result.score, lowConfidence: false });
+		appError.clear();
+		return true;
+	} catch (error) {
+		console.error('Failed to pin lyrics:', error);
+		appError.setError(`Failed to use those lyrics: ${error}`, {
+			severity: 'error',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return false;
+	}
+};
+
+// Never matches the candidate to the track again.
+export const blacklistLyrics = async (trackKey: string, candidateId: string): Promise<boolean> => {
+	try {
+		await invoke('blacklist_lyrics', { trackKey, candidateId });
+		return true;
+	} catch (error) {
+		console.error('Failed to blacklist lyrics:', error);
+		appError.setError(`Failed to blacklist those lyrics: ${error}`, {
+			severity: 'error',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return false;
+	}
+};
+
+// Moves the playing track's lyrics (or every track's) by `steps` of 100ms.
+// Positive steps show lines earlier.
+export const nudgeLyricsOffset = async (
+	steps: number,
+	scope: LyricsOffsetScope = 'track'
+): Promise<LyricsOffset | null> => {
+	try {
+		return await invoke<LyricsOffset>('nudge_lyrics_offset', { scope, steps });
+	} catch (error) {
+		console.error('Failed to adjust lyrics offset:', error);
+		return null;
+	}
+};
+
+export const resetLyricsOffset = async (
+	scope: LyricsOffsetScope = 'track'
+): Promise<LyricsOffset | null> => {
+	try {
+		return await invoke<LyricsOffset>('reset_lyrics_offset', { scope });
+	} catch (error) {
+		console.error('Failed to reset lyrics offset:', error);
+		return null;
+	}
+};
+
+const syncEditorError = (action: string, error: unknown) => {
+	console.error(`Failed to ${action}:`, error);
+	appError.setError(`Failed to ${action}: ${error}`, {
+		severity: 'warning',
+		category: 'lyrics',
+		recoverable: true
+	});
+};
+
+// Opens the sync editor on plain lyrics for the playing track.
+export const startSyncEditor = async (plain: string): Promise<SyncEditorState | null> => {
+	try {
+		return await invoke<SyncEditorState>('start_sync_editor', { plain });
+	} catch (error) {
+		syncEditorError('start syncing lyrics', error);
+		return null;
+	}
+};
+
+// Stamps the next line with the current playback position.
+export const tapSyncEditor = async (): Promise<SyncEditorState | null> => {
+	try {
+		return await invoke<SyncEditorState>('tap_sync_editor');
+	} catch (error) {
+		syncEditorError('stamp the line', error);
+		return null;
+	}
+};
+
+export const undoSyncEditor = async (): Promise<SyncEditorState | null> => {
+	try {
+		return await invoke<SyncEditorState>('undo_sync_editor');
+	} catch (error) {
+		console.error('Failed to undo:', error);
+		return null;
+	}
+};
+
+export const closeSyncEditor = async () => {
+	try {
+		await invoke('close_sync_editor');
+	} catch (error) {
+		console.error('Failed to close the sync editor:', error);
+	}
+};
+
+// Saves the synced lyrics as LRC to `path`, or to the cache for the track
+// when there's no path.
+export const saveSyncEditor = async (path?: string): Promise<LyricsResult | null> => {
+	try {
+		return await invoke<LyricsResult>('save_sync_editor', { path: path ?? null });
+	} catch (error) {
+		syncEditorError('save the synced lyrics', error);
+		return null;
+	}
+};
+
+// Shows what publishing `synced` (LRC) to lrclib would send. Nothing is
+// sent until the draft is passed to `publishLyrics`.
+export const preparePublish = async (synced: string): Promise<LyricsPublishDraft | null> => {
+	try {
+		return await invoke<LyricsPublishDraft>('prepare_publish', { synced });
+	} catch (error) {
+		console.error('Failed to prepare lyrics for publishing:', error);
+		appError.setError(`Can't publish these lyrics: ${error}`, {
+			severity: 'warning',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return null;
+	}
+};
+
+// Publishes a draft the user confirmed.
+export const publishLyrics = async (draft: LyricsPublishDraft): Promise<boolean> => {
+	try {
+		await invoke('publish_lyrics', { draftId: draft.id });
+		return true;
+	} catch (error) {
+		console.error('Failed to publish lyrics:', error);
+		appError.setError(`Failed to publish lyrics: ${error}`, {
+			severity: 'error',
+			category: 'lyrics',
+			recoverable: true
+		});
+		return false;
+	}
+};
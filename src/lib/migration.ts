@@ -0,0 +1,38 @@
+import { invoke } from '@tauri-apps/api/core';
+import type { LegacyCacheImportReport } from './types';
+
+// Set once older versions' localStorage caches have been handed to the backend
+const IMPORTED_FLAG = 'legacyCacheImported';
+
+// Moves the lyrics, accent colors and album art older versions cached in
+// localStorage into the backend's caches. Runs once; the old entries are only
+// removed after the backend has written every one of them.
+export const importLegacyCache = async (): Promise<LegacyCacheImportReport | null> => {
+	if (localStorage.getItem(IMPORTED_FLAG)) {
+		return null;
+	}
+
+	// The backend picks its caches out from among everything else
+	const storage: Record<string, string> = {};
+	for (let i = 0; i < localStorage.length; i++) {
+		const key = localStorage.key(i);
+		if (key === null) continue;
+		storage[key] = localStorage.getItem(key) ?? '';
+	}
+
+	const report = await invoke<LegacyCacheImportReport>('import_legacy_cache', { storage });
+
+	if (report.errors.length > 0) {
+		// Keep everything for another try on the next launch
+		console.error('Some cached entries could not be imported:', report.errors);
+		return report;
+	}
+
+	report.keys.forEach((key) => localStorage.removeItem(key));
+	localStorage.setItem(IMPORTED_FLAG, String(Date.now()));
+	console.info(
+		`Imported ${report.lyrics} lyrics, ${report.accentColors} accent colors and ${report.albumArt} album covers ` +
+			`(${report.duplicates} duplicates, ${report.invalid} unreadable)`
+	);
+	return report;
+};
@@ -1,16 +1,32 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import {
 	accentColor,
 	albumArt,
 	currentPlayingSong,
 	playTime,
 	textColor,
-	cachedAlbumArt
+	duration
 } from './stores/player-store';
-import type { Song } from './types';
-import { getLyrics } from './lyrics';
-import { currentLine, syncedLyrics, nextLine } from './stores/lyricsStore';
-import { Lyrics } from 'paroles';
+import type {
+	LyricLineChangedEvent,
+	LyricWordChangedEvent,
+	PlaybackStatusChangedEvent,
+	PlayerEvent,
+	PlayerSnapshot,
+	SeekedEvent,
+	Song,
+	TrackChangedEvent
+} from './types';
+import { getAccentCacheKey, getLyrics } from './lyrics';
+import { importLegacyCache } from './migration';
+import {
+	currentLine,
+	currentWordIndex,
+	currentWords,
+	nextLine,
+	syncedLyrics
+} from './stores/lyricsStore';
 import { prominent } from 'color.js';
 import { getTextColor } from './ui';
 import { replaceSpecialChars, debounce } from './utils';
@@ -23,7 +39,7 @@ export const PROXY_ENABLED = false;
 import { get, writable } from 'svelte/store';
 
 let previousTime: number | null = null;
-let songChangeInterval: ReturnType<typeof setInterval> | null = null;
+let unlistenPlayerEvents: UnlistenFn[] = [];
 let playTimeInterval: ReturnType<typeof setInterval> | null = null;
 let currentSongKey = '';
 
@@ -81,11 +97,11 @@ export const getCurrentPlaying = async (): Promise<void> => {
 		appError.clear();
 		ensureDefaultColors();
 
-		// Fetch current song with retry logic
-		const response: Song = await retryWithBackoff(
+		// Fetch the whole player state in one call, with retry logic
+		const snapshot: PlayerSnapshot = await retryWithBackoff(
 			async () => {
 				if (signal.aborted) throw new Error('Request cancelled');
-				return await invoke<Song>('get_current_playing_song');
+				return await invoke<PlayerSnapshot>('get_player_snapshot');
 			},
 			{
 				maxRetries: 2,
@@ -97,6 +113,8 @@ export const getCurrentPlaying = async (): Promise<void> => {
 			}
 		);
 
+		const response = snapshot.metadata;
+
 		// Validate response
 		if (!response || (!response.artist && !response.title)) {
 			throw new Error(
@@ -108,7 +126,10 @@ export const getCurrentPlaying = async (): Promise<void> => {
 			throw new Error('Incomplete song info. Make sure your media player exposes artist and title.');
 		}
 
+		activePlayer.set(snapshot.player);
 		currentPlayingSong.set(response);
+		duration.set(response.duration ?? 0);
+		applyPlayTime(snapshot.position);
 		currentSongKey = getSongKey(response);
 
 		// Cancel any previous album art or lyrics requests
@@ -117,14 +138,16 @@ export const getCurrentPlaying = async (): Promise<void> => {
 
 		// Fetch lyrics and album art in parallel, but handle errors independently
 		const promises = [
-			getLyrics(response.artist, response.title).catch((error) => {
+			getLyrics(
+				response.artist,
+				response.title,
+				response.album || '',
+				response.duration ?? null,
+				response.url ?? null
+			).catch((error) => {
 				console.error('Failed to fetch lyrics:', error);
 				// Don't throw - allow album art to still load
 			}),
-			getPlayTime().catch((error) => {
-				console.error('Failed to get play time:', error);
-				// Don't throw - this is not critical
-			}),
 			getAlbumArt(response.artist, response.title, response.album || '').catch((error) => {
 				console.error('Failed to fetch album art:', error);
 				// Don't throw - use default art
@@ -152,18 +175,20 @@ export const getCurrentPlaying = async (): Promise<void> => {
 	}
 };
 
+// The backend extrapolates the position, so keep the sub-second precision
+// for lyric timing and only floor what's displayed
+const applyPlayTime = (time: number): void => {
+	if (previousTime !== time && time >= 0) {
+		playTime.set(Math.floor(time));
+		previousTime = time;
+	}
+};
+
 export const getPlayTime = async (): Promise<number> => {
 	try {
 		const response: number = await invoke('get_current_audio_time');
-		const flooredTime = Math.floor(response);
-
-		if (previousTime !== flooredTime && flooredTime >= 0) {
-			playTime.set(flooredTime);
-			previousTime = flooredTime;
-			updateLyrics(flooredTime);
-		}
-
-		return flooredTime;
+		applyPlayTime(response);
+		return response;
 	} catch (error) {
 		console.error('Error getting play time:', error);
 		// Return previous time or 0, don't disrupt UI for minor errors
@@ -178,93 +203,64 @@ const debouncedSongChange = debounce(async (song: Song) => {
 	}
 }, 500);
 
-const checkSongChange = (): void => {
-	let currentSong: Song | null = null;
-	let previousSong: Song | null = null;
-	let activePlayer: string | null = null;
-	let consecutiveErrors = 0;
-	const MAX_CONSECUTIVE_ERRORS = 5;
-	const CHECK_INTERVAL = 2000; // Check every 2 seconds instead of 1
-
-	// Get the active player initially
-	const initializePlayer = async () => {
-		try {
-			const response = await invoke<string>('get_active_player');
-			activePlayer = response;
-			console.log('Active player detected:', activePlayer);
-			consecutiveErrors = 0;
-		} catch (error) {
-			console.error('Failed to get active player:', error);
-			consecutiveErrors++;
-		}
-	};
-
-	initializePlayer();
-
-	songChangeInterval = setInterval(async () => {
-		try {
-			// Update active player periodically (less frequently)
-			if (Math.random() < 0.1) {
-				await getAvailablePlayers();
-				await getActivePlayer();
-				// Only check 10% of the time to reduce load
-				try {
-					const newActivePlayer = await invoke<string>('get_active_player');
-					// if (newActivePlayer !== activePlayer) {
-					// 	console.log('Active player changed from', activePlayer, 'to', newActivePlayer);
-					// 	activePlayer = newActivePlayer;
-					// 	appError.clear();
-					// }
-				} catch (error) {
-					// Silently fail - not critical
-					console.debug('Failed to update active player:', error);
-				}
-			}
-
-			const song = await invoke<Song>('get_current_playing_song');
-
-			// Validate song data
-			if (!song || (!song.artist && !song.title)) {
-				consecutiveErrors++;
-				if (consecutiveErrors >= MAX_CONSECUTIVE_ERRORS) {
-					appError.setError('Unable to detect music. Is your media player running?', {
-						severity: 'warning',
-						category: 'player',
-						recoverable: true
-					});
-				}
-				return;
-			}
+const refreshPlayers = async (): Promise<void> => {
+	await getAvailablePlayers();
+	if (get(availablePlayers).length === 0) {
+		ensureDefaultColors();
+		appError.setError('Unable to detect music. Is your media player running?', {
+			severity: 'warning',
+			category: 'player',
+			recoverable: true,
+			retryable: true
+		});
+		return;
+	}
 
-			// Check if song changed
-			if (currentSong) {
-				const currentKey = getSongKey(song);
-				const previousKey = getSongKey(currentSong);
+	const previousPlayer = get(activePlayer);
+	await getActivePlayer();
+	if (get(activePlayer) !== previousPlayer) {
+		await getCurrentPlaying();
+	}
+};
 
-				if (currentKey !== previousKey && currentKey !== '') {
-					previousSong = currentSong;
-					// Use debounced function to avoid rapid updates
-					debouncedSongChange(song);
-				}
+// The backend watches the players and pushes changes, so we subscribe once
+// instead of polling
+const subscribeToPlayerEvents = async (): Promise<void> => {
+	unlistenPlayerEvents = await Promise.all([
+		listen<TrackChangedEvent>('track-changed', (event) => {
+			const player = get(activePlayer);
+			if (!player || event.payload.player === player) {
+				debouncedSongChange(event.payload.metadata);
 			}
-
-			currentSong = song;
-			consecutiveErrors = 0; // Reset on success
-		} catch (error) {
-			console.error('Error in checkSongChange:', error);
-			consecutiveErrors++;
-
-			if (consecutiveErrors >= MAX_CONSECUTIVE_ERRORS) {
-				ensureDefaultColors();
-				appError.setError('Unable to detect music. Is your media player running?', {
-					severity: 'warning',
-					category: 'player',
-					recoverable: true,
-					retryable: true
-				});
+		}),
+		// Another player starting playback can take over as the active one
+		listen<PlaybackStatusChangedEvent>('playback-status-changed', () => {
+			refreshPlayers().catch((error) => console.debug('Failed to refresh players:', error));
+		}),
+		listen<SeekedEvent>('seeked', (event) => {
+			if (event.payload.player === get(activePlayer)) {
+				getPlayTime();
 			}
-		}
-	}, CHECK_INTERVAL) as unknown as ReturnType<typeof setInterval>;
+		}),
+		listen<PlayerEvent>('player-added', () => {
+			appError.clear();
+			refreshPlayers().catch((error) => console.debug('Failed to refresh players:', error));
+		}),
+		listen<PlayerEvent>('player-removed', () => {
+			refreshPlayers().catch((error) => console.debug('Failed to refresh players:', error));
+		}),
+		// The backend times lyric lines against its position clock
+		listen<LyricLineChangedEvent>('lyric-line-changed', (event) => {
+			const { time, text, next, words } = event.payload;
+			currentLine.set({ time, text });
+			nextLine.set(next ?? '');
+			currentWords.set(words);
+			currentWordIndex.set(null);
+		}),
+		listen<LyricWordChangedEvent>('lyric-word-changed', (event) => {
+			currentWordIndex.set(event.payload.index);
+		})
+	]);
 };
 
 // Initialize app with retry logic
@@ -299,8 +295,15 @@ const initializeApp = async (retries = 3, delay = 1000): Promise<void> => {
 // Start the app
 const startApp = async () => {
 	try {
-		checkSongChange();
+		// Before anything reads the caches, so nothing is looked up twice
+		await importLegacyCache().catch((error) => {
+			console.error('Failed to import cached lyrics and art:', error);
+		});
+		await subscribeToPlayerEvents();
+		await getAvailablePlayers();
+		await getActivePlayer();
 		await initializeApp();
+		// Reads are served from the backend's position clock, so they're cheap
 		playTimeInterval = setInterval(() => {
 			getPlayTime().catch((error) => {
 				console.debug('Play time update failed:', error);
@@ -315,10 +318,8 @@ startApp();
 
 // Cleanup function (can be called on app close)
 export const cleanup = (): void => {
-	if (songChangeInterval) {
-		clearInterval(songChangeInterval);
-		songChangeInterval = null;
-	}
+	unlistenPlayerEvents.forEach((unlisten) => unlisten());
+	unlistenPlayerEvents = [];
 	if (playTimeInterval) {
 		clearInterval(playTimeInterval);
 		playTimeInterval = null;
@@ -326,57 +327,6 @@ export const cleanup = (): void => {
 	requestCancellation.cancelAll();
 };
 
-/** Lead time in seconds so lyrics scroll/highlight slightly before the beat (better sync feel). */
-const LYRICS_LEAD_SECONDS = 0.5;
-
-const updateLyrics = (time: number): void => {
-	try {
-		const lyrics = get(syncedLyrics);
-
-		if (!lyrics || time < 0) return;
-
-		const sync = new Lyrics(lyrics);
-		const ledTime = time + LYRICS_LEAD_SECONDS;
-		const current = sync.atTime(ledTime);
-
-		if (current) {
-			currentLine.set(current);
-
-			// Predict next line for smoother transitions
-			try {
-				const lyricsLines = lyrics.split('\n');
-				const nextTimeIndex = lyricsLines.findIndex((line) => {
-					const match = line.match(/\[(.*?)\]/);
-					if (!match) return false;
-
-					const timeStr = match[1].trim();
-					const timeParts = timeStr.split(':');
-					if (timeParts.length !== 2) return false;
-
-					const [minutes, seconds] = timeParts.map(Number);
-					if (isNaN(minutes) || isNaN(seconds)) return false;
-
-					const lineTime = minutes * 60 + seconds;
-					return lineTime > ledTime;
-				});
-
-				if (nextTimeIndex > 0 && nextTimeIndex < lyricsLines.length) {
-					const nextLineText = lyricsLines[nextTimeIndex].replace(/\[.*?\]/, '').trim();
-					if (nextLineText) {
-						nextLine.set(nextLineText);
-					}
-				}
-			} catch (error) {
-				// Silently fail - next line prediction is not critical
-				console.debug('Failed to predict next line:', error);
-			}
-		}
-	} catch (error) {
-		console.error('Error updating lyrics:', error);
-		// Don't set appError - lyrics update failures shouldn't disrupt the UI
-	}
-};
-
 export const getAlbumArt = async (
 	artist: string,
 	title: string,
@@ -397,8 +347,12 @@ export const getAlbumArt = async (
 	const cacheKey = `${artist}-${title}-${album}`.toLowerCase().trim();
 
 	// Check cache first
-	const cache = get(cachedAlbumArt);
-	const cached = cache[cacheKey];
+	const cached = await invoke<string | null>('get_cached_art', { key: cacheKey }).catch(
+		(error) => {
+			console.error('Failed to read cached album art:', error);
+			return null;
+		}
+	);
 
 	if (cached) {
 		albumArt.set(cached);
@@ -407,6 +361,22 @@ export const getAlbumArt = async (
 		return cached;
 	}
 
+	// Prefer the artwork the player publishes itself
+	if (get(currentPlayingSong).artUrl) {
+		try {
+			const trackArt = await invoke<string | null>('get_track_art');
+			if (trackArt && !signal.aborted) {
+				cacheAlbumArt(cacheKey, trackArt);
+				albumArt.set(trackArt);
+				await getAccentColor();
+				requestCancellation.cancel(requestKey);
+				return trackArt;
+			}
+		} catch (error) {
+			console.debug('Failed to load artwork from the player:', error);
+		}
+	}
+
 	// If not in cache, fetch from API
 	try {
 		if (signal.aborted) {
@@ -450,10 +420,7 @@ export const getAlbumArt = async (
 				}
 
 				if (!signal.aborted) {
-					cachedAlbumArt.update((cache) => ({
-						...cache,
-						[cacheKey]: base64Data
-					}));
+					cacheAlbumArt(cacheKey, base64Data);
 					albumArt.set(base64Data);
 				}
 			} catch (error) {
@@ -476,11 +443,26 @@ export const getAlbumArt = async (
 		}
 
 		console.error('Failed to fetch album art:', error);
+		// Without art to take it from, fall back to the accent color cached
+		// the last time this track played
+		const cachedAccent = await invoke<string | null>('get_cached_accent_color', {
+			key: getAccentCacheKey(artist, title)
+		}).catch(() => null);
+		if (cachedAccent) {
+			accentColor.set(cachedAccent);
+			textColor.set(getTextColor(cachedAccent));
+		}
 		// Return current album art or undefined
 		return get(albumArt);
 	}
 };
 
+const cacheAlbumArt = (key: string, dataUrl: string) => {
+	invoke('cache_art', { key, dataUrl }).catch((error) =>
+		console.error('Failed to cache album art:', error)
+	);
+};
+
 export const getAccentColor = async (): Promise<string> => {
 	try {
 		const url = get(albumArt);
@@ -524,6 +506,8 @@ export const goToTime = async (time: number): Promise<void> => {
 	}
 };
 
+// Saves the lyrics on screen as an LRC file. They came through the backend,
+// so nothing is fetched here and offline mode has nothing to stop.
 export const downloadLyrics = async (): Promise<void> => {
 	const playInfo = get(currentPlayingSong);
 
@@ -537,22 +521,17 @@ export const downloadLyrics = async (): Promise<void> => {
 	}
 
 	const { artist, title } = playInfo;
+	const lyrics = get(syncedLyrics);
+	if (!lyrics) {
+		appError.setError('No synced lyrics to download', {
+			severity: 'info',
+			category: 'lyrics',
+			recoverable: false
+		});
+		return;
+	}
 
 	try {
-		const response = await fetch(
-			`https://lrclib.net/api/search?artist_name=${encodeURIComponent(artist)}&track_name=${encodeURIComponent(title)}`
-		);
-
-		if (!response.ok) {
-			throw new Error(`Failed to fetch lyrics: ${response.status} ${response.statusText}`);
-		}
-
-		const data = await response.json();
-		if (!data || !Array.isArray(data) || !data[0] || !data[0].syncedLyrics) {
-			throw new Error('No lyrics found for download');
-		}
-
-		const lyrics = data[0].syncedLyrics;
 		const blob = new Blob([lyrics], { type: 'text/plain' });
 		const url = URL.createObjectURL(blob);
 		const a = document.createElement('a');
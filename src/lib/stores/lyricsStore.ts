@@ -1,4 +1,4 @@
-import type { Lyrics } from "../types";
+import type { LyricWord, Lyrics, LyricsMatch } from "../types";
 import { writable } from "svelte/store";
 
 export const syncedLyrics = writable<string>('');
@@ -7,4 +7,11 @@ export const plainLyrics = writable<string>('');
 export const currentLine = writable<Lyrics>({ time: 0, text: '' });
 export const nextLine = writable('');
 
+// The current line's words, and which of them is being sung
+export const currentWords = writable<LyricWord[]>([]);
+export const currentWordIndex = writable<number | null>(null);
+
+// Where the current lyrics came from and how well they fit the track
+export const lyricsMatch = writable<LyricsMatch | null>(null);
+
 export const parsedLyrics = writable<string>('');
\ No newline at end of file
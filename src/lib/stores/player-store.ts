@@ -1,6 +1,5 @@
 import type { Song } from "$lib/types";
 import { writable } from "svelte/store";
-import { persisted } from 'svelte-persisted-store';
 
 export const currentPlayingSong = writable<Song>({
     artist: null,
@@ -8,11 +7,6 @@ export const currentPlayingSong = writable<Song>({
     album: null
 });
 
-export const cachedAlbumArt = persisted<Record<string, string>>('cachedAlbumArt', {}, {
-    storage: 'local',
-    syncTabs: true
-});
-
 export const albumArt = writable('');
 export const accentColor = writable('#ffffff');
 export const textColor = writable('#000000');
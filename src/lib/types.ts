@@ -1,10 +1,251 @@
 export type Song = {
     artist: string | null,
     title: string | null,
-    album: string | null
+    album: string | null,
+    // Track length in seconds
+    duration?: number | null,
+    artUrl?: string | null,
+    trackId?: string | null,
+    url?: string | null,
+    albumArtists?: string[],
+    trackNumber?: number | null,
+    artists?: string[]
+}
+
+export type PlayerCapabilities = {
+    canPlay: boolean,
+    canPause: boolean,
+    canGoNext: boolean,
+    canGoPrevious: boolean,
+    canSeek: boolean,
+    canControl: boolean
+}
+
+export type PlayerSnapshot = {
+    player: string,
+    metadata: Song,
+    position: number,
+    status: string,
+    volume: number | null,
+    rate: number,
+    capabilities: PlayerCapabilities
+}
+
+export type LyricsResult = {
+    synced: string | null,
+    plain: string | null,
+    instrumental: boolean,
+    provider: string,
+    score: number | null,
+    // `provider:id` of the match the lyrics came from
+    candidate: string | null,
+    parsed: ParsedLyrics | null
+}
+
+export type LyricWord = {
+    start: number,
+    end: number,
+    text: string
+}
+
+export type LyricLine = {
+    time: number,
+    text: string,
+    words: LyricWord[]
+}
+
+// Lyrics as parsed by the backend
+export type ParsedLyrics = {
+    title: string | null,
+    artist: string | null,
+    album: string | null,
+    length: number | null,
+    offset: number,
+    tags: Record<string, string>,
+    lines: LyricLine[]
+}
+
+export type LrcDiagnostic = {
+    line: number,
+    message: string
+}
+
+export type LyricsMatch = {
+    provider: string,
+    score: number | null,
+    lowConfidence: boolean
+}
+
+export type LyricsProviderKind = 'lrclib' | 'netease' | 'qqmusic' | 'musixmatch' | 'local'
+
+export type Settings = {
+    lyricsProviders: LyricsProviderKind[],
+    lyricsStrategy: 'sequential' | 'race',
+    durationTolerance: number,
+    lyricsFolders: string[],
+    musixmatchToken: string | null,
+    lyricsCacheSizeMb: number,
+    // Another lrclib server, e.g. a local one for testing; lrclib.net when null
+    lrclibUrl: string | null,
+    // Hosts on this machine or the local network requests may reach, and the
+    // only IP addresses art may be fetched from
+    allowedPrivateHosts: string[],
+    // http(s):// or socks5(h):// URL; the system proxy when null
+    proxy: string | null,
+    // No network requests; only saved lyrics, art and colors
+    offline: boolean
+}
+
+export type CachedLyricsSummary = {
+    id: string,
+    artist: string,
+    title: string,
+    album: string | null,
+    provider: string,
+    fetchedAt: number,
+    synced: boolean,
+    size: number
+}
+
+export type CachedLyrics = {
+    id: string,
+    artist: string,
+    title: string,
+    album: string | null,
+    duration: number | null,
+    fetchedAt: number,
+    lyrics: LyricsResult
+}
+
+export type LegacyCacheImportReport = {
+    lyrics: number,
+    accentColors: number,
+    albumArt: number,
+    duplicates: number,
+    invalid: number,
+    errors: string[],
+    // The localStorage keys the caches were under
+    keys: string[]
 }
 
 export type Lyrics = {
     time: number,
     text: string
-}
\ No newline at end of file
+}
+
+export type PlayerEvent = {
+    player: string
+}
+
+export type TrackChangedEvent = PlayerEvent & {
+    metadata: Song
+}
+
+export type PlaybackStatusChangedEvent = PlayerEvent & {
+    status: string
+}
+
+export type SeekedEvent = PlayerEvent & {
+    position: number
+}
+
+export type LyricLineChangedEvent = PlayerEvent & {
+    index: number | null,
+    time: number,
+    text: string,
+    next: string | null,
+    // Player positions the line shows from and until
+    start: number,
+    end: number | null,
+    duration: number | null,
+    words: LyricWord[]
+}
+
+export type LyricWordChangedEvent = PlayerEvent & {
+    line: number,
+    index: number,
+    start: number,
+    end: number,
+    text: string
+}
+
+export type LibraryAction = 'cache' | 'sidecar' | 'embed'
+
+export type LibraryScanOutcome = 'saved' | 'alreadyDone' | 'notFound' | 'noTags' | 'failed'
+
+export type LibraryScanSummary = {
+    saved: number,
+    alreadyDone: number,
+    notFound: number,
+    noTags: number,
+    failed: number
+}
+
+export type LibraryScanProgressEvent = {
+    total: number,
+    done: number,
+    path: string | null,
+    outcome: LibraryScanOutcome | null,
+    error: string | null,
+    summary: LibraryScanSummary
+}
+
+export type LibraryScanFinishedEvent = {
+    total: number,
+    summary: LibraryScanSummary,
+    cancelled: boolean
+}
+
+export type LyricsExportFormat = 'lrc' | 'srt' | 'vtt' | 'ass' | 'ttml'
+
+export type LyricsCandidate = {
+    candidateId: string,
+    provider: string,
+    id: string,
+    artist: string,
+    title: string,
+    album: string | null,
+    duration: number | null,
+    synced: boolean | null,
+    score: number,
+    blacklisted: boolean
+}
+
+export type LyricsSearchResults = {
+    trackKey: string,
+    candidates: LyricsCandidate[]
+}
+
+export type LyricsOffsetScope = 'track' | 'global'
+
+// Milliseconds; positive values show lines earlier
+export type LyricsOffset = {
+    trackKey: string,
+    track: number,
+    global: number
+}
+
+export type SyncEditorLine = {
+    text: string,
+    // Seconds, once stamped
+    time: number | null
+}
+
+export type SyncEditorState = {
+    lines: SyncEditorLine[],
+    // The line the next tap stamps
+    next: number | null,
+    canUndo: boolean
+}
+
+// What publishing to lrclib would send, for the user to confirm
+export type LyricsPublishDraft = {
+    id: number,
+    server: string,
+    trackName: string,
+    artistName: string,
+    albumName: string,
+    duration: number,
+    plainLyrics: string,
+    syncedLyrics: string
+}